@@ -0,0 +1,63 @@
+use {
+    std::net::SocketAddr,
+    serde::{Serialize, Deserialize},
+};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_addr")]
+    pub addr: SocketAddr,
+
+    /// Has to be sent as bearer token with every request
+    #[serde(default)]
+    pub token: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            addr: default_addr(),
+            token: String::new(),
+        }
+    }
+}
+
+
+/// Prometheus metrics are served on their own address, without the admin token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_metrics_addr")]
+    pub addr: SocketAddr,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            addr: default_metrics_addr(),
+        }
+    }
+}
+
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8081))
+}
+
+fn default_metrics_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8082))
+}
@@ -0,0 +1,59 @@
+use {
+    thiserror::Error,
+    warp::{http::StatusCode, reject::Reject},
+};
+
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid or missing admin token")]
+    Unauthorized,
+
+    #[error("No stream with name {0} found")]
+    NoSuchStream(String),
+
+    #[error("Invalid stream name {0}")]
+    InvalidStreamName(String),
+
+    #[error("No client with id {0} found")]
+    NoSuchClient(u64),
+
+    #[error("No user with name {0} found")]
+    NoSuchUser(String),
+
+    #[error("User with name {0} already exists")]
+    UserExists(String),
+
+    #[error("No key with name {0} found")]
+    NoSuchKey(String),
+
+    #[error("No permission for {0} found")]
+    NoSuchPermission(String),
+
+    #[error("Session manager is not available")]
+    ManagerUnavailable,
+
+    #[error("Metrics are not available")]
+    MetricsUnavailable,
+
+    #[error(transparent)]
+    DatabaseError(#[from] javelin_types::models::Error),
+}
+
+impl Error {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::InvalidStreamName(_) => StatusCode::BAD_REQUEST,
+            Self::UserExists(_) => StatusCode::CONFLICT,
+            Self::NoSuchStream(_)
+            | Self::NoSuchClient(_)
+            | Self::NoSuchUser(_)
+            | Self::NoSuchKey(_)
+            | Self::NoSuchPermission(_) => StatusCode::NOT_FOUND,
+            Self::ManagerUnavailable | Self::MetricsUnavailable | Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl Reject for Error {}
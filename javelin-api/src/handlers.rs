@@ -0,0 +1,204 @@
+use {
+    std::convert::Infallible,
+    tokio::sync::oneshot,
+    warp::{
+        http::StatusCode,
+        reply::{self, Reply},
+        path::Tail,
+        reject, Rejection,
+    },
+    javelin_types::models::{self, UserRepository, StreamPermission, DEFAULT_KEY_NAME},
+    javelin_core::{
+        session::{self, ManagerMessage, ClientId},
+        metrics::METRICS,
+    },
+    crate::{
+        error::Error,
+        models::{Stream, Client, User, NewUser, CreatedUser, Permission, StreamKey},
+    },
+};
+
+
+pub async fn list_streams(session_manager: session::ManagerHandle) -> Result<impl Reply, Rejection> {
+    let sessions = query(&session_manager, ManagerMessage::ListSessions).await?;
+    let streams: Vec<Stream> = sessions.into_iter().map(Into::into).collect();
+    Ok(reply::json(&streams))
+}
+
+pub async fn show_stream(name: Tail, session_manager: session::ManagerHandle) -> Result<impl Reply, Rejection> {
+    let name = name.as_str().to_string();
+    if !session::is_valid_name(&name) {
+        return Err(reject::custom(Error::InvalidStreamName(name)));
+    }
+
+    let session = query(&session_manager, |r| ManagerMessage::GetSession((name.clone(), r))).await?;
+    let stream: Stream = session.ok_or_else(|| reject::custom(Error::NoSuchStream(name)))?.into();
+    Ok(reply::json(&stream))
+}
+
+pub async fn list_clients(session_manager: session::ManagerHandle) -> Result<impl Reply, Rejection> {
+    let clients = query(&session_manager, ManagerMessage::ListClients).await?;
+    let clients: Vec<Client> = clients.into_iter().map(Into::into).collect();
+    Ok(reply::json(&clients))
+}
+
+pub async fn kick_client(id: ClientId, session_manager: session::ManagerHandle) -> Result<impl Reply, Rejection> {
+    if !query(&session_manager, |r| ManagerMessage::KickClient((id, r))).await? {
+        return Err(reject::custom(Error::NoSuchClient(id)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_users<D>(user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    let users = user_repo.users().await.map_err(into_rejection)?;
+    let users: Vec<User> = users.into_iter().map(Into::into).collect();
+    Ok(reply::json(&users))
+}
+
+pub async fn create_user<D>(body: NewUser, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    if user_repo.user_by_name(&body.name).await.map_err(into_rejection)?.is_some() {
+        return Err(reject::custom(Error::UserExists(body.name)));
+    }
+
+    let key = body.key.unwrap_or_else(models::generate_key);
+    user_repo
+        .add_user_with_key(&body.name, &key).await
+        .map_err(into_rejection)?;
+
+    let user = CreatedUser { name: body.name, key };
+    Ok(reply::with_status(reply::json(&user), StatusCode::CREATED))
+}
+
+pub async fn show_user<D>(name: String, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    let user = user_repo.user_by_name(&name).await.map_err(into_rejection)?;
+    let user = user.ok_or_else(|| reject::custom(Error::NoSuchUser(name)))?;
+    Ok(reply::json(&User::from(user)))
+}
+
+pub async fn delete_user<D>(name: String, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    if !user_repo.delete_user(&name).await.map_err(into_rejection)? {
+        return Err(reject::custom(Error::NoSuchUser(name)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn set_user_key<D>(name: String, body: StreamKey, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    let key_name = body.name.as_deref().unwrap_or(DEFAULT_KEY_NAME);
+    user_repo
+        .add_user_key(&name, key_name, &body.key, body.expires_at).await
+        .map_err(into_rejection)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_user_key<D>(name: String, key_name: String, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    if !user_repo.revoke_key(&name, &key_name).await.map_err(into_rejection)? {
+        return Err(reject::custom(Error::NoSuchKey(key_name)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_permissions<D>(name: String, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    ensure_user_exists(&user_repo, name.clone()).await?;
+
+    let permissions = user_repo.permissions(&name).await.map_err(into_rejection)?;
+    let permissions: Vec<Permission> = permissions.into_iter().map(Into::into).collect();
+    Ok(reply::json(&permissions))
+}
+
+pub async fn allow_publish<D>(name: String, app: String, stream: String, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    ensure_user_exists(&user_repo, name.clone()).await?;
+
+    let permission = StreamPermission { app, stream };
+    user_repo
+        .allow_publish(&name, &permission).await
+        .map_err(into_rejection)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn disallow_publish<D>(name: String, app: String, stream: String, user_repo: D) -> Result<impl Reply, Rejection>
+    where D: UserRepository
+{
+    let permission = StreamPermission { app, stream };
+    if !user_repo.disallow_publish(&name, &permission).await.map_err(into_rejection)? {
+        let permission = format!("{}/{}", permission.app, permission.stream);
+        return Err(reject::custom(Error::NoSuchPermission(permission)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn metrics() -> Result<impl Reply, Rejection> {
+    match METRICS.encode() {
+        Ok(metrics) => Ok(metrics),
+        Err(why) => {
+            log::error!("Failed to encode metrics: {}", why);
+            Err(reject::custom(Error::MetricsUnavailable))
+        },
+    }
+}
+
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+    let (status, message) = if let Some(error) = err.find::<Error>() {
+        (error.status(), error.to_string())
+    } else if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_string())
+    } else {
+        (StatusCode::BAD_REQUEST, "Bad request".to_string())
+    };
+
+    let body = reply::json(&ErrorMessage { error: message });
+    Ok(reply::with_status(body, status))
+}
+
+
+#[derive(serde::Serialize)]
+struct ErrorMessage {
+    error: String,
+}
+
+
+async fn query<T, F>(session_manager: &session::ManagerHandle, make_message: F) -> Result<T, Rejection>
+    where F: FnOnce(oneshot::Sender<T>) -> ManagerMessage
+{
+    let (request, response) = oneshot::channel();
+
+    session_manager
+        .send(make_message(request))
+        .map_err(|_| reject::custom(Error::ManagerUnavailable))?;
+
+    response.await.map_err(|_| reject::custom(Error::ManagerUnavailable))
+}
+
+async fn ensure_user_exists<D>(user_repo: &D, name: String) -> Result<(), Rejection>
+    where D: UserRepository
+{
+    match user_repo.user_by_name(&name).await.map_err(into_rejection)? {
+        Some(_) => Ok(()),
+        None => Err(reject::custom(Error::NoSuchUser(name))),
+    }
+}
+
+fn into_rejection<E>(err: E) -> Rejection
+    where E: Into<Error>
+{
+    reject::custom(err.into())
+}
@@ -0,0 +1,13 @@
+mod config;
+mod error;
+mod models;
+mod handlers;
+pub mod service;
+pub mod metrics;
+
+
+pub use self::{
+    config::{Config, MetricsConfig},
+    service::Service,
+    metrics::MetricsService,
+};
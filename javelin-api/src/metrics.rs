@@ -0,0 +1,35 @@
+use {
+    warp::Filter,
+    javelin_core::shutdown::Shutdown,
+    crate::{
+        config::MetricsConfig,
+        handlers,
+    },
+};
+
+
+/// Serves `/metrics` for Prometheus, independent of the admin API.
+pub struct MetricsService {
+    config: MetricsConfig,
+}
+
+impl MetricsService {
+    pub fn new(config: MetricsConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn run(self, mut shutdown: Shutdown) {
+        let addr = self.config.addr;
+        log::info!("Serving metrics on {}", addr);
+
+        let routes = warp::path!("metrics")
+            .and(warp::get())
+            .and_then(handlers::metrics)
+            .recover(handlers::handle_rejection);
+
+        let (_, server) = warp::serve(routes)
+            .bind_with_graceful_shutdown(addr, async move { shutdown.wait().await });
+        server.await;
+        log::info!("Metrics server stopped");
+    }
+}
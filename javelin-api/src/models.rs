@@ -0,0 +1,165 @@
+use {
+    std::{net::SocketAddr, time::SystemTime},
+    chrono::{DateTime, Utc},
+    serde::{Serialize, Deserialize},
+    javelin_types::{models, Metadata},
+    javelin_core::session::{SessionInfo, IngestStats, ClientInfo, ClientRole},
+};
+
+
+#[derive(Serialize)]
+pub struct Stream {
+    pub name: String,
+    pub publisher: String,
+    pub publisher_addr: SocketAddr,
+    pub started_at: String,
+    pub viewers: usize,
+    pub metadata: Option<Metadata>,
+    pub ingest: Ingest,
+}
+
+impl From<SessionInfo> for Stream {
+    fn from(val: SessionInfo) -> Self {
+        Self {
+            name: val.app_name,
+            publisher: val.publisher,
+            publisher_addr: val.publisher_addr,
+            started_at: format_time(val.started_at),
+            viewers: val.viewers,
+            metadata: val.metadata,
+            ingest: val.ingest.into(),
+        }
+    }
+}
+
+
+#[derive(Serialize)]
+pub struct Ingest {
+    pub bytes_received: u64,
+    pub video_frames: u64,
+    pub audio_frames: u64,
+    pub bitrate: u64,
+    pub frame_rate: f64,
+}
+
+impl From<IngestStats> for Ingest {
+    fn from(val: IngestStats) -> Self {
+        Self {
+            bytes_received: val.bytes_received,
+            video_frames: val.video_frames,
+            audio_frames: val.audio_frames,
+            bitrate: val.bitrate,
+            frame_rate: val.frame_rate,
+        }
+    }
+}
+
+
+#[derive(Serialize)]
+pub struct Client {
+    pub id: u64,
+    pub role: &'static str,
+    pub stream: String,
+    pub addr: SocketAddr,
+    pub connected_at: String,
+}
+
+impl From<ClientInfo> for Client {
+    fn from(val: ClientInfo) -> Self {
+        let role = match val.role {
+            ClientRole::Publisher => "publisher",
+            ClientRole::Viewer => "viewer",
+        };
+
+        Self {
+            id: val.id,
+            role,
+            stream: val.app_name,
+            addr: val.addr,
+            connected_at: format_time(val.connected_at),
+        }
+    }
+}
+
+
+#[derive(Serialize)]
+pub struct User {
+    pub name: String,
+    pub keys: Vec<Key>,
+}
+
+impl From<models::User> for User {
+    fn from(val: models::User) -> Self {
+        Self {
+            name: val.name,
+            keys: val.keys.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+
+/// Stream key without the hash.
+#[derive(Serialize)]
+pub struct Key {
+    pub name: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+impl From<models::StreamKey> for Key {
+    fn from(val: models::StreamKey) -> Self {
+        Self {
+            name: val.name,
+            expires_at: val.expires_at.map(|time| time.to_rfc3339()),
+            last_used_at: val.last_used_at.map(|time| time.to_rfc3339()),
+        }
+    }
+}
+
+
+#[derive(Deserialize)]
+pub struct NewUser {
+    pub name: String,
+    /// A key is generated if none is given
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+
+/// The only time the plaintext key is returned.
+#[derive(Serialize)]
+pub struct CreatedUser {
+    pub name: String,
+    pub key: String,
+}
+
+
+#[derive(Serialize)]
+pub struct Permission {
+    pub app: String,
+    pub stream: String,
+}
+
+impl From<models::StreamPermission> for Permission {
+    fn from(val: models::StreamPermission) -> Self {
+        Self {
+            app: val.app,
+            stream: val.stream,
+        }
+    }
+}
+
+
+#[derive(Deserialize)]
+pub struct StreamKey {
+    pub key: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
@@ -0,0 +1,312 @@
+use {
+    constant_time_eq::constant_time_eq,
+    warp::{Filter, Rejection, Reply, reject},
+    javelin_types::models::UserRepository,
+    javelin_core::{
+        session::{self, ClientId},
+        shutdown::Shutdown,
+    },
+    crate::{
+        config::Config as ApiConfig,
+        error::Error,
+        handlers,
+    },
+};
+
+
+pub struct Service<D> {
+    config: ApiConfig,
+    session_manager: session::ManagerHandle,
+    user_repo: D,
+}
+
+impl<D> Service<D>
+    where D: UserRepository + Clone + Send + Sync + 'static
+{
+    pub fn new(session_manager: session::ManagerHandle, user_repo: D, config: ApiConfig) -> Self {
+        Self {
+            config,
+            session_manager,
+            user_repo,
+        }
+    }
+
+    pub async fn run(self, mut shutdown: Shutdown) {
+        if !self.config.enabled {
+            return;
+        }
+
+        if self.config.token.is_empty() {
+            log::warn!("No admin token configured, HTTP API is not served");
+            // returning early would look like a failed service
+            shutdown.wait().await;
+            return;
+        }
+
+        let addr = self.config.addr;
+        log::info!("Starting HTTP API on {}", addr);
+
+        let routes = api_routes(self.session_manager, self.user_repo, self.config.token)
+            .recover(handlers::handle_rejection);
+
+        let (_, server) = warp::serve(routes)
+            .bind_with_graceful_shutdown(addr, async move { shutdown.wait().await });
+        server.await;
+        log::info!("HTTP API stopped");
+    }
+}
+
+
+/// Routes below `/api`, every request has to carry the admin token.
+fn api_routes<D>(session_manager: session::ManagerHandle, user_repo: D, token: String)
+    -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone
+    where D: UserRepository + Clone + Send + Sync + 'static
+{
+    let with_manager = warp::any().map(move || session_manager.clone());
+    let with_user_repo = warp::any().map(move || user_repo.clone());
+
+    let list_streams = warp::path!("streams")
+        .and(warp::get())
+        .and(with_manager.clone())
+        .and_then(handlers::list_streams);
+
+    // stream names contain slashes, so they span multiple segments
+    let show_stream = warp::path("streams")
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(with_manager.clone())
+        .and_then(handlers::show_stream);
+
+    let list_clients = warp::path!("clients")
+        .and(warp::get())
+        .and(with_manager.clone())
+        .and_then(handlers::list_clients);
+
+    let kick_client = warp::path!("clients" / ClientId)
+        .and(warp::delete())
+        .and(with_manager)
+        .and_then(handlers::kick_client);
+
+    let list_users = warp::path!("users")
+        .and(warp::get())
+        .and(with_user_repo.clone())
+        .and_then(handlers::list_users);
+
+    let create_user = warp::path!("users")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(with_user_repo.clone())
+        .and_then(handlers::create_user);
+
+    let show_user = warp::path!("users" / String)
+        .and(warp::get())
+        .and(with_user_repo.clone())
+        .and_then(handlers::show_user);
+
+    let set_user_key = warp::path!("users" / String)
+        .and(warp::put())
+        .and(warp::body::json())
+        .and(with_user_repo.clone())
+        .and_then(handlers::set_user_key);
+
+    let delete_user = warp::path!("users" / String)
+        .and(warp::delete())
+        .and(with_user_repo.clone())
+        .and_then(handlers::delete_user);
+
+    let revoke_user_key = warp::path!("users" / String / "keys" / String)
+        .and(warp::delete())
+        .and(with_user_repo.clone())
+        .and_then(handlers::revoke_user_key);
+
+    let list_permissions = warp::path!("users" / String / "permissions")
+        .and(warp::get())
+        .and(with_user_repo.clone())
+        .and_then(handlers::list_permissions);
+
+    let allow_publish = warp::path!("users" / String / "permissions" / String / String)
+        .and(warp::put())
+        .and(with_user_repo.clone())
+        .and_then(handlers::allow_publish);
+
+    let disallow_publish = warp::path!("users" / String / "permissions" / String / String)
+        .and(warp::delete())
+        .and(with_user_repo)
+        .and_then(handlers::disallow_publish);
+
+    warp::path("api")
+        .and(authorized(token))
+        .and(list_streams
+            .or(show_stream)
+            .or(list_clients)
+            .or(kick_client)
+            .or(list_users)
+            .or(create_user)
+            .or(show_user)
+            .or(set_user_key)
+            .or(delete_user)
+            .or(revoke_user_key)
+            .or(list_permissions)
+            .or(allow_publish)
+            .or(disallow_publish))
+}
+
+fn authorized(token: String) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let expected = format!("Bearer {}", token);
+            async move {
+                match header {
+                    Some(header) if constant_time_eq(header.as_bytes(), expected.as_bytes()) => Ok(()),
+                    _ => Err(reject::custom(Error::Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        chrono::{DateTime, Utc},
+        tokio::sync::mpsc,
+        warp::{http::StatusCode, test::request},
+        javelin_types::{
+            async_trait,
+            models::{User, StreamKey, StreamPermission, Error as DbError},
+        },
+        javelin_core::session::ManagerMessage,
+        super::*,
+    };
+
+    const TOKEN: &str = "token";
+    const USER: &str = "alice";
+
+    /// Knows a single user without any keys or permissions.
+    #[derive(Clone)]
+    struct Users;
+
+    #[async_trait]
+    impl UserRepository for Users {
+        async fn user_by_name(&self, name: &str) -> Result<Option<User>, DbError> {
+            if name != USER {
+                return Ok(None);
+            }
+
+            Ok(Some(User { name: name.to_string(), keys: Vec::new() }))
+        }
+
+        async fn users(&self) -> Result<Vec<User>, DbError> {
+            Ok(vec![User { name: USER.to_string(), keys: Vec::new() }])
+        }
+
+        async fn delete_user(&self, name: &str) -> Result<bool, DbError> {
+            Ok(name == USER)
+        }
+
+        async fn put_key(&self, _user_name: &str, _key: &StreamKey) -> Result<(), DbError> {
+            Ok(())
+        }
+
+        async fn revoke_key(&self, _user_name: &str, _key_name: &str) -> Result<bool, DbError> {
+            Ok(false)
+        }
+
+        async fn touch_key(&self, _user_name: &str, _key_name: &str, _used_at: DateTime<Utc>) -> Result<bool, DbError> {
+            Ok(false)
+        }
+
+        async fn permissions(&self, _user_name: &str) -> Result<Vec<StreamPermission>, DbError> {
+            Ok(Vec::new())
+        }
+
+        async fn stream_publishers(&self, _app: &str, _stream: &str) -> Result<Vec<String>, DbError> {
+            Ok(Vec::new())
+        }
+
+        async fn allow_publish(&self, _user_name: &str, _permission: &StreamPermission) -> Result<(), DbError> {
+            Ok(())
+        }
+
+        async fn disallow_publish(&self, _user_name: &str, _permission: &StreamPermission) -> Result<bool, DbError> {
+            Ok(false)
+        }
+    }
+
+    /// Answers session queries as if no stream is live.
+    fn session_manager() -> session::ManagerHandle {
+        let (handle, mut receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    ManagerMessage::ListSessions(responder) => {
+                        let _ = responder.send(Vec::new());
+                    },
+                    ManagerMessage::GetSession((_, responder)) => {
+                        let _ = responder.send(None);
+                    },
+                    _ => (),
+                }
+            }
+        });
+
+        handle
+    }
+
+    async fn send(method: &str, path: &str, token: Option<&str>) -> (StatusCode, String) {
+        let filter = api_routes(session_manager(), Users, TOKEN.to_string())
+            .recover(handlers::handle_rejection);
+
+        let mut request = request().method(method).path(path);
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+
+        let response = request.reply(&filter).await;
+        let body = String::from_utf8_lossy(response.body()).into_owned();
+        (response.status(), body)
+    }
+
+    #[tokio::test]
+    async fn requests_without_valid_token_are_rejected() {
+        let (status, _) = send("GET", "/api/streams", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send("GET", "/api/streams", Some("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = send("GET", "/api/streams", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn stream_name_spans_multiple_segments() {
+        let (status, body) = send("GET", "/api/streams/live/test", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.contains("No stream with name live/test found"));
+
+        let (status, _) = send("GET", "/api/streams/live/../test", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn users_can_be_managed() {
+        let (status, body) = send("GET", "/api/users", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(USER));
+
+        let (status, _) = send("DELETE", "/api/users/alice", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = send("DELETE", "/api/users/bob", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send("PUT", "/api/users/alice/permissions/live/*", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, _) = send("PUT", "/api/users/bob/permissions/live/*", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
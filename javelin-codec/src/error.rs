@@ -2,6 +2,7 @@ use {
     thiserror::Error,
     crate::{
         avc::AvcError,
+        hevc::HevcError,
         aac::AacError,
         flv::FlvError,
     }
@@ -15,6 +16,9 @@ pub enum CodecError {
     #[error(transparent)]
     AvcError(#[from] AvcError),
 
+    #[error(transparent)]
+    HevcError(#[from] HevcError),
+
     #[error(transparent)]
     AacError(#[from] AacError),
 
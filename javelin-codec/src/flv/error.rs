@@ -18,9 +18,15 @@ pub enum FlvError {
     #[error("Video format with id {0} is not supported")]
     UnsupportedVideoFormat(u8),
 
+    #[error("Video codec {0} is not supported")]
+    UnsupportedVideoFourCc(String),
+
     #[error("Audio format with id {0} is not supported")]
     UnsupportedAudioFormat(u8),
 
+    #[error("Audio codec {0} is not supported")]
+    UnsupportedAudioFourCc(String),
+
     #[error("Not enough data: {0}")]
     NotEnoughData(&'static str),
 
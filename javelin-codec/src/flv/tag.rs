@@ -3,6 +3,6 @@ pub mod video;
 
 
 pub use {
-    audio::AudioData,
-    video::VideoData,
+    audio::{AudioData, AudioFormat, AudioPacketType},
+    video::{VideoData, VideoCodec, VideoPacketType},
 };
@@ -17,31 +17,75 @@ pub struct Frequency(u32);
 #[non_exhaustive]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AudioFormat {
+    LinearPcmPlatformEndian,
+    Adpcm,
+    Mp3,
+    LinearPcmLittleEndian,
+    Nellymoser16kMono,
+    Nellymoser8kMono,
+    Nellymoser,
+    G711ALaw,
+    G711MuLaw,
     Aac,
+    Speex,
+    Mp3_8k,
+    DeviceSpecific,
+    // Enhanced RTMP only
+    Opus,
+    Flac,
+    Ac3,
+    Eac3,
+}
+
+impl AudioFormat {
+    /// Codecs of the Enhanced RTMP extension
+    fn from_fourcc(val: [u8; 4]) -> Result<Self, FlvError> {
+        match &val {
+            b"Opus" => Ok(Self::Opus),
+            b"fLaC" => Ok(Self::Flac),
+            b"ac-3" => Ok(Self::Ac3),
+            b"ec-3" => Ok(Self::Eac3),
+            b".mp3" => Ok(Self::Mp3),
+            b"mp4a" => Ok(Self::Aac),
+            _ => Err(FlvError::UnsupportedAudioFourCc(String::from_utf8_lossy(&val).into_owned())),
+        }
+    }
 }
 
 impl TryFrom<u8> for AudioFormat {
     type Error = FlvError;
 
     fn try_from(val: u8) -> Result<Self, Self::Error> {
-        // only needs to have support for AAC right now
-        if val == 10 {
-            Ok(Self::Aac)
-        } else {
-            Err(FlvError::UnsupportedAudioFormat(val))
-        }
+        Ok(match val {
+            0 => Self::LinearPcmPlatformEndian,
+            1 => Self::Adpcm,
+            2 => Self::Mp3,
+            3 => Self::LinearPcmLittleEndian,
+            4 => Self::Nellymoser16kMono,
+            5 => Self::Nellymoser8kMono,
+            6 => Self::Nellymoser,
+            7 => Self::G711ALaw,
+            8 => Self::G711MuLaw,
+            10 => Self::Aac,
+            11 => Self::Speex,
+            14 => Self::Mp3_8k,
+            15 => Self::DeviceSpecific,
+            x => return Err(FlvError::UnsupportedAudioFormat(x))
+        })
     }
 }
 
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum AacPacketType {
+pub enum AudioPacketType {
     SequenceHeader,
     Raw,
+    SequenceEnd,
+    MultichannelConfig,
     None,
 }
 
-impl TryFrom<u8> for AacPacketType {
+impl TryFrom<u8> for AudioPacketType {
     type Error = FlvError;
 
     fn try_from(val: u8) -> Result<Self, Self::Error> {
@@ -54,27 +98,75 @@ impl TryFrom<u8> for AacPacketType {
 }
 
 
+/// Sound format of the extended header
+const EX_HEADER_FORMAT: u8 = 9;
+
+
 // Field                | Type
 // -------------------- | ---
 // Audio Format         | u4
-// Sampling Rate        | u4
-// Sampling Size        | u2
+// Sampling Rate        | u2
+// Sampling Size        | u1
 // Stereo Flag          | u1
-// AAC Packet Type      | u8
+// AAC Packet Type      | u8, only AAC
+// Body                 | [u8]
+//
+// Enhanced RTMP, if the audio format is 9:
+//
+// Field                | Type
+// -------------------- | ---
+// Audio Format         | u4
+// Packet Type          | u4
+// FourCC               | [u8; 4]
 // Body                 | [u8]
 #[derive(Clone)]
 pub struct AudioData {
     pub format: AudioFormat,
-    pub sampling_rate: Frequency,
-    pub sample_size: u8,
-    pub stereo: bool,
-    pub aac_packet_type: AacPacketType,
+    /// Not part of Enhanced RTMP tags, the sequence header describes it instead.
+    pub sampling_rate: Option<Frequency>,
+    pub sample_size: Option<u8>,
+    pub stereo: Option<bool>,
+    pub packet_type: AudioPacketType,
     pub body: Bytes,
 }
 
 impl AudioData {
     pub fn is_sequence_header(&self) -> bool {
-        self.aac_packet_type == AacPacketType::SequenceHeader
+        self.packet_type == AudioPacketType::SequenceHeader
+    }
+
+    fn try_from_extended(bytes: &[u8]) -> Result<Self, FlvError> {
+        if bytes.len() < 5 {
+            return Err(FlvError::NotEnoughData("Enhanced FLV Audio Tag header"))
+        }
+
+        let mut buf = Cursor::new(bytes);
+
+        let header = buf.get_u8();
+
+        let mut fourcc = [0; 4];
+        buf.copy_to_slice(&mut fourcc);
+        let format = AudioFormat::from_fourcc(fourcc)?;
+
+        let packet_type = match header & 0x0F {
+            0 => AudioPacketType::SequenceHeader,
+            1 => AudioPacketType::Raw,
+            2 => AudioPacketType::SequenceEnd,
+            4 => AudioPacketType::MultichannelConfig,
+            x => return Err(FlvError::UnknownPackageType(x)),
+        };
+
+        let mut body = Vec::new();
+        buf.read_to_end(&mut body)?;
+
+        Ok(Self {
+            format,
+            sampling_rate: None,
+            sample_size: None,
+            stereo: None,
+            packet_type,
+            body: body.into(),
+        })
     }
 }
 
@@ -82,7 +174,11 @@ impl TryFrom<&[u8]> for AudioData {
     type Error = FlvError;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        if bytes.len() < 2 {
+        if bytes.first().map_or(false, |header| header >> 4 == EX_HEADER_FORMAT) {
+            return Self::try_from_extended(bytes)
+        }
+
+        if bytes.is_empty() {
             return Err(FlvError::NotEnoughData("FLV Audio Tag header"))
         }
 
@@ -90,20 +186,30 @@ impl TryFrom<&[u8]> for AudioData {
 
         let header = buf.get_u8();
         let format = AudioFormat::try_from(header >> 4)?;
-        let sampling_rate = try_convert_sampling_rate((header >> 2) & 0x02)?;
+        let sampling_rate = try_convert_sampling_rate((header >> 2) & 0x03)?;
         let sample_size = try_convert_sample_size((header >> 1) & 0x01)?;
         let stereo = (header & 0x01) == 1;
 
-        let aac_packet_type = if format == AudioFormat::Aac {
-            AacPacketType::try_from(buf.get_u8())?
+        let packet_type = if format == AudioFormat::Aac {
+            if !buf.has_remaining() {
+                return Err(FlvError::NotEnoughData("FLV Audio Tag AAC packet type"))
+            }
+            AudioPacketType::try_from(buf.get_u8())?
         } else {
-            AacPacketType::None
+            AudioPacketType::Raw
         };
 
         let mut body = Vec::new();
         buf.read_to_end(&mut body)?;
 
-        Ok(Self { format, sampling_rate, sample_size, stereo, aac_packet_type, body: body.into() })
+        Ok(Self {
+            format,
+            sampling_rate: Some(sampling_rate),
+            sample_size: Some(sample_size),
+            stereo: Some(stereo),
+            packet_type,
+            body: body.into(),
+        })
     }
 }
 
@@ -114,7 +220,7 @@ impl Debug for AudioData {
             .field("sampling_rate", &self.sampling_rate)
             .field("sample_size", &self.sample_size)
             .field("stereo", &self.stereo)
-            .field("aac_packet_type", &self.aac_packet_type)
+            .field("packet_type", &self.packet_type)
             .finish()
     }
 }
@@ -122,10 +228,10 @@ impl Debug for AudioData {
 
 fn try_convert_sampling_rate(val: u8) -> Result<Frequency, FlvError> {
     Ok(match val {
-        0 => Frequency(5500),
-        1 => Frequency(11000),
-        2 => Frequency(22000),
-        3 => Frequency(44000),
+        0 => Frequency(5512),
+        1 => Frequency(11025),
+        2 => Frequency(22050),
+        3 => Frequency(44100),
         x => return Err(FlvError::UnsupportedSamplingRate(x))
     })
 }
@@ -137,3 +243,109 @@ fn try_convert_sample_size(val: u8) -> Result<u8, FlvError> {
         x => return Err(FlvError::UnsupportedSampleSize(x))
     })
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [(u8, AudioFormat); 13] = [
+        (0, AudioFormat::LinearPcmPlatformEndian),
+        (1, AudioFormat::Adpcm),
+        (2, AudioFormat::Mp3),
+        (3, AudioFormat::LinearPcmLittleEndian),
+        (4, AudioFormat::Nellymoser16kMono),
+        (5, AudioFormat::Nellymoser8kMono),
+        (6, AudioFormat::Nellymoser),
+        (7, AudioFormat::G711ALaw),
+        (8, AudioFormat::G711MuLaw),
+        (10, AudioFormat::Aac),
+        (11, AudioFormat::Speex),
+        (14, AudioFormat::Mp3_8k),
+        (15, AudioFormat::DeviceSpecific),
+    ];
+
+    const RATES: [(u8, u32); 4] = [(0, 5512), (1, 11025), (2, 22050), (3, 44100)];
+
+    fn header_byte(audio: &AudioData) -> u8 {
+        let format = FORMATS.iter().find(|(_, format)| *format == audio.format).unwrap().0;
+        let rate = RATES.iter().find(|(_, hz)| Some(Frequency(*hz)) == audio.sampling_rate).unwrap().0;
+        let size = if audio.sample_size == Some(16) { 1 } else { 0 };
+        let stereo = if audio.stereo == Some(true) { 1 } else { 0 };
+        format << 4 | rate << 2 | size << 1 | stereo
+    }
+
+    #[test]
+    fn header_round_trips_for_every_format_and_rate() {
+        for &(id, format) in &FORMATS {
+            for &(rate, hz) in &RATES {
+                for &(size, stereo) in &[(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    let header = id << 4 | rate << 2 | size << 1 | stereo;
+                    let bytes = if format == AudioFormat::Aac {
+                        vec![header, 1, 0xAB]
+                    } else {
+                        vec![header, 0xAB]
+                    };
+
+                    let audio = AudioData::try_from(bytes.as_slice()).unwrap();
+
+                    assert_eq!(audio.format, format);
+                    assert_eq!(audio.sampling_rate, Some(Frequency(hz)));
+                    assert_eq!(audio.packet_type, AudioPacketType::Raw);
+                    assert_eq!(audio.body.as_ref(), &[0xAB]);
+                    assert_eq!(header_byte(&audio), header);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reserved_formats_are_rejected() {
+        for &id in &[12u8, 13] {
+            let result = AudioData::try_from([id << 4, 0].as_ref());
+            assert!(matches!(result, Err(FlvError::UnsupportedAudioFormat(x)) if x == id));
+        }
+    }
+
+    #[test]
+    fn aac_sequence_header_is_detected() {
+        let audio = AudioData::try_from([0xAF, 0, 0x12, 0x10].as_ref()).unwrap();
+        assert!(audio.is_sequence_header());
+        assert_eq!(audio.body.as_ref(), &[0x12, 0x10]);
+    }
+
+    #[test]
+    fn mp3_is_never_a_sequence_header() {
+        let audio = AudioData::try_from([0x2F, 0, 0xFF, 0xFB].as_ref()).unwrap();
+        assert!(!audio.is_sequence_header());
+        assert_eq!(audio.body.as_ref(), &[0, 0xFF, 0xFB]);
+    }
+
+    #[test]
+    fn parses_enhanced_opus_tags() {
+        let mut bytes = vec![EX_HEADER_FORMAT << 4];
+        bytes.extend(b"Opus");
+        bytes.extend(&[0x4F, 0x70]);
+
+        let audio = AudioData::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(audio.format, AudioFormat::Opus);
+        assert!(audio.is_sequence_header());
+        assert_eq!(audio.sampling_rate, None);
+        assert_eq!(audio.body.as_ref(), &[0x4F, 0x70]);
+
+        bytes[0] = EX_HEADER_FORMAT << 4 | 1;
+        let audio = AudioData::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(audio.packet_type, AudioPacketType::Raw);
+    }
+
+    #[test]
+    fn unknown_fourcc_is_rejected() {
+        let mut bytes = vec![EX_HEADER_FORMAT << 4 | 1];
+        bytes.extend(b"abcd");
+
+        let result = AudioData::try_from(bytes.as_slice());
+
+        assert!(matches!(result, Err(FlvError::UnsupportedAudioFourCc(fourcc)) if fourcc == "abcd"));
+    }
+}
@@ -34,21 +34,49 @@ impl TryFrom<u8> for FrameType {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    Avc,
+    Hevc,
+    Av1,
+}
+
+impl VideoCodec {
+    fn from_codec_id(val: u8) -> Result<Self, FlvError> {
+        match val {
+            7 => Ok(Self::Avc),
+            x => Err(FlvError::UnsupportedVideoFormat(x)),
+        }
+    }
+
+    /// Codecs of the Enhanced RTMP extension
+    fn from_fourcc(val: [u8; 4]) -> Result<Self, FlvError> {
+        match &val {
+            b"avc1" => Ok(Self::Avc),
+            b"hvc1" => Ok(Self::Hevc),
+            b"av01" => Ok(Self::Av1),
+            _ => Err(FlvError::UnsupportedVideoFourCc(String::from_utf8_lossy(&val).into_owned())),
+        }
+    }
+}
+
+
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum AvcPacketType {
+pub enum VideoPacketType {
     SequenceHeader,
-    NalUnit,
+    CodedFrames,
     EndOfSequence,
+    Metadata,
     None,
 }
 
-impl TryFrom<u8> for AvcPacketType {
+impl TryFrom<u8> for VideoPacketType {
     type Error = FlvError;
 
     fn try_from(val: u8) -> Result<Self, Self::Error> {
         Ok(match val {
             0 => Self::SequenceHeader,
-            1 => Self::NalUnit,
+            1 => Self::CodedFrames,
             2 => Self::EndOfSequence,
             x => return Err(FlvError::UnknownPackageType(x))
         })
@@ -63,28 +91,80 @@ impl TryFrom<u8> for AvcPacketType {
 // AVC Packet Type      | u8
 // Composition Time     | i24
 // Body                 | [u8]
+//
+// Enhanced RTMP, if the highest bit is set:
+//
+// Field                | Type
+// -------------------- | ---
+// Extended Header Flag | u1
+// Frame Type           | u3
+// Packet Type          | u4
+// FourCC               | [u8; 4]
+// Composition Time     | i24, only coded frames of AVC and HEVC
+// Body                 | [u8]
 #[derive(Clone)]
 pub struct VideoData {
     pub frame_type: FrameType,
-    pub packet_type: AvcPacketType,
+    pub codec: VideoCodec,
+    pub packet_type: VideoPacketType,
     pub composition_time: i32,
     pub body: Bytes,
 }
 
 impl VideoData {
     pub fn is_sequence_header(&self) -> bool {
-        self.packet_type == AvcPacketType::SequenceHeader
+        self.packet_type == VideoPacketType::SequenceHeader
     }
 
     pub fn is_keyframe(&self) -> bool {
         self.frame_type == FrameType::KeyFrame
     }
+
+    fn try_from_extended(bytes: &[u8]) -> Result<Self, FlvError> {
+        if bytes.len() < 5 {
+            return Err(FlvError::NotEnoughData("Enhanced FLV Video Tag header"))
+        }
+
+        let mut buf = Cursor::new(bytes);
+
+        let header = buf.get_u8();
+        let frame_type = FrameType::try_from((header >> 4) & 0x07)?;
+
+        let mut fourcc = [0; 4];
+        buf.copy_to_slice(&mut fourcc);
+        let codec = VideoCodec::from_fourcc(fourcc)?;
+
+        let (packet_type, has_composition_time) = match header & 0x0F {
+            0 => (VideoPacketType::SequenceHeader, false),
+            1 => (VideoPacketType::CodedFrames, codec != VideoCodec::Av1),
+            2 => (VideoPacketType::EndOfSequence, false),
+            // coded frames without composition time
+            3 => (VideoPacketType::CodedFrames, false),
+            4 => (VideoPacketType::Metadata, false),
+            x => return Err(FlvError::UnknownPackageType(x)),
+        };
+
+        let composition_time = if has_composition_time {
+            if buf.remaining() < 3 {
+                return Err(FlvError::NotEnoughData("Enhanced FLV Video Tag composition time"))
+            }
+            sign_extend_i24(buf.get_uint(3) as u32)
+        } else {
+            0
+        };
+
+        let mut remaining = Vec::new();
+        buf.read_to_end(&mut remaining)?;
+
+        Ok(Self { frame_type, codec, packet_type, composition_time, body: remaining.into() })
+    }
 }
 
 impl Debug for VideoData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Video")
             .field("frame_type", &self.frame_type)
+            .field("codec", &self.codec)
             .field("packet_type", &self.packet_type)
             .field("composition_time", &self.composition_time)
             .finish()
@@ -95,6 +175,10 @@ impl TryFrom<&[u8]> for VideoData {
     type Error = FlvError;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.first().map_or(false, |header| header & 0x80 != 0) {
+            return Self::try_from_extended(bytes)
+        }
+
         if bytes.len() < 5 {
             return Err(FlvError::NotEnoughData("FLV Video Tag header"))
         }
@@ -103,23 +187,121 @@ impl TryFrom<&[u8]> for VideoData {
 
         let header_a = buf.get_u8();
 
-        // Only support AVC payloads
-        let codec_id = header_a & 0x0F;
-        if codec_id != 7 {
-            return Err(FlvError::UnsupportedVideoFormat(codec_id))
-        }
+        let codec = VideoCodec::from_codec_id(header_a & 0x0F)?;
 
         let frame_type = FrameType::try_from(header_a >> 4)?;
 
         let header_b = buf.get_u32();
 
-        let packet_type =  AvcPacketType::try_from((header_b >> 24) as u8)?;
+        let packet_type =  VideoPacketType::try_from((header_b >> 24) as u8)?;
 
-        let composition_time = (header_b & 0x00_FF_FF_FF) as i32;
+        let composition_time = sign_extend_i24(header_b & 0x00_FF_FF_FF);
 
         let mut remaining = Vec::new();
         buf.read_to_end(&mut remaining)?;
 
-        Ok(Self { frame_type, packet_type, composition_time, body: remaining.into() })
+        Ok(Self { frame_type, codec, packet_type, composition_time, body: remaining.into() })
+    }
+}
+
+
+/// Composition times are signed 24 bit integers.
+fn sign_extend_i24(val: u32) -> i32 {
+    ((val << 8) as i32) >> 8
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extended_tag(frame_type: u8, packet_type: u8, fourcc: &[u8; 4], rest: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x80 | frame_type << 4 | packet_type];
+        bytes.extend(fourcc);
+        bytes.extend(rest);
+        bytes
+    }
+
+    #[test]
+    fn parses_hevc_sequence_header() {
+        let bytes = extended_tag(1, 0, b"hvc1", &[0x01, 0x02]);
+
+        let video = VideoData::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(video.codec, VideoCodec::Hevc);
+        assert!(video.is_sequence_header());
+        assert!(video.is_keyframe());
+        assert_eq!(video.composition_time, 0);
+        assert_eq!(video.body.as_ref(), &[0x01, 0x02]);
+    }
+
+    #[test]
+    fn parses_hevc_coded_frames_with_composition_time() {
+        let bytes = extended_tag(2, 1, b"hvc1", &[0x00, 0x00, 0x50, 0xAA]);
+
+        let video = VideoData::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(video.packet_type, VideoPacketType::CodedFrames);
+        assert_eq!(video.frame_type, FrameType::InterFrame);
+        assert_eq!(video.composition_time, 80);
+        assert_eq!(video.body.as_ref(), &[0xAA]);
+    }
+
+    #[test]
+    fn negative_composition_time_is_sign_extended() {
+        let bytes = extended_tag(2, 1, b"hvc1", &[0xFF, 0xFF, 0xD8, 0xAA]);
+        let video = VideoData::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(video.composition_time, -40);
+
+        let bytes = [0x27, 0x01, 0xFF, 0xFF, 0xD8, 0xAA];
+        let video = VideoData::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(video.codec, VideoCodec::Avc);
+        assert_eq!(video.composition_time, -40);
+    }
+
+    #[test]
+    fn hevc_frames_without_composition_time() {
+        let bytes = extended_tag(1, 3, b"hvc1", &[0xAA]);
+
+        let video = VideoData::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(video.packet_type, VideoPacketType::CodedFrames);
+        assert_eq!(video.composition_time, 0);
+        assert_eq!(video.body.as_ref(), &[0xAA]);
+    }
+
+    #[test]
+    fn av1_coded_frames_have_no_composition_time() {
+        let bytes = extended_tag(1, 1, b"av01", &[0x12, 0x00, 0x0A]);
+
+        let video = VideoData::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(video.codec, VideoCodec::Av1);
+        assert_eq!(video.packet_type, VideoPacketType::CodedFrames);
+        assert!(video.is_keyframe());
+        assert_eq!(video.composition_time, 0);
+        assert_eq!(video.body.as_ref(), &[0x12, 0x00, 0x0A]);
+    }
+
+    #[test]
+    fn parses_end_of_sequence_and_metadata() {
+        let video = VideoData::try_from(extended_tag(1, 2, b"av01", &[]).as_slice()).unwrap();
+        assert_eq!(video.packet_type, VideoPacketType::EndOfSequence);
+
+        let video = VideoData::try_from(extended_tag(5, 4, b"hvc1", &[0x02]).as_slice()).unwrap();
+        assert_eq!(video.packet_type, VideoPacketType::Metadata);
+        assert_eq!(video.body.as_ref(), &[0x02]);
+    }
+
+    #[test]
+    fn rejects_truncated_composition_time() {
+        let result = VideoData::try_from(extended_tag(2, 1, b"hvc1", &[0x00]).as_slice());
+        assert!(matches!(result, Err(FlvError::NotEnoughData(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_fourcc() {
+        let result = VideoData::try_from(extended_tag(1, 1, b"vp09", &[0, 0, 0]).as_slice());
+        assert!(matches!(result, Err(FlvError::UnsupportedVideoFourCc(fourcc)) if fourcc == "vp09"));
     }
 }
@@ -0,0 +1,226 @@
+mod error;
+pub mod nal;
+pub mod config;
+pub mod annexb;
+pub mod hvcc;
+
+use {
+    std::{
+        convert::TryInto,
+        fmt::{self, Debug},
+    },
+    self::config::DecoderConfigurationRecord,
+    crate::{ReadFormat, WriteFormat, FormatReader, FormatWriter},
+};
+pub use self::{
+    error::HevcError,
+    annexb::AnnexB,
+    hvcc::Hvcc,
+};
+
+
+pub struct Hevc(Vec<nal::Unit>);
+
+impl From<Vec<nal::Unit>> for Hevc {
+    fn from(val: Vec<nal::Unit>) -> Self {
+        Self(val)
+    }
+}
+
+impl From<Hevc> for Vec<nal::Unit> {
+    fn from(val: Hevc) -> Self {
+        val.0
+    }
+}
+
+
+#[derive(Debug, PartialEq, Eq)]
+enum State {
+    Initializing,
+    Ready,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::Initializing
+    }
+}
+
+
+#[derive(Default)]
+pub struct HevcCoder {
+    dcr: Option<DecoderConfigurationRecord>,
+    state: State,
+}
+
+impl HevcCoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_dcr<D>(&mut self, dcr: D) -> Result<(), HevcError>
+        where D: TryInto<DecoderConfigurationRecord, Error=HevcError>
+    {
+        let dcr = dcr.try_into()?;
+        self.dcr = Some(dcr);
+        self.state = State::Ready;
+        Ok(())
+    }
+}
+
+impl Debug for HevcCoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HevcDecoder")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl FormatReader<Hvcc> for HevcCoder {
+    type Output = Hevc;
+    type Error = HevcError;
+
+    fn read_format(&mut self, format: Hvcc, input: &[u8]) -> Result<Option<Self::Output>, Self::Error> {
+        Ok(match &self.state {
+            State::Initializing => {
+                self.set_dcr(input)
+                    .map_err(|_| HevcError::DecoderInitializationFailed)?;
+                None
+            },
+            State::Ready => {
+                let dcr = self.dcr.as_ref().unwrap();
+                Some(format.read_format(input, dcr)?)
+            }
+        })
+    }
+}
+
+impl FormatWriter<AnnexB> for HevcCoder {
+    type Input = Hevc;
+    type Error = HevcError;
+
+    fn write_format(&mut self, format: AnnexB, input: Self::Input) -> Result<Vec<u8>, Self::Error> {
+        match &self.state {
+            State::Initializing => {
+                Err(HevcError::NotInitialized)
+            },
+            State::Ready => {
+                let dcr = self.dcr.as_ref().unwrap();
+                Ok(format.write_format(input, dcr)?)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::convert::TryFrom,
+        super::*,
+    };
+
+    // parameter sets of a 1280x720 Main profile stream encoded by x265
+    const VPS: &[u8] = &[
+        0x40, 0x01, 0x0C, 0x01, 0xFF, 0xFF, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00,
+        0x90, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x00, 0x5D, 0x95, 0x98, 0x09,
+    ];
+    const SPS: &[u8] = &[
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x03, 0x00, 0x5D, 0xA0, 0x02, 0x80, 0x80, 0x2D, 0x16,
+        0x59, 0x59, 0xA4, 0x93, 0x2B, 0xC0, 0x40, 0x40, 0x00, 0x00, 0x03, 0x00,
+        0x40, 0x00, 0x00, 0x06, 0x42,
+    ];
+    const PPS: &[u8] = &[0x44, 0x01, 0xC1, 0x72, 0xB4, 0x62, 0x40];
+
+    const IDR_SLICE: &[u8] = &[0x26, 0x01, 0xAF, 0x09, 0x40];
+    const UNSPECIFIED: &[u8] = &[0x7C, 0x01, 0xFF];
+
+    fn configuration_record() -> Vec<u8> {
+        let mut bytes = vec![
+            0x01, // version
+            0x01, // profile space 0, main tier, Main profile
+            0x60, 0x00, 0x00, 0x00, // profile compatibility
+            0x90, 0x00, 0x00, 0x00, 0x00, 0x00, // constraint indicator
+            0x5D, // level 3.1
+            0xF0, 0x00, // min spatial segmentation
+            0xFC, // parallelism type
+            0xFD, // chroma format 4:2:0
+            0xF8, // luma bit depth 8
+            0xF8, // chroma bit depth 8
+            0x00, 0x00, // average frame rate
+            0x0F, // one temporal layer, nested, 4 byte NALU length
+            0x03, // array count
+        ];
+
+        for (kind, unit) in &[(0xA0, VPS), (0xA1, SPS), (0xA2, PPS)] {
+            bytes.push(*kind);
+            bytes.extend(&1u16.to_be_bytes());
+            bytes.extend(&(unit.len() as u16).to_be_bytes());
+            bytes.extend(*unit);
+        }
+
+        bytes
+    }
+
+    fn length_prefixed(units: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for unit in units {
+            bytes.extend(&(unit.len() as u32).to_be_bytes());
+            bytes.extend(*unit);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_configuration_record() {
+        let record = DecoderConfigurationRecord::try_from(configuration_record().as_slice()).unwrap();
+
+        assert_eq!(record.profile_idc, 1);
+        assert!(!record.tier_flag);
+        assert_eq!(record.level_idc, 93);
+        assert_eq!(record.chroma_format, 1);
+        assert_eq!(record.bit_depth_luma, 8);
+        assert_eq!(record.bit_depth_chroma, 8);
+        assert_eq!(record.nalu_size, 4);
+        assert_eq!(Vec::<u8>::from(&record.vps[0]), VPS);
+        assert_eq!(Vec::<u8>::from(&record.sps[0]), SPS);
+        assert_eq!(Vec::<u8>::from(&record.pps[0]), PPS);
+    }
+
+    #[test]
+    fn converts_keyframe_to_annexb() {
+        let mut coder = HevcCoder::new();
+        assert!(coder.read_format(Hvcc, &configuration_record()).unwrap().is_none());
+
+        let frame = length_prefixed(&[IDR_SLICE]);
+        let hevc = coder.read_format(Hvcc, &frame).unwrap().unwrap();
+        let annexb = coder.write_format(AnnexB, hevc).unwrap();
+
+        let mut expected = vec![0x00, 0x00, 0x00, 0x01, 0x46, 0x01, 0x50];
+        for unit in &[VPS, SPS, PPS] {
+            expected.extend(&[0x00, 0x00, 0x00, 0x01]);
+            expected.extend(*unit);
+        }
+        expected.extend(&[0x00, 0x00, 0x01]);
+        expected.extend(IDR_SLICE);
+
+        assert_eq!(annexb, expected);
+    }
+
+    #[test]
+    fn unknown_unit_types_are_passed_through() {
+        assert_eq!(nal::UnitType::from(41), nal::UnitType::Reserved(41));
+        assert_eq!(nal::UnitType::from(62), nal::UnitType::Unspecified(62));
+        assert_eq!(nal::UnitType::from(63), nal::UnitType::Unspecified(63));
+
+        let mut coder = HevcCoder::new();
+        coder.read_format(Hvcc, &configuration_record()).unwrap();
+
+        let frame = length_prefixed(&[IDR_SLICE, UNSPECIFIED]);
+        let hevc = coder.read_format(Hvcc, &frame).unwrap().unwrap();
+        let annexb = coder.write_format(AnnexB, hevc).unwrap();
+
+        assert!(annexb.ends_with(&[0x00, 0x00, 0x01, 0x7C, 0x01, 0xFF]));
+    }
+}
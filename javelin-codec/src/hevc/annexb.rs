@@ -0,0 +1,68 @@
+use {
+    crate::{
+        hevc::{Hevc, error::HevcError, nal, config::DecoderConfigurationRecord},
+        WriteFormat,
+    },
+};
+
+pub struct AnnexB;
+
+impl AnnexB {
+    const DELIMITER1: &'static [u8] = &[0x00, 0x00, 0x01];
+    const DELIMITER2: &'static [u8] = &[0x00, 0x00, 0x00, 0x01];
+    const ACCESS_UNIT_DELIMITER: &'static [u8] = &[0x00, 0x00, 0x00, 0x01, 0x46, 0x01, 0x50];
+}
+
+impl WriteFormat<Hevc> for AnnexB {
+    type Context = DecoderConfigurationRecord;
+    type Error = HevcError;
+
+    fn write_format(&self, input: Hevc, ctx: &Self::Context) -> Result<Vec<u8>, Self::Error> {
+        let mut out_buffer = Vec::new();
+        let mut aud_appended = false;
+        let mut parameter_sets_appended = false;
+        let nalus: Vec<nal::Unit> = input.into();
+
+        for nalu in nalus {
+            use nal::UnitType::*;
+
+            match nalu.kind {
+                VideoParameterSet | SequenceParameterSet | PictureParameterSet | AccessUnitDelimiter => continue,
+                kind if kind.is_irap() => {
+                    if !aud_appended {
+                        out_buffer.extend(Self::ACCESS_UNIT_DELIMITER);
+                        aud_appended = true;
+                    }
+
+                    if !parameter_sets_appended {
+                        let parameter_sets = ctx.vps.iter()
+                            .chain(ctx.sps.iter())
+                            .chain(ctx.pps.iter());
+
+                        for unit in parameter_sets {
+                            out_buffer.extend(Self::DELIMITER2);
+                            let tmp: Vec<u8> = unit.into();
+                            out_buffer.extend(tmp);
+                        }
+
+                        parameter_sets_appended = true;
+                    }
+                },
+                kind if kind.is_vcl() || kind == PrefixSupplementalEnhancementInformation => {
+                    if !aud_appended {
+                        out_buffer.extend(Self::ACCESS_UNIT_DELIMITER);
+                        aud_appended = true;
+                    }
+                },
+                t => log::debug!("Received unhandled NALU type {:?}", t),
+            }
+
+            out_buffer.extend(Self::DELIMITER1);
+
+            let nalu_data: Vec<u8> = nalu.into();
+            out_buffer.extend(nalu_data);
+        }
+
+        Ok(out_buffer)
+    }
+}
@@ -0,0 +1,153 @@
+use {
+    std::{
+        convert::TryFrom,
+        io::Cursor,
+    },
+    bytes::Buf,
+    super::{
+        nal,
+        HevcError,
+    },
+};
+
+
+// Bits | Name
+// ---- | ----
+// 8    | Version
+// 2    | General Profile Space
+// 1    | General Tier Flag
+// 5    | General Profile IDC
+// 32   | General Profile Compatibility Flags
+// 48   | General Constraint Indicator Flags
+// 8    | General Level IDC
+// 4    | Reserved
+// 12   | Min Spatial Segmentation IDC
+// 6    | Reserved
+// 2    | Parallelism Type
+// 6    | Reserved
+// 2    | Chroma Format
+// 5    | Reserved
+// 3    | Bit Depth Luma - 8
+// 5    | Reserved
+// 3    | Bit Depth Chroma - 8
+// 16   | Average Frame Rate
+// 2    | Constant Frame Rate
+// 3    | Temporal Layer Count
+// 1    | Temporal ID Nested
+// 2    | NALU Length - 1
+// 8    | Array Count
+//
+// Every array:
+// 1    | Array Completeness
+// 1    | Reserved
+// 6    | NAL Unit Type
+// 16   | NALU Count
+// 16   | NALU Length
+// var  | NALU
+#[derive(Debug, Clone)]
+pub struct DecoderConfigurationRecord {
+    pub version: u8,
+    pub profile_space: u8,
+    pub tier_flag: bool,
+    pub profile_idc: u8,
+    pub profile_compatibility_flags: u32,
+    pub constraint_indicator_flags: u64,
+    pub level_idc: u8,
+    pub chroma_format: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    pub nalu_size: u8,
+    pub vps: Vec<nal::Unit>,
+    pub sps: Vec<nal::Unit>,
+    pub pps: Vec<nal::Unit>,
+}
+
+impl TryFrom<&[u8]> for DecoderConfigurationRecord {
+    type Error = HevcError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut buf = Cursor::new(bytes);
+
+        if buf.remaining() < 23 {
+            return Err(HevcError::NotEnoughData("HEVC configuration record"))
+        }
+
+        let version = buf.get_u8();
+        if version != 1 {
+            return Err(HevcError::UnsupportedConfigurationRecordVersion(version));
+        }
+
+        let profile = buf.get_u8();
+        let profile_space = profile >> 6;
+        let tier_flag = (profile >> 5) & 0x01 == 1;
+        let profile_idc = profile & 0x1F;
+        let profile_compatibility_flags = buf.get_u32();
+        let constraint_indicator_flags = buf.get_uint(6);
+        let level_idc = buf.get_u8();
+
+        // min spatial segmentation and parallelism type
+        buf.advance(3);
+
+        let chroma_format = buf.get_u8() & 0x03;
+        let bit_depth_luma = (buf.get_u8() & 0x07) + 8;
+        let bit_depth_chroma = (buf.get_u8() & 0x07) + 8;
+
+        // average frame rate
+        buf.advance(2);
+
+        let nalu_size = (buf.get_u8() & 0x03) + 1;
+
+        let mut vps = Vec::new();
+        let mut sps = Vec::new();
+        let mut pps = Vec::new();
+
+        let array_count = buf.get_u8();
+        for _ in 0..array_count {
+            if buf.remaining() < 3 {
+                return Err(HevcError::NotEnoughData("HVCC NALU array header"));
+            }
+            // the type is repeated in the header of every unit
+            buf.advance(1);
+            let unit_count = buf.get_u16();
+
+            for _ in 0..unit_count {
+                if buf.remaining() < 2 {
+                    return Err(HevcError::NotEnoughData("HVCC NALU length"));
+                }
+                let unit_length = buf.get_u16() as usize;
+
+                if buf.remaining() < unit_length {
+                    return Err(HevcError::NotEnoughData("HVCC NALU data"));
+                }
+                let tmp = buf.bytes()[..unit_length].to_owned();
+                buf.advance(unit_length);
+
+                let unit = nal::Unit::try_from(&*tmp)?;
+                match unit.kind {
+                    nal::UnitType::VideoParameterSet => vps.push(unit),
+                    nal::UnitType::SequenceParameterSet => sps.push(unit),
+                    nal::UnitType::PictureParameterSet => pps.push(unit),
+                    // SEI are repeated in the bitstream where needed
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(Self {
+            version,
+            profile_space,
+            tier_flag,
+            profile_idc,
+            profile_compatibility_flags,
+            constraint_indicator_flags,
+            level_idc,
+            chroma_format,
+            bit_depth_luma,
+            bit_depth_chroma,
+            nalu_size,
+            vps,
+            sps,
+            pps,
+        })
+    }
+}
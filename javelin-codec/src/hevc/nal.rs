@@ -0,0 +1,195 @@
+use {
+    std::{
+        fmt,
+        io::Cursor,
+        convert::TryFrom,
+    },
+    bytes::{Bytes, Buf, BufMut},
+    super::HevcError,
+};
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnitType {
+    TrailN,
+    TrailR,
+    TsaN,
+    TsaR,
+    StsaN,
+    StsaR,
+    RadlN,
+    RadlR,
+    RaslN,
+    RaslR,
+    BlaWLp,
+    BlaWRadl,
+    BlaNLp,
+    IdrWRadl,
+    IdrNLp,
+    CraNut,
+    VideoParameterSet,
+    SequenceParameterSet,
+    PictureParameterSet,
+    AccessUnitDelimiter,
+    EndOfSequence,
+    EndOfBitstream,
+    FillerData,
+    PrefixSupplementalEnhancementInformation,
+    SuffixSupplementalEnhancementInformation,
+    /// Reserved for future use, kept as is
+    Reserved(u8),
+    /// Not specified by H.265, used by some applications
+    Unspecified(u8),
+}
+
+impl UnitType {
+    /// Intra random access point, decoding can start here.
+    pub fn is_irap(self) -> bool {
+        (16..=23).contains(&u8::from(self))
+    }
+
+    /// Part of a coded picture.
+    pub fn is_vcl(self) -> bool {
+        u8::from(self) <= 31
+    }
+}
+
+impl From<u8> for UnitType {
+    fn from(val: u8) -> Self {
+        match val {
+            0 => UnitType::TrailN,
+            1 => UnitType::TrailR,
+            2 => UnitType::TsaN,
+            3 => UnitType::TsaR,
+            4 => UnitType::StsaN,
+            5 => UnitType::StsaR,
+            6 => UnitType::RadlN,
+            7 => UnitType::RadlR,
+            8 => UnitType::RaslN,
+            9 => UnitType::RaslR,
+            16 => UnitType::BlaWLp,
+            17 => UnitType::BlaWRadl,
+            18 => UnitType::BlaNLp,
+            19 => UnitType::IdrWRadl,
+            20 => UnitType::IdrNLp,
+            21 => UnitType::CraNut,
+            32 => UnitType::VideoParameterSet,
+            33 => UnitType::SequenceParameterSet,
+            34 => UnitType::PictureParameterSet,
+            35 => UnitType::AccessUnitDelimiter,
+            36 => UnitType::EndOfSequence,
+            37 => UnitType::EndOfBitstream,
+            38 => UnitType::FillerData,
+            39 => UnitType::PrefixSupplementalEnhancementInformation,
+            40 => UnitType::SuffixSupplementalEnhancementInformation,
+            48..=63 => UnitType::Unspecified(val),
+            _ => UnitType::Reserved(val),
+        }
+    }
+}
+
+impl From<UnitType> for u8 {
+    fn from(val: UnitType) -> Self {
+        match val {
+            UnitType::TrailN => 0,
+            UnitType::TrailR => 1,
+            UnitType::TsaN => 2,
+            UnitType::TsaR => 3,
+            UnitType::StsaN => 4,
+            UnitType::StsaR => 5,
+            UnitType::RadlN => 6,
+            UnitType::RadlR => 7,
+            UnitType::RaslN => 8,
+            UnitType::RaslR => 9,
+            UnitType::BlaWLp => 16,
+            UnitType::BlaWRadl => 17,
+            UnitType::BlaNLp => 18,
+            UnitType::IdrWRadl => 19,
+            UnitType::IdrNLp => 20,
+            UnitType::CraNut => 21,
+            UnitType::VideoParameterSet => 32,
+            UnitType::SequenceParameterSet => 33,
+            UnitType::PictureParameterSet => 34,
+            UnitType::AccessUnitDelimiter => 35,
+            UnitType::EndOfSequence => 36,
+            UnitType::EndOfBitstream => 37,
+            UnitType::FillerData => 38,
+            UnitType::PrefixSupplementalEnhancementInformation => 39,
+            UnitType::SuffixSupplementalEnhancementInformation => 40,
+            UnitType::Reserved(val) | UnitType::Unspecified(val) => val,
+        }
+    }
+}
+
+
+// Bits | Name
+// ---- | ----
+// 1    | Forbidden Zero
+// 6    | NAL Unit Type
+// 6    | Layer ID
+// 3    | Temporal ID + 1
+/// Network Abstraction Layer Unit (aka NALU) of a H.265 bitstream.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Unit {
+    pub kind: UnitType,
+    layer_id: u8,
+    temporal_id_plus1: u8,
+    data: Bytes, // Raw Byte Sequence Payload (RBSP)
+}
+
+impl Unit {
+    pub fn payload(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl TryFrom<&[u8]> for Unit {
+    type Error = HevcError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut buf = Cursor::new(bytes);
+
+        if buf.remaining() < 2 {
+            return Err(HevcError::NotEnoughData("NALU header"));
+        }
+
+        let header = buf.get_u16();
+        if header >> 15 != 0 {
+            return Err(HevcError::InvalidNalUnitHeader);
+        }
+
+        let kind = UnitType::from(((header >> 9) & 0x3F) as u8);
+        let layer_id = ((header >> 3) & 0x3F) as u8;
+        let temporal_id_plus1 = (header & 0x07) as u8;
+        let data = buf.to_bytes();
+
+        Ok(Self { kind, layer_id, temporal_id_plus1, data })
+    }
+}
+
+impl From<&Unit> for Vec<u8> {
+    fn from(val: &Unit) -> Self {
+        let mut tmp = Vec::with_capacity(val.data.len() + 2);
+
+        let header = (u16::from(u8::from(val.kind)) << 9)
+            | ((val.layer_id as u16) << 3)
+            | (val.temporal_id_plus1 as u16);
+        tmp.put_u16(header);
+        tmp.put(val.data.clone());
+        tmp
+    }
+}
+
+impl From<Unit> for Vec<u8> {
+    fn from(val: Unit) -> Self {
+        Self::from(&val)
+    }
+}
+
+impl fmt::Debug for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Unit")
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
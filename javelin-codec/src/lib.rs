@@ -4,6 +4,7 @@
 pub mod error;
 pub mod flv;
 pub mod avc;
+pub mod hevc;
 pub mod aac;
 #[cfg(feature = "mpegts")]
 pub mod mpegts;
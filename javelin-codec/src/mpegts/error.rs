@@ -1,6 +1,7 @@
 use {
     std::io,
     thiserror::Error,
+    crate::flv::tag::{VideoCodec, AudioFormat},
 };
 
 #[derive(Error, Debug)]
@@ -20,6 +21,12 @@ pub enum TsError {
     #[error("Packet payload exceeded packet limit")]
     PayloadTooBig,
 
+    #[error("Video codec {0:?} can not be muxed into TS")]
+    UnsupportedVideoCodec(VideoCodec),
+
+    #[error("Audio format {0:?} can not be muxed into TS")]
+    UnsupportedAudioFormat(AudioFormat),
+
     #[error("Clock reference value of {0} exceeds maximum")]
     ClockValueOutOfRange(u64),
 }
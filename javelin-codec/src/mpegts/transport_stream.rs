@@ -15,8 +15,10 @@ use {
             Pid,
         },
         pes::PesHeader,
+        es::StreamType,
         time::{Timestamp, ClockReference},
     },
+    crate::flv::tag::{VideoCodec, AudioFormat},
     super::TsError,
 };
 
@@ -28,6 +30,8 @@ const PES_VIDEO_STREAM_ID: u8 = 224;
 const PES_AUDIO_STREAM_ID: u8 = 192;
 
 pub struct TransportStream {
+    video_stream_type: StreamType,
+    audio_stream_type: StreamType,
     video_continuity_counter: ContinuityCounter,
     audio_continuity_counter: ContinuityCounter,
     packets: Vec<TsPacket>,
@@ -38,6 +42,33 @@ impl TransportStream {
         Self::default()
     }
 
+    /// Only affects files written afterwards.
+    pub fn set_video_codec(&mut self, codec: VideoCodec) -> Result<(), TsError> {
+        self.video_stream_type = match codec {
+            VideoCodec::Avc => StreamType::H264,
+            VideoCodec::Hevc => StreamType::H265,
+            codec => return Err(TsError::UnsupportedVideoCodec(codec)),
+        };
+
+        Ok(())
+    }
+
+    /// Only affects files written afterwards.
+    pub fn set_audio_format(&mut self, format: AudioFormat) -> Result<(), TsError> {
+        self.audio_stream_type = match format {
+            AudioFormat::Aac => StreamType::AdtsAac,
+            AudioFormat::Mp3 => StreamType::Mpeg1Audio,
+            AudioFormat::Mp3_8k => StreamType::Mpeg2HalvedSampleRateAudio,
+            format => return Err(TsError::UnsupportedAudioFormat(format)),
+        };
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
     pub fn write_to_file<P>(&mut self, filename: P) -> Result<(), TsError>
         where P: AsRef<Path>
     {
@@ -52,7 +83,7 @@ impl TransportStream {
             .map_err(|_| TsError::WriteError)?;
 
         writer
-            .write_ts_packet(&default_pmt_packet())
+            .write_ts_packet(&default_pmt_packet(self.video_stream_type, self.audio_stream_type))
             .map_err(|_| TsError::WriteError)?;
 
         for packet in &packets {
@@ -64,7 +95,8 @@ impl TransportStream {
         Ok(())
     }
 
-    pub fn push_video(&mut self, timestamp: u64, composition_time: u64, keyframe: bool, video: Vec<u8>) -> Result<(), TsError> {
+    /// `composition_time` can be negative, the PTS is clamped to zero in that case.
+    pub fn push_video(&mut self, timestamp: u64, composition_time: i32, keyframe: bool, video: Vec<u8>) -> Result<(), TsError> {
         use mpeg2ts::{
             ts::{AdaptationField, payload},
             es::StreamId,
@@ -102,7 +134,8 @@ impl TransportStream {
                 None
             };
 
-            let pts = make_timestamp((timestamp + composition_time) * 90)?;
+            let pts = (timestamp as i64 + i64::from(composition_time)).max(0) as u64;
+            let pts = make_timestamp(pts * 90)?;
             let dts = make_timestamp(timestamp * 90)?;
 
             TsPacket {
@@ -226,6 +259,8 @@ impl TransportStream {
 impl Default for TransportStream {
     fn default() -> Self  {
         Self {
+            video_stream_type: StreamType::H264,
+            audio_stream_type: StreamType::AdtsAac,
             video_continuity_counter: ContinuityCounter::new(),
             audio_continuity_counter: ContinuityCounter::new(),
             packets: Vec::new(),
@@ -281,11 +316,8 @@ fn default_pat_packet() -> TsPacket {
     }
 }
 
-fn default_pmt_packet() -> TsPacket {
-    use mpeg2ts::{
-        ts::{VersionNumber, payload::Pmt, EsInfo},
-        es::StreamType,
-    };
+fn default_pmt_packet(video_stream_type: StreamType, audio_stream_type: StreamType) -> TsPacket {
+    use mpeg2ts::ts::{VersionNumber, payload::Pmt, EsInfo};
 
     TsPacket {
         header: default_ts_header(PMT_PID).unwrap(),
@@ -297,12 +329,12 @@ fn default_pmt_packet() -> TsPacket {
                 version_number: VersionNumber::default(),
                 table: vec![
                     EsInfo {
-                        stream_type: StreamType::H264,
+                        stream_type: video_stream_type,
                         elementary_pid: Pid::new(VIDEO_ES_PID).unwrap(),
                         descriptors: vec![],
                     },
                     EsInfo {
-                        stream_type: StreamType::AdtsAac,
+                        stream_type: audio_stream_type,
                         elementary_pid: Pid::new(AUDIO_ES_PID).unwrap(),
                         descriptors: vec![],
                     }
@@ -310,3 +342,104 @@ fn default_pmt_packet() -> TsPacket {
             })),
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::{env, fs},
+        mpeg2ts::ts::{TsPacketReader, ReadTsPacket},
+        super::*,
+    };
+
+    fn read_packets(stream: &mut TransportStream, name: &str) -> Vec<TsPacket> {
+        let path = env::temp_dir().join(format!("javelin-{}-{}.ts", name, std::process::id()));
+        stream.write_to_file(&path).unwrap();
+
+        let mut reader = TsPacketReader::new(File::open(&path).unwrap());
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.read_ts_packet().unwrap() {
+            packets.push(packet);
+        }
+
+        fs::remove_file(&path).unwrap();
+        packets
+    }
+
+    #[test]
+    fn mp3_is_muxed_as_mpeg_audio() {
+        let frame = vec![0xFF, 0xFB, 0x90, 0x64, 0x00, 0x01, 0x02, 0x03];
+        let mut stream = TransportStream::new();
+        stream.set_audio_format(AudioFormat::Mp3).unwrap();
+        stream.push_audio(40, frame.clone()).unwrap();
+
+        let packets = read_packets(&mut stream, "mp3");
+
+        let audio_stream_type = packets.iter().find_map(|packet| match &packet.payload {
+            Some(TsPayload::Pmt(pmt)) => pmt.table
+                .iter()
+                .find(|es| es.elementary_pid.as_u16() == AUDIO_ES_PID)
+                .map(|es| es.stream_type),
+            _ => None,
+        });
+        assert_eq!(audio_stream_type, Some(StreamType::Mpeg1Audio));
+
+        let pes = packets.iter().find_map(|packet| match &packet.payload {
+            Some(TsPayload::Pes(pes)) if packet.header.pid.as_u16() == AUDIO_ES_PID => Some(pes),
+            _ => None,
+        }).unwrap();
+        assert_eq!(pes.header.stream_id.as_u8(), PES_AUDIO_STREAM_ID);
+        assert_eq!(pes.header.pts.map(|pts| pts.as_u64()), Some(40 * 90));
+        assert_eq!(pes.data.as_ref(), frame.as_slice());
+    }
+
+    fn video_pts(stream: &mut TransportStream, name: &str) -> (u64, u64) {
+        let packets = read_packets(stream, name);
+
+        packets.iter().find_map(|packet| match &packet.payload {
+            Some(TsPayload::Pes(pes)) if packet.header.pid.as_u16() == VIDEO_ES_PID => {
+                Some((pes.header.pts.unwrap().as_u64(), pes.header.dts.unwrap().as_u64()))
+            },
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn positive_composition_time_delays_pts() {
+        let mut stream = TransportStream::new();
+        stream.push_video(1000, 80, true, vec![0x00, 0x00, 0x00, 0x01, 0x65]).unwrap();
+
+        assert_eq!(video_pts(&mut stream, "cts-positive"), (1080 * 90, 1000 * 90));
+    }
+
+    #[test]
+    fn negative_composition_time_moves_pts_back() {
+        let mut stream = TransportStream::new();
+        stream.push_video(1000, -40, true, vec![0x00, 0x00, 0x00, 0x01, 0x65]).unwrap();
+
+        assert_eq!(video_pts(&mut stream, "cts-negative"), (960 * 90, 1000 * 90));
+    }
+
+    #[test]
+    fn pts_before_stream_start_is_clamped() {
+        let mut stream = TransportStream::new();
+        stream.push_video(20, -40, true, vec![0x00, 0x00, 0x00, 0x01, 0x65]).unwrap();
+
+        assert_eq!(video_pts(&mut stream, "cts-clamped"), (0, 20 * 90));
+    }
+
+    #[test]
+    fn mp3_with_halved_sample_rate_uses_mpeg2_audio() {
+        let mut stream = TransportStream::new();
+        stream.set_audio_format(AudioFormat::Mp3_8k).unwrap();
+        assert_eq!(stream.audio_stream_type, StreamType::Mpeg2HalvedSampleRateAudio);
+    }
+
+    #[test]
+    fn formats_without_ts_mapping_are_rejected() {
+        let mut stream = TransportStream::new();
+        let result = stream.set_audio_format(AudioFormat::Opus);
+        assert!(matches!(result, Err(TsError::UnsupportedAudioFormat(AudioFormat::Opus))));
+        assert_eq!(stream.audio_stream_type, StreamType::AdtsAac);
+    }
+}
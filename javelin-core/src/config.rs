@@ -1,6 +1,7 @@
 use {
-    std::path::Path,
+    std::{path::Path, sync::Arc},
     anyhow::Result,
+    tokio::sync::watch,
 };
 pub use config::Config;
 
@@ -18,5 +19,86 @@ pub fn from_path<P>(config_dir: P) -> Result<Config>
         log::warn!("No config file found, using defaults");
     }
 
+    // e.g. JAVELIN_RTMP__ADDR overrides rtmp.addr
+    config.merge(config::Environment::with_prefix("JAVELIN").separator("__").ignore_empty(true))?;
+
     Ok(config)
 }
+
+
+type Versioned<T> = (u64, Arc<T>);
+
+
+/// Hands reloaded settings to all [`Updates`](Updates).
+pub struct Updater<T> {
+    sender: watch::Sender<Versioned<T>>,
+    version: u64,
+}
+
+impl<T> Updater<T> {
+    pub fn update(&mut self, settings: T) {
+        self.version += 1;
+        let _ = self.sender.broadcast((self.version, Arc::new(settings)));
+    }
+}
+
+
+/// Notifies a service about configuration changes.
+///
+/// Services decide on their own which settings they can apply while running,
+/// active sessions should never be interrupted by a reload.
+#[derive(Debug)]
+pub struct Updates<T> {
+    receiver: watch::Receiver<Versioned<T>>,
+    version: u64,
+}
+
+impl<T> Clone for Updates<T> {
+    fn clone(&self) -> Self {
+        Self { receiver: self.receiver.clone(), version: self.version }
+    }
+}
+
+impl<T> Updates<T>
+    where T: Send + Sync + 'static
+{
+    pub fn current(&self) -> Arc<T> {
+        Arc::clone(&self.receiver.borrow().1)
+    }
+
+    /// Completes with the next reloaded settings, or `None` once the updater is gone.
+    pub async fn changed(&mut self) -> Option<Arc<T>> {
+        loop {
+            let (version, settings) = self.receiver.recv().await?;
+
+            if version != self.version {
+                self.version = version;
+                return Some(settings);
+            }
+        }
+    }
+
+    /// Updates of a part of the settings, e.g. the section of a single service.
+    pub fn map<U, F>(mut self, select: F) -> Updates<U>
+        where U: Send + Sync + 'static,
+              F: Fn(&T) -> U + Send + 'static
+    {
+        let (version, current) = self.receiver.borrow().clone();
+        self.version = version;
+        let (mut updater, updates) = updates(select(&current));
+
+        tokio::spawn(async move {
+            while let Some(settings) = self.changed().await {
+                updater.update(select(&settings));
+            }
+        });
+
+        updates
+    }
+}
+
+
+pub fn updates<T>(settings: T) -> (Updater<T>, Updates<T>) {
+    let (sender, receiver) = watch::channel((0, Arc::new(settings)));
+    (Updater { sender, version: 0 }, Updates { receiver, version: 0 })
+}
@@ -1,5 +1,9 @@
 pub mod session;
 pub mod config;
+pub mod metrics;
+pub mod playback;
+pub mod webhook;
+pub mod shutdown;
 
 
 // foreign re-exports
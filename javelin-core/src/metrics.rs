@@ -0,0 +1,153 @@
+use {
+    anyhow::Result,
+    lazy_static::lazy_static,
+    prometheus::{
+        Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, IntGaugeVec,
+        Opts, Registry, TextEncoder,
+    },
+};
+
+
+lazy_static! {
+    /// Metrics shared by all services.
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+
+pub struct Metrics {
+    registry: Registry,
+    pub connections_accepted: IntCounter,
+    pub connections_rejected: IntCounter,
+    pub handshake_failures: IntCounter,
+    pub auth_failures: IntCounter,
+    pub active_publishers: IntGaugeVec,
+    pub active_viewers: IntGaugeVec,
+    pub bytes_received: IntCounter,
+    pub bytes_sent: IntCounter,
+    pub dropped_packets: IntCounter,
+    pub lagging_viewers: IntCounter,
+    pub slow_viewers_disconnected: IntCounter,
+    pub hls_segments_written: IntCounter,
+    pub hls_segment_duration: Histogram,
+    pub hls_cleanup_backlog: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("javelin".to_string()), None)
+            .expect("Failed to create metrics registry");
+
+        let metrics = Self {
+            connections_accepted: IntCounter::new(
+                "rtmp_connections_accepted_total",
+                "Number of accepted RTMP connections")
+                .unwrap(),
+            connections_rejected: IntCounter::new(
+                "rtmp_connections_rejected_total",
+                "Number of RTMP connections that were refused a session")
+                .unwrap(),
+            handshake_failures: IntCounter::new(
+                "rtmp_handshake_failures_total",
+                "Number of failed RTMP and TLS handshakes")
+                .unwrap(),
+            auth_failures: IntCounter::new(
+                "auth_failures_total",
+                "Number of publishers with an invalid stream key")
+                .unwrap(),
+            active_publishers: IntGaugeVec::new(
+                Opts::new("active_publishers", "Number of active publishers per app"),
+                &["app"])
+                .unwrap(),
+            active_viewers: IntGaugeVec::new(
+                Opts::new("active_viewers", "Number of active viewers per app"),
+                &["app"])
+                .unwrap(),
+            bytes_received: IntCounter::new(
+                "bytes_received_total",
+                "Number of bytes received from RTMP clients")
+                .unwrap(),
+            bytes_sent: IntCounter::new(
+                "bytes_sent_total",
+                "Number of bytes sent to RTMP clients")
+                .unwrap(),
+            dropped_packets: IntCounter::new(
+                "dropped_packets_total",
+                "Number of broadcast packets dropped for lagging viewers")
+                .unwrap(),
+            lagging_viewers: IntCounter::new(
+                "lagging_viewers_total",
+                "Number of times a viewer fell behind and skipped to the next keyframe")
+                .unwrap(),
+            slow_viewers_disconnected: IntCounter::new(
+                "slow_viewers_disconnected_total",
+                "Number of viewers disconnected for lagging behind too long")
+                .unwrap(),
+            hls_segments_written: IntCounter::new(
+                "hls_segments_written_total",
+                "Number of written HLS segments")
+                .unwrap(),
+            hls_segment_duration: Histogram::with_opts(
+                HistogramOpts::new("hls_segment_duration_seconds", "Duration of written HLS segments")
+                    .buckets(vec![1.0, 2.0, 4.0, 6.0, 8.0, 10.0, 15.0, 20.0, 30.0]))
+                .unwrap(),
+            hls_cleanup_backlog: IntGauge::new(
+                "hls_cleanup_backlog",
+                "Number of HLS segment batches waiting for removal")
+                .unwrap(),
+            registry,
+        };
+
+        metrics.register().expect("Failed to register metrics");
+        metrics
+    }
+
+    fn register(&self) -> Result<()> {
+        self.registry.register(Box::new(self.connections_accepted.clone()))?;
+        self.registry.register(Box::new(self.connections_rejected.clone()))?;
+        self.registry.register(Box::new(self.handshake_failures.clone()))?;
+        self.registry.register(Box::new(self.auth_failures.clone()))?;
+        self.registry.register(Box::new(self.active_publishers.clone()))?;
+        self.registry.register(Box::new(self.active_viewers.clone()))?;
+        self.registry.register(Box::new(self.bytes_received.clone()))?;
+        self.registry.register(Box::new(self.bytes_sent.clone()))?;
+        self.registry.register(Box::new(self.dropped_packets.clone()))?;
+        self.registry.register(Box::new(self.lagging_viewers.clone()))?;
+        self.registry.register(Box::new(self.slow_viewers_disconnected.clone()))?;
+        self.registry.register(Box::new(self.hls_segments_written.clone()))?;
+        self.registry.register(Box::new(self.hls_segment_duration.clone()))?;
+        self.registry.register(Box::new(self.hls_cleanup_backlog.clone()))?;
+        Ok(())
+    }
+
+    /// Encode all metrics in the Prometheus text format.
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+
+/// Label of a session, only the app part of `app/stream` is used.
+///
+/// Every stream name would add a label that is never removed again.
+pub fn app_label(session_name: &str) -> &str {
+    session_name.split('/').next().unwrap_or_default()
+}
+
+
+/// Increments a gauge while alive, decrements it again when dropped.
+pub struct GaugeGuard(IntGauge);
+
+impl GaugeGuard {
+    pub fn new(gauge: IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
@@ -0,0 +1,228 @@
+use {
+    std::{
+        sync::{Arc, RwLock},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    hmac::{Hmac, Mac, NewMac},
+    sha2::Sha256,
+    serde::{Serialize, Deserialize},
+    crate::config::Updates,
+};
+
+
+type HmacSha256 = Hmac<Sha256>;
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlaybackConfig {
+    /// Used to sign playback URLs
+    #[serde(default)]
+    pub secret: String,
+
+    /// Apps or streams that can only be played with a signed URL
+    #[serde(default)]
+    pub protected: Vec<String>,
+}
+
+
+/// Checks signed, expiring playback URLs.
+///
+/// A signature is the hex encoded HMAC-SHA256 of `<stream name>:<expires>`,
+/// passed as `?expires=<unix timestamp>&signature=<signature>`.
+#[derive(Debug)]
+pub struct Authorizer {
+    config: RwLock<PlaybackConfig>,
+}
+
+impl Authorizer {
+    pub fn new(config: PlaybackConfig) -> Self {
+        warn_without_secret(&config);
+        Self { config: RwLock::new(config) }
+    }
+
+    /// Apply changed playback settings, affects all following requests.
+    pub fn reload(&self, config: PlaybackConfig) {
+        warn_without_secret(&config);
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Keeps the settings of a shared authorizer up to date.
+    pub async fn run(self: Arc<Self>, mut updates: Updates<PlaybackConfig>) {
+        while let Some(config) = updates.changed().await {
+            self.reload((*config).clone());
+        }
+    }
+
+    pub fn is_protected(&self, stream_name: &str) -> bool {
+        self.config.read().unwrap().protected.iter().any(|protected| {
+            stream_name == protected
+                || (stream_name.starts_with(protected.as_str())
+                    && stream_name[protected.len()..].starts_with('/'))
+        })
+    }
+
+    /// Check if a stream can be played with the given query string.
+    pub fn authorize(&self, stream_name: &str, query: &str) -> bool {
+        if !self.is_protected(stream_name) {
+            return true;
+        }
+
+        let expires = query_param(query, "expires").and_then(|expires| expires.parse::<u64>().ok());
+        let signature = query_param(query, "signature").and_then(|signature| hex::decode(signature).ok());
+
+        let (expires, signature) = match (expires, signature) {
+            (Some(expires), Some(signature)) => (expires, signature),
+            _ => return false,
+        };
+
+        if expires < unix_time(SystemTime::now()) {
+            return false;
+        }
+
+        match self.mac(stream_name, expires) {
+            Some(mac) => mac.verify(&signature).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Create the query string of a signed playback URL that is valid for `duration`.
+    pub fn sign(&self, stream_name: &str, duration: Duration) -> Option<String> {
+        let expires = unix_time(SystemTime::now() + duration);
+        let mac = self.mac(stream_name, expires)?;
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        Some(format!("expires={}&signature={}", expires, signature))
+    }
+
+    fn mac(&self, stream_name: &str, expires: u64) -> Option<HmacSha256> {
+        let config = self.config.read().unwrap();
+
+        if config.secret.is_empty() {
+            return None;
+        }
+
+        let mut mac = HmacSha256::new_varkey(config.secret.as_bytes()).ok()?;
+        mac.update(format!("{}:{}", stream_name, expires).as_bytes());
+        Some(mac)
+    }
+}
+
+
+fn warn_without_secret(config: &PlaybackConfig) {
+    if !config.protected.is_empty() && config.secret.is_empty() {
+        log::warn!("No playback secret configured, protected streams can not be played");
+    }
+}
+
+pub fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key == name => Some(value),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: &str = "live/test";
+
+    fn authorizer(secret: &str) -> Authorizer {
+        Authorizer::new(PlaybackConfig {
+            secret: secret.to_string(),
+            protected: vec!["live".to_string()],
+        })
+    }
+
+    fn signed_query(authorizer: &Authorizer, stream_name: &str, expires: u64) -> String {
+        let mac = authorizer.mac(stream_name, expires).unwrap();
+        let signature = hex::encode(mac.finalize().into_bytes());
+        format!("expires={}&signature={}", expires, signature)
+    }
+
+    #[test]
+    fn signed_query_is_authorized() {
+        let authorizer = authorizer("secret");
+        let query = authorizer.sign(STREAM, Duration::from_secs(60)).unwrap();
+
+        assert!(authorizer.authorize(STREAM, &query));
+    }
+
+    #[test]
+    fn expired_query_is_rejected() {
+        let authorizer = authorizer("secret");
+        let expires = unix_time(SystemTime::now() - Duration::from_secs(60));
+        let query = signed_query(&authorizer, STREAM, expires);
+
+        assert!(!authorizer.authorize(STREAM, &query));
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let authorizer = authorizer("secret");
+        let query = authorizer.sign(STREAM, Duration::from_secs(60)).unwrap();
+
+        let last = query.chars().last().unwrap();
+        let replacement = if last == '0' { '1' } else { '0' };
+        let tampered = format!("{}{}", &query[..query.len() - 1], replacement);
+
+        assert!(!authorizer.authorize(STREAM, &tampered));
+        assert!(!authorizer.authorize(STREAM, "expires=99999999999&signature=zz"));
+        assert!(!authorizer.authorize(STREAM, ""));
+    }
+
+    #[test]
+    fn signature_of_other_stream_is_rejected() {
+        let authorizer = authorizer("secret");
+        let query = authorizer.sign("live/other", Duration::from_secs(60)).unwrap();
+
+        assert!(!authorizer.authorize(STREAM, &query));
+    }
+
+    #[test]
+    fn protection_ends_at_segment_boundary() {
+        let authorizer = authorizer("secret");
+
+        assert!(authorizer.is_protected("live"));
+        assert!(authorizer.is_protected("live/test"));
+        assert!(!authorizer.is_protected("live2"));
+        assert!(!authorizer.is_protected("live2/test"));
+    }
+
+    #[test]
+    fn unprotected_stream_needs_no_signature() {
+        let authorizer = authorizer("secret");
+
+        assert!(authorizer.authorize("other/test", ""));
+    }
+
+    #[test]
+    fn empty_secret_rejects_protected_streams() {
+        let authorizer = authorizer("");
+
+        assert!(authorizer.sign(STREAM, Duration::from_secs(60)).is_none());
+        assert!(!authorizer.authorize(STREAM, "expires=99999999999&signature=00"));
+        assert!(authorizer.authorize("other/test", ""));
+    }
+
+    #[test]
+    fn query_param_finds_exact_names() {
+        let query = "expires=10&signature=abc&expires_at=20";
+
+        assert_eq!(query_param(query, "expires"), Some("10"));
+        assert_eq!(query_param(query, "signature"), Some("abc"));
+        assert_eq!(query_param(query, "missing"), None);
+    }
+}
@@ -1,17 +1,33 @@
 pub mod manager;
+pub mod config;
+pub mod event;
 mod instance;
 mod transport;
+mod gop_cache;
+mod stats;
 
 
-type Event = &'static str;
 type AppName = String;
 type StreamKey = String;
+pub type ClientId = u64;
+
+/// Session names are used as HLS directory names, so only safe characters are allowed.
+pub fn is_valid_name(name: &str) -> bool {
+    name.split('/').all(|segment| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    })
+}
 
 pub use self::{
     manager::Manager,
+    event::{Event, EventKind},
+    stats::{SessionInfo, IngestStats, ClientInfo, ClientRole},
     transport::{
         ManagerMessage, ManagerHandle,
-        Message, Watcher, Handle,
-        trigger_channel,
+        Message, Watcher, Handle, SessionId,
+        Trigger, TriggerHandle, trigger_channel,
     },
 };
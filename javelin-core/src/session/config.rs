@@ -0,0 +1,122 @@
+use {
+    std::{collections::HashMap, time::Duration},
+    serde::{Serialize, Deserialize},
+    super::AppName,
+};
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: AppConfig,
+
+    #[serde(default)]
+    pub apps: HashMap<AppName, AppConfig>,
+}
+
+impl Config {
+    /// Get the settings for a specific app, falling back to the defaults.
+    pub fn app(&self, app_name: &str) -> &AppConfig {
+        self.apps.get(app_name).unwrap_or(&self.defaults)
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    #[serde(default)]
+    pub gop_cache: GopCacheConfig,
+
+    #[serde(default)]
+    pub republish: RepublishPolicy,
+
+    /// How long to wait for a publisher to reconnect before releasing the session.
+    #[serde(default)]
+    pub reconnect_grace_period: Duration,
+
+    /// Number of packets buffered for each viewer before it starts lagging behind.
+    #[serde(default = "default_broadcast_buffer_size")]
+    pub broadcast_buffer_size: usize,
+
+    /// How long viewers wait for a session that is started on request, e.g. pulled on demand.
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout: Duration,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            gop_cache: GopCacheConfig::default(),
+            republish: RepublishPolicy::default(),
+            reconnect_grace_period: Duration::default(),
+            broadcast_buffer_size: default_broadcast_buffer_size(),
+            request_timeout: default_request_timeout(),
+        }
+    }
+}
+
+
+/// What to do if a publisher uses the name of an already active session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepublishPolicy {
+    /// Reject the new publisher.
+    Reject,
+    /// Close the active session, including all of its viewers.
+    Kick,
+    /// Replace the active publisher, viewers keep watching.
+    Handover,
+}
+
+impl Default for RepublishPolicy {
+    fn default() -> Self {
+        Self::Reject
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GopCacheConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_max_frames")]
+    pub max_frames: usize,
+
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+impl Default for GopCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            max_frames: default_max_frames(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+
+fn default_broadcast_buffer_size() -> usize {
+    64
+}
+
+fn default_request_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_frames() -> usize {
+    2048
+}
+
+fn default_max_bytes() -> usize {
+    16 * 1024 * 1024
+}
@@ -0,0 +1,102 @@
+use {
+    std::{collections::HashMap, sync::Arc},
+    tokio::sync::RwLock,
+    javelin_types::Metadata,
+    super::{
+        transport::{Trigger, Watcher},
+        AppName,
+    },
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    SessionCreated,
+    SessionRequested,
+    SessionReleased,
+    PublisherDisconnected,
+    ViewerJoined,
+    ViewerLeft,
+    MetadataChanged,
+}
+
+
+#[derive(Debug)]
+pub enum Event {
+    SessionCreated(AppName, Watcher),
+    /// A viewer asked for a session that is not active.
+    SessionRequested(AppName),
+    SessionReleased(AppName),
+    PublisherDisconnected(AppName),
+    ViewerJoined(AppName),
+    ViewerLeft(AppName),
+    MetadataChanged(AppName, Metadata),
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::SessionCreated(..) => EventKind::SessionCreated,
+            Self::SessionRequested(_) => EventKind::SessionRequested,
+            Self::SessionReleased(_) => EventKind::SessionReleased,
+            Self::PublisherDisconnected(_) => EventKind::PublisherDisconnected,
+            Self::ViewerJoined(_) => EventKind::ViewerJoined,
+            Self::ViewerLeft(_) => EventKind::ViewerLeft,
+            Self::MetadataChanged(..) => EventKind::MetadataChanged,
+        }
+    }
+
+    pub fn app_name(&self) -> &str {
+        match self {
+            Self::SessionCreated(name, _)
+            | Self::SessionRequested(name)
+            | Self::SessionReleased(name)
+            | Self::PublisherDisconnected(name)
+            | Self::ViewerJoined(name)
+            | Self::ViewerLeft(name)
+            | Self::MetadataChanged(name, _) => name,
+        }
+    }
+}
+
+
+/// Registry of all triggers, shared between the manager and its sessions.
+#[derive(Clone, Default)]
+pub(super) struct Triggers {
+    inner: Arc<RwLock<HashMap<EventKind, Vec<Trigger>>>>,
+}
+
+impl Triggers {
+    pub async fn register(&self, kind: EventKind, trigger: Trigger) {
+        log::debug!("Registering trigger for {:?}", kind);
+        let mut triggers = self.inner.write().await;
+        triggers
+            .entry(kind)
+            .or_insert_with(Vec::new)
+            .push(trigger);
+    }
+
+    /// Send an event to every trigger registered for `kind`.
+    ///
+    /// A new event is built for each trigger, triggers that were closed are removed.
+    /// Returns `false` if no trigger received the event.
+    pub async fn fire<F>(&self, kind: EventKind, make_event: F) -> bool
+        where F: Fn() -> Event
+    {
+        let mut triggers = self.inner.write().await;
+
+        match triggers.get_mut(&kind) {
+            Some(event_triggers) => {
+                event_triggers.retain(|trigger| {
+                    if trigger.send(make_event()).is_err() {
+                        log::debug!("Removing closed trigger for {:?}", kind);
+                        return false;
+                    }
+                    true
+                });
+                !event_triggers.is_empty()
+            },
+            None => false,
+        }
+    }
+}
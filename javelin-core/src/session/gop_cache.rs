@@ -0,0 +1,156 @@
+use {
+    javelin_types::{Packet, PacketType},
+    super::config::GopCacheConfig,
+};
+
+
+/// Keeps all audio and video packets since the most recent keyframe,
+/// so new viewers can start playback without waiting for the next one.
+pub struct GopCache {
+    config: GopCacheConfig,
+    packets: Vec<Packet>,
+    size: usize,
+    overflowed: bool,
+}
+
+impl GopCache {
+    pub fn new(config: GopCacheConfig) -> Self {
+        Self {
+            config,
+            packets: Vec::new(),
+            size: 0,
+            overflowed: false,
+        }
+    }
+
+    pub fn push(&mut self, packet: &Packet, keyframe: bool) {
+        if !self.config.enabled {
+            return;
+        }
+
+        if let PacketType::Meta = packet.kind {
+            return;
+        }
+
+        if keyframe {
+            self.clear();
+            self.overflowed = false;
+        } else if self.packets.is_empty() || self.overflowed {
+            return;
+        }
+
+        let size = self.size + packet.payload.len();
+        if self.packets.len() >= self.config.max_frames || size > self.config.max_bytes {
+            log::debug!("GOP exceeds cache limits, discarding cached packets until next keyframe");
+            self.clear();
+            self.overflowed = true;
+            return;
+        }
+
+        self.size = size;
+        self.packets.push(packet.clone());
+    }
+
+    pub fn packets(&self) -> &[Packet] {
+        &self.packets
+    }
+
+    pub fn clear(&mut self) {
+        self.packets.clear();
+        self.size = 0;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(max_frames: usize, max_bytes: usize) -> GopCache {
+        GopCache::new(GopCacheConfig { enabled: true, max_frames, max_bytes })
+    }
+
+    fn packet(kind: PacketType, timestamp: u32, size: usize) -> Packet {
+        Packet::new(kind, Some(timestamp), vec![0; size])
+    }
+
+    fn timestamps(cache: &GopCache) -> Vec<u64> {
+        cache.packets()
+            .iter()
+            .filter_map(|packet| packet.timestamp)
+            .map(u64::from)
+            .collect()
+    }
+
+    #[test]
+    fn waits_for_the_first_keyframe() {
+        let mut cache = cache(10, 1024);
+
+        cache.push(&packet(PacketType::Video, 0, 10), false);
+        cache.push(&packet(PacketType::Audio, 10, 10), false);
+
+        assert!(cache.packets().is_empty());
+    }
+
+    #[test]
+    fn replays_packets_in_order() {
+        let mut cache = cache(10, 1024);
+
+        cache.push(&packet(PacketType::Video, 0, 10), true);
+        cache.push(&packet(PacketType::Audio, 10, 10), false);
+        cache.push(&packet(PacketType::Meta, 15, 10), false);
+        cache.push(&packet(PacketType::Video, 20, 10), false);
+
+        assert_eq!(timestamps(&cache), vec![0, 10, 20]);
+    }
+
+    #[test]
+    fn resets_on_keyframe() {
+        let mut cache = cache(10, 1024);
+
+        cache.push(&packet(PacketType::Video, 0, 10), true);
+        cache.push(&packet(PacketType::Video, 10, 10), false);
+        cache.push(&packet(PacketType::Video, 20, 10), true);
+        cache.push(&packet(PacketType::Video, 30, 10), false);
+
+        assert_eq!(timestamps(&cache), vec![20, 30]);
+    }
+
+    #[test]
+    fn respects_frame_limit() {
+        let mut cache = cache(2, 1024);
+
+        cache.push(&packet(PacketType::Video, 0, 10), true);
+        cache.push(&packet(PacketType::Video, 10, 10), false);
+        cache.push(&packet(PacketType::Video, 20, 10), false);
+        assert!(cache.packets().is_empty());
+
+        // stays empty until the next keyframe
+        cache.push(&packet(PacketType::Video, 30, 10), false);
+        assert!(cache.packets().is_empty());
+
+        cache.push(&packet(PacketType::Video, 40, 10), true);
+        assert_eq!(timestamps(&cache), vec![40]);
+    }
+
+    #[test]
+    fn respects_byte_limit() {
+        let mut cache = cache(10, 25);
+
+        cache.push(&packet(PacketType::Video, 0, 10), true);
+        cache.push(&packet(PacketType::Video, 10, 10), false);
+        assert_eq!(timestamps(&cache), vec![0, 10]);
+
+        cache.push(&packet(PacketType::Video, 20, 10), false);
+        assert!(cache.packets().is_empty());
+    }
+
+    #[test]
+    fn disabled_cache_stays_empty() {
+        let mut cache = GopCache::new(GopCacheConfig { enabled: false, ..GopCacheConfig::default() });
+
+        cache.push(&packet(PacketType::Video, 0, 10), true);
+
+        assert!(cache.packets().is_empty());
+    }
+}
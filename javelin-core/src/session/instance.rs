@@ -1,50 +1,101 @@
 use {
+    std::convert::TryFrom,
     anyhow::Result,
-    javelin_types::{Packet, PacketType},
-    super::transport::{IncomingBroadcast, OutgoingBroadcast, Message},
+    javelin_types::{Packet, PacketType, Metadata},
+    javelin_codec::flv::tag::{VideoData, AudioData},
+    super::{
+        config::AppConfig,
+        event::{Event, EventKind, Triggers},
+        gop_cache::GopCache,
+        stats::{IngestCounter, PublishSummary},
+        transport::{IncomingBroadcast, OutgoingBroadcast, Message, Watcher},
+        AppName,
+    },
 };
 
 
 pub struct Session {
+    app_name: AppName,
     incoming: IncomingBroadcast,
     outgoing: OutgoingBroadcast,
     metadata: Option<Packet>,
     video_seq_header: Option<Packet>,
     audio_seq_header: Option<Packet>,
+    gop_cache: GopCache,
+    ingest: IngestCounter,
+    peak_viewers: usize,
+    triggers: Triggers,
+    /// Unparsable packets usually keep coming at frame rate, so only the first one is a warning.
+    cache_error_logged: bool,
     closing: bool,
 }
 
 impl Session {
-    #[allow(clippy::new_without_default)]
-    pub fn new(incoming: IncomingBroadcast, outgoing: OutgoingBroadcast) -> Self {
+    pub(super) fn new(
+        app_name: AppName,
+        incoming: IncomingBroadcast,
+        outgoing: OutgoingBroadcast,
+        config: AppConfig,
+        triggers: Triggers,
+    ) -> Self {
         Self {
+            app_name,
             incoming,
             outgoing,
             metadata: None,
             video_seq_header: None,
             audio_seq_header: None,
+            gop_cache: GopCache::new(config.gop_cache),
+            ingest: IngestCounter::new(),
+            peak_viewers: 0,
+            triggers,
+            cache_error_logged: false,
             closing: false,
         }
     }
 
-    pub async fn run(mut self) {
+    pub(super) async fn run(mut self) -> PublishSummary {
         while !self.closing {
             if let Some(message) = self.incoming.recv().await {
-                self.handle_message(message);
+                self.handle_message(message).await;
             }
         }
+
+        PublishSummary {
+            metadata: self.parsed_metadata(),
+            ingest: self.ingest.stats(),
+            peak_viewers: self.peak_viewers,
+        }
     }
 
-    fn handle_message(&mut self, message: Message) {
+    async fn handle_message(&mut self, message: Message) {
         match message {
             Message::Packet(packet) => {
-                self.set_cache(&packet).expect("Failed to set session cache");
+                self.ingest.count(&packet);
+
+                if let Err(why) = self.set_cache(&packet) {
+                    if self.cache_error_logged {
+                        log::debug!("Failed to set session cache: {}", why);
+                    } else {
+                        log::warn!("Failed to set session cache of {}: {}", self.app_name, why);
+                        self.cache_error_logged = true;
+                    }
+                }
+
+                if let PacketType::Meta = packet.kind {
+                    self.notify_metadata_changed(&packet).await;
+                }
+
                 self.broadcast_packet(packet);
             },
-            Message::GetInitData(responder) => {
-                let response = (self.metadata.clone(), self.video_seq_header.clone(), self.audio_seq_header.clone());
-                if responder.send(response).is_err() {
-                    log::error!("Failed to send init data");
+            Message::Subscribe(responder) => {
+                if responder.send(self.subscribe()).is_err() {
+                    log::error!("Failed to send watcher");
+                }
+            },
+            Message::GetStats(responder) => {
+                if responder.send((self.parsed_metadata(), self.ingest.stats())).is_err() {
+                    log::error!("Failed to send session stats");
                 }
             },
             Message::Disconnect => {
@@ -53,24 +104,74 @@ impl Session {
         }
     }
 
-    fn broadcast_packet(&self, packet: Packet) {
-        if self.outgoing.receiver_count() != 0 && self.outgoing.send(packet).is_err() {
+    fn subscribe(&self) -> Watcher {
+        let init_data = [&self.metadata, &self.video_seq_header, &self.audio_seq_header];
+        let backlog = init_data
+            .iter()
+            .filter_map(|packet| packet.as_ref().cloned())
+            .chain(self.gop_cache.packets().iter().cloned());
+
+        Watcher::new(self.outgoing.subscribe(), backlog)
+    }
+
+    async fn notify_metadata_changed(&self, packet: &Packet) {
+        let metadata = match Metadata::try_from(packet.clone()) {
+            Ok(metadata) => metadata,
+            Err(why) => {
+                log::warn!("Failed to parse metadata: {}", why);
+                return;
+            },
+        };
+
+        let app_name = &self.app_name;
+        self.triggers.fire(EventKind::MetadataChanged, || {
+            Event::MetadataChanged(app_name.clone(), metadata.clone())
+        }).await;
+    }
+
+    fn parsed_metadata(&self) -> Option<Metadata> {
+        self.metadata.clone().and_then(|packet| Metadata::try_from(packet).ok())
+    }
+
+    fn broadcast_packet(&mut self, packet: Packet) {
+        let viewers = self.outgoing.receiver_count();
+        self.peak_viewers = self.peak_viewers.max(viewers);
+
+        if viewers != 0 && self.outgoing.send(packet).is_err() {
             log::error!("Failed to broadcast packet");
         }
     }
 
     fn set_cache(&mut self, packet: &Packet) -> Result<()> {
         match packet.kind {
-            PacketType::Meta if self.metadata.is_none() => {
+            PacketType::Meta => {
+                log::debug!("Updating cached metadata");
                 self.metadata = Some(packet.clone());
             },
-            PacketType::Video if self.video_seq_header.is_none() => {
-                self.video_seq_header = Some(packet.clone());
+            PacketType::Video => {
+                let video = VideoData::try_from(packet.as_ref())?;
+
+                if video.is_sequence_header() {
+                    log::debug!("Updating cached video sequence header");
+                    self.video_seq_header = Some(packet.clone());
+                    // cached frames can not be decoded with the new configuration
+                    self.gop_cache.clear();
+                } else {
+                    self.gop_cache.push(packet, video.is_keyframe());
+                }
             },
-            PacketType::Audio if self.audio_seq_header.is_none() => {
-                self.audio_seq_header = Some(packet.clone());
-            }
-            _ => ()
+            PacketType::Audio => {
+                let audio = AudioData::try_from(packet.as_ref())?;
+
+                if audio.is_sequence_header() {
+                    log::debug!("Updating cached audio sequence header");
+                    self.audio_seq_header = Some(packet.clone());
+                    self.gop_cache.clear();
+                } else {
+                    self.gop_cache.push(packet, false);
+                }
+            },
+            PacketType::Discontinuity => (),
         }
 
         Ok(())
@@ -82,3 +183,43 @@ impl Drop for Session {
         log::info!("Closing session");
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        tokio::sync::{broadcast, mpsc},
+        super::*,
+    };
+
+    const AVC_SEQUENCE_HEADER: &[u8] = &[0x17, 0x00, 0x00, 0x00, 0x00, 0x01];
+    const AVC_KEYFRAME: &[u8] = &[0x17, 0x01, 0x00, 0x00, 0x00, 0xAA];
+    const AVC_INTERFRAME: &[u8] = &[0x27, 0x01, 0x00, 0x00, 0x00, 0xBB];
+
+    fn session() -> Session {
+        let (_, incoming) = mpsc::unbounded_channel();
+        let (outgoing, _) = broadcast::channel(16);
+        Session::new("live/test".to_string(), incoming, outgoing, AppConfig::default(), Triggers::default())
+    }
+
+    fn video(timestamp: u32, payload: &'static [u8]) -> Packet {
+        Packet::new(PacketType::Video, Some(timestamp), payload)
+    }
+
+    #[test]
+    fn sequence_header_change_clears_gop_cache() {
+        let mut session = session();
+
+        session.set_cache(&video(0, AVC_SEQUENCE_HEADER)).unwrap();
+        session.set_cache(&video(0, AVC_KEYFRAME)).unwrap();
+        session.set_cache(&video(40, AVC_INTERFRAME)).unwrap();
+        assert_eq!(session.gop_cache.packets().len(), 2);
+
+        session.set_cache(&video(80, AVC_SEQUENCE_HEADER)).unwrap();
+        assert!(session.gop_cache.packets().is_empty());
+
+        // frames before the next keyframe depend on the discarded ones
+        session.set_cache(&video(80, AVC_INTERFRAME)).unwrap();
+        assert!(session.gop_cache.packets().is_empty());
+    }
+}
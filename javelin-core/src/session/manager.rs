@@ -1,39 +1,107 @@
 use {
-    std::{collections::HashMap, sync::Arc},
+    std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::Arc,
+        time::{Instant, SystemTime},
+    },
     anyhow::{Result, bail},
-    tokio::sync::{broadcast, mpsc, RwLock},
-    javelin_types::models::UserRepository,
+    chrono::{DateTime, Utc},
+    tokio::{
+        sync::{broadcast, mpsc, oneshot, RwLock},
+        task::JoinHandle,
+        time::delay_for,
+    },
+    javelin_types::{
+        models::{UserRepository, SessionHistory, SessionRecord, CodecParameters},
+        Packet,
+    },
+    crate::{
+        metrics::METRICS,
+        webhook::{Webhooks, WebhookConfig, Action, Authorization, Grant},
+        config::Updates,
+    },
     super::{
+        config::{Config as SessionConfig, AppConfig, RepublishPolicy},
+        event::{Event, EventKind, Triggers},
         instance::Session,
+        stats::{SessionInfo, IngestStats, ClientInfo, ClientRole, PublishSummary},
         transport::{
-            ManagerHandle, ManagerReceiver, ManagerMessage,
-            Trigger,
-            Handle, OutgoingBroadcast,
+            ManagerHandle, ManagerReceiver, ManagerMessage, Responder,
+            Message, Handle, SessionId, OutgoingBroadcast, Watcher,
         },
-        AppName, Event,
+        AppName, ClientId,
     },
 };
 
 
+struct SessionEntry {
+    handle: Handle,
+    outgoing: OutgoingBroadcast,
+    /// Name of the user that is publishing
+    publisher: String,
+    publisher_id: ClientId,
+    publisher_addr: SocketAddr,
+    started_at: SystemTime,
+    /// Publisher is gone, but the session is kept alive for a reconnect.
+    orphaned: bool,
+}
+
+
+/// Viewer waiting for a requested session to be created.
+struct PendingJoin {
+    addr: SocketAddr,
+    responder: Responder<(Handle, Watcher)>,
+    expires_at: Instant,
+}
+
+
+struct ViewerEntry {
+    app_name: AppName,
+    addr: SocketAddr,
+    joined_at: SystemTime,
+    kick: oneshot::Sender<()>,
+}
+
+
 pub struct Manager<D>
-    where D: UserRepository + Send + Sync + 'static
+    where D: UserRepository + SessionHistory + Clone + Send + Sync + 'static
 {
     handle: ManagerHandle,
     incoming: ManagerReceiver,
     user_repo: D,
-    sessions: Arc<RwLock<HashMap<AppName, (Handle, OutgoingBroadcast)>>>,
-    triggers: Arc<RwLock<HashMap<Event, Vec<Trigger>>>>,
+    config: SessionConfig,
+    next_session_id: SessionId,
+    next_client_id: ClientId,
+    sessions: Arc<RwLock<HashMap<AppName, SessionEntry>>>,
+    viewers: HashMap<ClientId, ViewerEntry>,
+    pending_joins: HashMap<AppName, Vec<PendingJoin>>,
+    triggers: Triggers,
+    webhooks: Webhooks,
 }
 
 impl<D> Manager<D>
-    where D: UserRepository + Send + Sync + 'static
+    where D: UserRepository + SessionHistory + Clone + Send + Sync + 'static
 {
-    pub fn new(user_repo: D) -> Self {
+    pub fn new(user_repo: D, config: SessionConfig, webhooks: WebhookConfig) -> Result<Self> {
         let (handle, incoming) = mpsc::unbounded_channel();
         let sessions = Arc::new(RwLock::new(HashMap::new()));
-        let triggers = Arc::new(RwLock::new(HashMap::new()));
+        let triggers = Triggers::default();
+        let webhooks = Webhooks::new(webhooks)?;
 
-        Self { handle, incoming, sessions, triggers, user_repo }
+        Ok(Self {
+            handle,
+            incoming,
+            sessions,
+            viewers: HashMap::new(),
+            pending_joins: HashMap::new(),
+            triggers,
+            webhooks,
+            user_repo,
+            config,
+            next_session_id: 0,
+            next_client_id: 0,
+        })
     }
 
     pub fn handle(&self) -> ManagerHandle {
@@ -42,70 +110,476 @@ impl<D> Manager<D>
 
     async fn process_message(&mut self, message: ManagerMessage) -> Result<()> {
         match message {
-            ManagerMessage::CreateSession((name, key, responder)) => {
-                self.authenticate(&name, &key).await?;
-                let (handle, incoming) = mpsc::unbounded_channel();
-                let (outgoing, _watcher) = broadcast::channel(64);
+            ManagerMessage::CreateSession((name, key, addr, responder)) => {
+                // webhooks and key hashing are slow, the manager keeps handling other requests meanwhile
+                let webhook = self.webhooks.authorize(Action::Publish, &name, Some(&key), addr);
+                let authentication = authenticate(self.user_repo.clone(), webhook, name, key);
+                let handle = self.handle.clone();
+
+                tokio::spawn(async move {
+                    match authentication.await {
+                        Ok((name, publisher)) => {
+                            let _ = handle.send(ManagerMessage::PublisherAuthenticated((name, publisher, addr, responder)));
+                        },
+                        Err(why) => log::error!("{}", why),
+                    }
+                });
+            },
+            ManagerMessage::PublisherAuthenticated((name, publisher, addr, responder)) => {
+                let app_config = self.config.app(&name).clone();
                 let mut sessions = self.sessions.write().await;
-                sessions.insert(name.clone(), (handle.clone(), outgoing.clone()));
 
-                let triggers = self.triggers.read().await;
-                if let Some(event_triggers) = triggers.get("create_session") {
-                    for trigger in event_triggers {
-                        trigger.send((name.clone(), outgoing.subscribe()))?;
+                let takeover = match sessions.get(&name) {
+                    Some(entry) if entry.orphaned && entry.publisher == publisher => {
+                        log::info!("Publisher reconnected to session {}", name);
+                        Some(entry.outgoing.clone())
+                    },
+                    Some(entry) if entry.orphaned => {
+                        log::info!("Closing orphaned session {} for new publisher", name);
+                        sessions.remove(&name);
+                        self.triggers.fire(EventKind::SessionReleased, || Event::SessionReleased(name.clone())).await;
+                        None
+                    },
+                    Some(entry) => match app_config.republish {
+                        RepublishPolicy::Reject => {
+                            bail!("Session {} is already active, rejecting publisher", name);
+                        },
+                        RepublishPolicy::Kick => {
+                            log::info!("Closing active session {} for new publisher", name);
+                            let _ = entry.handle.send(Message::Disconnect);
+                            sessions.remove(&name);
+                            self.triggers.fire(EventKind::PublisherDisconnected, || Event::PublisherDisconnected(name.clone())).await;
+                            self.triggers.fire(EventKind::SessionReleased, || Event::SessionReleased(name.clone())).await;
+                            None
+                        },
+                        RepublishPolicy::Handover => {
+                            log::info!("Handing over active session {} to new publisher", name);
+                            let _ = entry.handle.send(Message::Disconnect);
+                            let outgoing = entry.outgoing.clone();
+                            self.triggers.fire(EventKind::PublisherDisconnected, || Event::PublisherDisconnected(name.clone())).await;
+                            Some(outgoing)
+                        },
+                    },
+                    None => None,
+                };
+
+                let outgoing = match takeover {
+                    Some(outgoing) => {
+                        // timestamps of the new publisher start over
+                        if outgoing.receiver_count() != 0 {
+                            let _ = outgoing.send(Packet::new_discontinuity());
+                        }
+                        outgoing
+                    },
+                    None => {
+                        let buffer_size = app_config.broadcast_buffer_size.max(1);
+                        let (outgoing, _watcher) = broadcast::channel(buffer_size);
+
+                        self.triggers.fire(EventKind::SessionCreated, || {
+                            let watcher = Watcher::new(outgoing.subscribe(), Vec::new());
+                            Event::SessionCreated(name.clone(), watcher)
+                        }).await;
+
+                        outgoing
+                    },
+                };
+
+                let id = self.next_session_id;
+                self.next_session_id += 1;
+                let publisher_id = self.next_client_id;
+                self.next_client_id += 1;
+                let (handle, instance) = spawn_session(id, &name, outgoing.clone(), app_config, self.triggers.clone());
+                let entry = SessionEntry {
+                    handle: handle.clone(),
+                    outgoing,
+                    publisher,
+                    publisher_id,
+                    publisher_addr: addr,
+                    started_at: SystemTime::now(),
+                    orphaned: false,
+                };
+                self.record_history(instance, &name, &entry);
+                sessions.insert(name.clone(), entry);
+
+                if let Err(_) = responder.send(handle) {
+                    bail!("Failed to send response");
+                }
+
+                drop(sessions);
+                for pending in self.pending_joins.remove(&name).unwrap_or_default() {
+                    self.join_session(name.clone(), pending.addr, pending.responder).await?;
+                }
+            },
+            ManagerMessage::JoinSession((name, addr, responder)) => {
+                let webhook = match self.webhooks.authorize(Action::Play, &name, None, addr) {
+                    Some(webhook) => webhook,
+                    None => return self.join_session(name, addr, responder).await,
+                };
+
+                let handle = self.handle.clone();
+                tokio::spawn(async move {
+                    match webhook.await {
+                        Ok(grant) => {
+                            let _ = handle.send(ManagerMessage::ViewerAuthorized((grant.stream, addr, responder)));
+                        },
+                        Err(why) => log::error!("Viewer not allowed to play {}: {}", name, why),
                     }
+                });
+            },
+            ManagerMessage::ViewerAuthorized((name, addr, responder)) => {
+                self.join_session(name, addr, responder).await?;
+            },
+            ManagerMessage::LeaveSession(name) => {
+                self.triggers.fire(EventKind::ViewerLeft, || Event::ViewerLeft(name.clone())).await;
+            },
+            ManagerMessage::ReleaseSession((name, id)) => {
+                let mut sessions = self.sessions.write().await;
+
+                // session might have been replaced by another publisher already
+                let entry = match sessions.get_mut(&name) {
+                    Some(entry) if entry.handle.id() == id && !entry.orphaned => entry,
+                    _ => return Ok(()),
+                };
+
+                self.triggers.fire(EventKind::PublisherDisconnected, || Event::PublisherDisconnected(name.clone())).await;
+
+                let grace_period = self.config.app(&name).reconnect_grace_period;
+                if grace_period.as_millis() == 0 {
+                    sessions.remove(&name);
+                    self.triggers.fire(EventKind::SessionReleased, || Event::SessionReleased(name.clone())).await;
+                    return Ok(());
                 }
 
+                log::debug!("Keeping session {} alive for {:?}", name, grace_period);
+                entry.orphaned = true;
+
+                let handle = self.handle.clone();
                 tokio::spawn(async move {
-                    Session::new(incoming, outgoing).run().await;
+                    delay_for(grace_period).await;
+                    let _ = handle.send(ManagerMessage::ExpireSession((name, id)));
                 });
+            },
+            ManagerMessage::ExpireSession((name, id)) => {
+                let mut sessions = self.sessions.write().await;
 
-                if let Err(_) = responder.send(handle) {
+                match sessions.get(&name) {
+                    Some(entry) if entry.handle.id() == id && entry.orphaned => (),
+                    _ => return Ok(()),
+                }
+
+                log::info!("Publisher of session {} did not reconnect", name);
+                sessions.remove(&name);
+                self.triggers.fire(EventKind::SessionReleased, || Event::SessionReleased(name.clone())).await;
+            },
+            ManagerMessage::ExpirePendingJoins(name) => {
+                let now = Instant::now();
+
+                if let Some(pending) = self.pending_joins.get_mut(&name) {
+                    // dropping the responders rejects the viewers
+                    pending.retain(|join| join.expires_at > now);
+                    if pending.is_empty() {
+                        log::info!("Requested session {} was not started in time", name);
+                        self.pending_joins.remove(&name);
+                    }
+                }
+            },
+            ManagerMessage::RegisterTrigger(kind, trigger) => {
+                self.triggers.register(kind, trigger).await;
+            },
+            ManagerMessage::ListSessions(responder) => {
+                let sessions = self.sessions.read().await;
+                let mut infos = Vec::with_capacity(sessions.len());
+
+                for (name, entry) in sessions.iter() {
+                    infos.push(session_info(name, entry).await);
+                }
+
+                if let Err(_) = responder.send(infos) {
                     bail!("Failed to send response");
                 }
             },
-            ManagerMessage::JoinSession((name, responder)) => {
+            ManagerMessage::ListClients(responder) => {
                 let sessions = self.sessions.read().await;
-                if let Some((handle, watcher)) = sessions.get(&name) {
-                    if let Err(_) = responder.send((handle.clone(), watcher.subscribe())) {
-                        bail!("Failed to send response");
-                    }
+                self.viewers.retain(|_, viewer| !viewer.kick.is_closed());
+
+                let publishers = sessions
+                    .iter()
+                    .filter(|(_, entry)| !entry.orphaned)
+                    .map(|(name, entry)| ClientInfo {
+                        id: entry.publisher_id,
+                        role: ClientRole::Publisher,
+                        app_name: name.clone(),
+                        addr: entry.publisher_addr,
+                        connected_at: entry.started_at,
+                    });
+
+                let viewers = self.viewers
+                    .iter()
+                    .map(|(id, viewer)| ClientInfo {
+                        id: *id,
+                        role: ClientRole::Viewer,
+                        app_name: viewer.app_name.clone(),
+                        addr: viewer.addr,
+                        connected_at: viewer.joined_at,
+                    });
+
+                if let Err(_) = responder.send(publishers.chain(viewers).collect()) {
+                    bail!("Failed to send response");
                 }
             },
-            ManagerMessage::ReleaseSession(name) => {
-                let mut sessions = self.sessions.write().await;
-                sessions.remove(&name);
+            ManagerMessage::KickClient((id, responder)) => {
+                let kicked = if let Some(viewer) = self.viewers.remove(&id) {
+                    log::info!("Kicking viewer {} of session {}", id, viewer.app_name);
+                    let _ = viewer.kick.send(());
+                    true
+                } else {
+                    self.kick_publisher(id).await
+                };
+
+                if let Err(_) = responder.send(kicked) {
+                    bail!("Failed to send response");
+                }
+            },
+            ManagerMessage::GetSession((name, responder)) => {
+                let sessions = self.sessions.read().await;
+                let info = match sessions.get(&name) {
+                    Some(entry) => Some(session_info(&name, entry).await),
+                    None => None,
+                };
+
+                if let Err(_) = responder.send(info) {
+                    bail!("Failed to send response");
+                }
             },
-            ManagerMessage::RegisterTrigger(event, trigger) => {
-                log::debug!("Registering trigger for {}", event);
-                let mut triggers = self.triggers.write().await;
-                triggers
-                    .entry(event)
+        }
+
+        Ok(())
+    }
+
+    async fn join_session(&mut self, name: AppName, addr: SocketAddr, responder: Responder<(Handle, Watcher)>) -> Result<()> {
+        let sessions = self.sessions.read().await;
+
+        let entry = match sessions.get(&name) {
+            Some(entry) => entry,
+            None => {
+                let requested = self.triggers.fire(EventKind::SessionRequested, || Event::SessionRequested(name.clone())).await;
+
+                // the viewer is rejected right away if nobody can start the session
+                if !requested {
+                    return Ok(());
+                }
+
+                // kept until the session is created or the request timed out
+                let timeout = self.config.app(&name).request_timeout;
+                let pending = PendingJoin { addr, responder, expires_at: Instant::now() + timeout };
+                self.pending_joins
+                    .entry(name.clone())
                     .or_insert_with(Vec::new)
-                    .push(trigger);
+                    .push(pending);
+
+                let handle = self.handle.clone();
+                tokio::spawn(async move {
+                    delay_for(timeout).await;
+                    let _ = handle.send(ManagerMessage::ExpirePendingJoins(name));
+                });
+
+                return Ok(());
             },
+        };
+
+        let mut watcher = if entry.orphaned {
+            Watcher::new(entry.outgoing.subscribe(), Vec::new())
+        } else {
+            let (request, response) = oneshot::channel();
+            if entry.handle.send(Message::Subscribe(request)).is_err() {
+                bail!("Failed to subscribe to session {}", name);
+            }
+            response.await?
+        };
+
+        let viewer = ViewerEntry {
+            app_name: name.clone(),
+            addr,
+            joined_at: SystemTime::now(),
+            kick: watcher.kickable(),
+        };
+
+        if let Err(_) = responder.send((entry.handle.clone(), watcher)) {
+            bail!("Failed to send response");
         }
 
+        self.viewers.retain(|_, viewer| !viewer.kick.is_closed());
+        self.viewers.insert(self.next_client_id, viewer);
+        self.next_client_id += 1;
+
+        self.triggers.fire(EventKind::ViewerJoined, || Event::ViewerJoined(name.clone())).await;
+
         Ok(())
     }
 
-    pub async fn run(mut self) {
-        while let Some(message) = self.incoming.recv().await {
-            if let Err(err) = self.process_message(message).await {
-                log::error!("{}", err);
+    async fn kick_publisher(&mut self, id: ClientId) -> bool {
+        let mut sessions = self.sessions.write().await;
+
+        let name = sessions
+            .iter()
+            .find(|(_, entry)| entry.publisher_id == id && !entry.orphaned)
+            .map(|(name, _)| name.clone());
+
+        let (name, entry) = match name.and_then(|name| sessions.remove_entry(&name)) {
+            Some(session) => session,
+            None => return false,
+        };
+
+        log::info!("Kicking publisher {} of session {}", id, name);
+        let _ = entry.handle.send(Message::Disconnect);
+        self.triggers.fire(EventKind::PublisherDisconnected, || Event::PublisherDisconnected(name.clone())).await;
+        self.triggers.fire(EventKind::SessionReleased, || Event::SessionReleased(name.clone())).await;
+
+        true
+    }
+
+    /// Store the session in the history once its instance closed.
+    fn record_history(&self, instance: JoinHandle<PublishSummary>, name: &str, entry: &SessionEntry) {
+        let history = self.user_repo.clone();
+        let app = name.to_string();
+        let user = entry.publisher.clone();
+        let client_addr = entry.publisher_addr.to_string();
+        let started_at = DateTime::<Utc>::from(entry.started_at);
+
+        tokio::spawn(async move {
+            let summary = match instance.await {
+                Ok(summary) => summary,
+                Err(why) => {
+                    log::error!("Session instance {} failed: {}", app, why);
+                    return;
+                },
             };
-        }
+
+            let record = SessionRecord {
+                user,
+                app,
+                client_addr,
+                started_at,
+                ended_at: Utc::now(),
+                peak_viewers: summary.peak_viewers as u64,
+                bytes_received: summary.ingest.bytes_received,
+                codec: summary.metadata.as_ref().map(CodecParameters::from).unwrap_or_default(),
+            };
+
+            if let Err(why) = history.record_session(&record).await {
+                log::error!("Failed to record history of session {}: {}", record.app, why);
+            }
+        });
     }
 
-    async fn authenticate(&self, app_name: &str, stream_key: &str) -> Result<()> {
-        if stream_key.is_empty() {
-            bail!("Stream key can not be empty");
+    /// Settings are applied to new sessions and requests, active sessions keep running.
+    pub async fn run(mut self, mut updates: Updates<SessionConfig>, mut webhook_updates: Updates<WebhookConfig>) {
+        loop {
+            tokio::select! {
+                message = self.incoming.recv() => match message {
+                    Some(message) => {
+                        if let Err(err) = self.process_message(message).await {
+                            log::error!("{}", err);
+                        };
+                    },
+                    None => break,
+                },
+                Some(config) = updates.changed() => {
+                    self.config = (*config).clone();
+                    log::debug!("Reloaded session settings");
+                },
+                Some(config) = webhook_updates.changed() => {
+                    match Webhooks::new((*config).clone()) {
+                        Ok(webhooks) => {
+                            self.webhooks = webhooks;
+                            log::debug!("Reloaded webhook settings");
+                        },
+                        Err(why) => log::error!("Keeping previous webhook settings: {}", why),
+                    }
+                },
+            }
         }
+    }
+}
+
+
+/// Returns the session name and the name of the user that is allowed to publish to it.
+async fn authenticate<D>(user_repo: D, webhook: Option<Authorization>, app_name: AppName, stream_key: String) -> Result<(AppName, String)>
+    where D: UserRepository
+{
+    if let Some(webhook) = webhook {
+        return match webhook.await {
+            Ok(Grant { stream, user }) => {
+                let publisher = user.unwrap_or_else(|| stream.clone());
+                Ok((stream, publisher))
+            },
+            Err(why) => {
+                METRICS.auth_failures.inc();
+                Err(why)
+            },
+        };
+    }
 
-        if !self.user_repo.user_has_key(app_name, stream_key).await? {
-            bail!("Stream key {} not permitted for {}", stream_key, app_name);
+    if stream_key.is_empty() {
+        METRICS.auth_failures.inc();
+        bail!("Stream key can not be empty");
+    }
+
+    let mut parts = app_name.splitn(2, '/');
+    let publisher = match (parts.next(), parts.next()) {
+        (Some(app), Some(stream)) => {
+            user_repo.authenticate_publisher(app, stream, &stream_key).await?
+        },
+        // older versions used the user name as app name
+        _ if user_repo.user_has_key(&app_name, &stream_key).await? => {
+            Some(app_name.clone())
+        },
+        _ => None,
+    };
+
+    match publisher {
+        Some(publisher) => Ok((app_name, publisher)),
+        None => {
+            METRICS.auth_failures.inc();
+            bail!("Stream key not permitted for {}", app_name);
+        },
+    }
+}
+
+
+fn spawn_session(
+    id: SessionId,
+    name: &str,
+    outgoing: OutgoingBroadcast,
+    config: AppConfig,
+    triggers: Triggers,
+) -> (Handle, JoinHandle<PublishSummary>) {
+    let (sender, incoming) = mpsc::unbounded_channel();
+    let session = Session::new(name.to_string(), incoming, outgoing, config, triggers);
+
+    let instance = tokio::spawn(session.run());
+
+    (Handle::new(id, name.to_string(), sender), instance)
+}
+
+async fn session_info(name: &str, entry: &SessionEntry) -> SessionInfo {
+    let (metadata, ingest) = if entry.orphaned {
+        (None, IngestStats::default())
+    } else {
+        let (request, response) = oneshot::channel();
+        match entry.handle.send(Message::GetStats(request)) {
+            Ok(()) => response.await.unwrap_or_default(),
+            Err(_) => (None, IngestStats::default()),
         }
+    };
 
-        Ok(())
+    SessionInfo {
+        app_name: name.to_string(),
+        publisher: entry.publisher.clone(),
+        publisher_addr: entry.publisher_addr,
+        started_at: entry.started_at,
+        viewers: entry.outgoing.receiver_count(),
+        metadata,
+        ingest,
     }
 }
@@ -0,0 +1,110 @@
+use {
+    std::{
+        net::SocketAddr,
+        time::{Duration, Instant, SystemTime},
+    },
+    javelin_types::{Metadata, Packet, PacketType},
+    super::{AppName, ClientId},
+};
+
+
+/// Snapshot of an active session.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub app_name: AppName,
+    /// Name of the user that is publishing
+    pub publisher: String,
+    pub publisher_addr: SocketAddr,
+    pub started_at: SystemTime,
+    pub viewers: usize,
+    pub metadata: Option<Metadata>,
+    pub ingest: IngestStats,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientRole {
+    Publisher,
+    Viewer,
+}
+
+
+/// Snapshot of a connected publisher or viewer.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: ClientId,
+    pub role: ClientRole,
+    pub app_name: AppName,
+    pub addr: SocketAddr,
+    pub connected_at: SystemTime,
+}
+
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IngestStats {
+    pub bytes_received: u64,
+    pub video_frames: u64,
+    pub audio_frames: u64,
+    /// Bits per second, measured over the last second
+    pub bitrate: u64,
+    /// Video frames per second, measured over the last second
+    pub frame_rate: f64,
+}
+
+
+/// Final state of a session instance, once its publisher is gone.
+pub(super) struct PublishSummary {
+    pub metadata: Option<Metadata>,
+    pub ingest: IngestStats,
+    pub peak_viewers: usize,
+}
+
+
+pub(super) struct IngestCounter {
+    stats: IngestStats,
+    window_start: Instant,
+    window_bytes: u64,
+    window_frames: u64,
+}
+
+impl IngestCounter {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Self {
+            stats: IngestStats::default(),
+            window_start: Instant::now(),
+            window_bytes: 0,
+            window_frames: 0,
+        }
+    }
+
+    pub fn count(&mut self, packet: &Packet) {
+        let size = packet.payload.len() as u64;
+        self.stats.bytes_received += size;
+        self.window_bytes += size;
+
+        match packet.kind {
+            PacketType::Video => {
+                self.stats.video_frames += 1;
+                self.window_frames += 1;
+            },
+            PacketType::Audio => self.stats.audio_frames += 1,
+            _ => (),
+        }
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Self::WINDOW {
+            let secs = elapsed.as_secs_f64();
+            self.stats.bitrate = ((self.window_bytes * 8) as f64 / secs) as u64;
+            self.stats.frame_rate = self.window_frames as f64 / secs;
+            self.window_start = Instant::now();
+            self.window_bytes = 0;
+            self.window_frames = 0;
+        }
+    }
+
+    pub fn stats(&self) -> IngestStats {
+        self.stats
+    }
+}
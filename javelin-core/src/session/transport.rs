@@ -1,7 +1,19 @@
 use {
+    std::{
+        collections::VecDeque,
+        convert::TryFrom,
+        fmt::{self, Debug},
+        net::SocketAddr,
+        time::{Duration, Instant},
+    },
     tokio::sync::{mpsc, oneshot, broadcast},
-    javelin_types::Packet,
-    super::{AppName, StreamKey, Event},
+    javelin_types::{Packet, PacketType, Metadata},
+    javelin_codec::flv::tag::{VideoData, AudioData},
+    super::{
+        event::{Event, EventKind},
+        stats::{SessionInfo, IngestStats, ClientInfo},
+        AppName, StreamKey, ClientId,
+    },
 };
 
 
@@ -9,18 +21,32 @@ pub type Responder<P> = oneshot::Sender<P>;
 
 // session manager
 pub enum ManagerMessage {
-    CreateSession((AppName, StreamKey, Responder<Handle>)),
-    ReleaseSession(AppName),
-    JoinSession((AppName, Responder<(Handle, Watcher)>)),
-    RegisterTrigger(Event, Trigger),
+    CreateSession((AppName, StreamKey, SocketAddr, Responder<Handle>)),
+    /// Sent by the manager itself once the publisher was authenticated, with the name of the user.
+    PublisherAuthenticated((AppName, String, SocketAddr, Responder<Handle>)),
+    ReleaseSession((AppName, SessionId)),
+    JoinSession((AppName, SocketAddr, Responder<(Handle, Watcher)>)),
+    /// Sent by the manager itself once the play webhook allowed the viewer.
+    ViewerAuthorized((AppName, SocketAddr, Responder<(Handle, Watcher)>)),
+    LeaveSession(AppName),
+    /// Sent by the manager itself once the reconnect grace period is over.
+    ExpireSession((AppName, SessionId)),
+    /// Sent by the manager itself once viewers of a requested session waited long enough.
+    ExpirePendingJoins(AppName),
+    RegisterTrigger(EventKind, Trigger),
+    ListSessions(Responder<Vec<SessionInfo>>),
+    GetSession((AppName, Responder<Option<SessionInfo>>)),
+    ListClients(Responder<Vec<ClientInfo>>),
+    /// Disconnect a client, responds with `false` if no such client exists.
+    KickClient((ClientId, Responder<bool>)),
 }
 
 pub type ManagerHandle = mpsc::UnboundedSender<ManagerMessage>;
 pub(super) type ManagerReceiver = mpsc::UnboundedReceiver<ManagerMessage>;
 
 
-pub type Trigger = mpsc::UnboundedSender<(String, Watcher)>;
-pub(super) type TriggerHandle = mpsc::UnboundedReceiver<(String, Watcher)>;
+pub type Trigger = mpsc::UnboundedSender<Event>;
+pub type TriggerHandle = mpsc::UnboundedReceiver<Event>;
 
 pub fn trigger_channel() -> (Trigger, TriggerHandle) {
     mpsc::unbounded_channel()
@@ -30,11 +56,255 @@ pub fn trigger_channel() -> (Trigger, TriggerHandle) {
 // session instance
 pub enum Message {
     Packet(Packet),
-    GetInitData(Responder<(Option<Packet>, Option<Packet>, Option<Packet>)>),
+    Subscribe(Responder<Watcher>),
+    GetStats(Responder<(Option<Metadata>, IngestStats)>),
     Disconnect,
 }
 
-pub type Handle = mpsc::UnboundedSender<Message>;
+pub type SessionId = u64;
+
+#[derive(Debug, Clone)]
+pub struct Handle {
+    id: SessionId,
+    name: AppName,
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+impl Handle {
+    pub(super) fn new(id: SessionId, name: AppName, sender: mpsc::UnboundedSender<Message>) -> Self {
+        Self { id, name, sender }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// Name of the session, might differ from the requested name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn send(&self, message: Message) -> Result<(), mpsc::error::SendError<Message>> {
+        self.sender.send(message)
+    }
+}
+
 pub(super) type IncomingBroadcast = mpsc::UnboundedReceiver<Message>;
 pub(super) type OutgoingBroadcast = broadcast::Sender<Packet>;
-pub type Watcher = broadcast::Receiver<Packet>;
+
+
+/// Receives packets from a session.
+///
+/// Packets cached by the session at the time of subscribing are replayed first.
+///
+/// If the watcher falls behind the broadcast, the sequence headers are sent again
+/// and all frames up to the next keyframe are skipped.
+pub struct Watcher {
+    backlog: VecDeque<Packet>,
+    receiver: broadcast::Receiver<Packet>,
+    kick: Option<oneshot::Receiver<()>>,
+    kicked: bool,
+    metadata: Option<Packet>,
+    video_seq_header: Option<Packet>,
+    audio_seq_header: Option<Packet>,
+    resyncing: bool,
+    behind_since: Option<Instant>,
+}
+
+impl Watcher {
+    pub(super) fn new<I>(receiver: broadcast::Receiver<Packet>, backlog: I) -> Self
+        where I: IntoIterator<Item=Packet>
+    {
+        Self {
+            backlog: backlog.into_iter().collect(),
+            receiver,
+            kick: None,
+            kicked: false,
+            metadata: None,
+            video_seq_header: None,
+            audio_seq_header: None,
+            resyncing: false,
+            behind_since: None,
+        }
+    }
+
+    /// Allows the watcher to be closed through the returned sender.
+    ///
+    /// Dropping the sender closes the watcher as well.
+    pub(super) fn kickable(&mut self) -> oneshot::Sender<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.kick = Some(receiver);
+        sender
+    }
+
+    /// How long the watcher has not been able to keep up with the broadcast.
+    pub fn behind_for(&self) -> Option<Duration> {
+        self.behind_since.map(|since| since.elapsed())
+    }
+
+    /// Receive the next packet.
+    ///
+    /// `RecvError::Lagged` is only informational, the watcher recovers on its own
+    /// and can continue to be used.
+    pub async fn recv(&mut self) -> Result<Packet, broadcast::RecvError> {
+        loop {
+            if self.kicked {
+                return Err(broadcast::RecvError::Closed);
+            }
+
+            let packet = match self.backlog.pop_front() {
+                Some(packet) => packet,
+                None => self.next_packet().await?,
+            };
+
+            match FrameKind::of(&packet) {
+                FrameKind::Metadata => self.metadata = Some(packet.clone()),
+                FrameKind::VideoSequenceHeader => self.video_seq_header = Some(packet.clone()),
+                FrameKind::AudioSequenceHeader => self.audio_seq_header = Some(packet.clone()),
+                FrameKind::Keyframe => self.resyncing = false,
+                // without video every audio frame can be decoded on its own
+                FrameKind::Audio if self.video_seq_header.is_none() => self.resyncing = false,
+                FrameKind::Audio | FrameKind::Other if self.resyncing => continue,
+                FrameKind::Audio | FrameKind::Other | FrameKind::Discontinuity => (),
+            }
+
+            return Ok(packet);
+        }
+    }
+
+    async fn next_packet(&mut self) -> Result<Packet, broadcast::RecvError> {
+        match self.receiver.try_recv() {
+            Ok(packet) => return Ok(packet),
+            Err(broadcast::TryRecvError::Closed) => return Err(broadcast::RecvError::Closed),
+            Err(broadcast::TryRecvError::Lagged(count)) => return Err(self.lagged(count)),
+            Err(broadcast::TryRecvError::Empty) => {
+                if !self.resyncing {
+                    self.behind_since = None;
+                }
+            },
+        }
+
+        let result = match &mut self.kick {
+            Some(kick) => tokio::select! {
+                _ = kick => None,
+                result = self.receiver.recv() => Some(result),
+            },
+            None => Some(self.receiver.recv().await),
+        };
+
+        match result {
+            Some(Err(broadcast::RecvError::Lagged(count))) => Err(self.lagged(count)),
+            Some(result) => result,
+            None => {
+                self.kick = None;
+                self.kicked = true;
+                Err(broadcast::RecvError::Closed)
+            },
+        }
+    }
+
+    fn lagged(&mut self, count: u64) -> broadcast::RecvError {
+        self.resyncing = true;
+        self.behind_since.get_or_insert_with(Instant::now);
+
+        // headers might have been skipped, decoding restarts at the next keyframe
+        let init_data = [&self.metadata, &self.video_seq_header, &self.audio_seq_header];
+        self.backlog.extend(init_data.iter().filter_map(|packet| packet.as_ref().cloned()));
+
+        broadcast::RecvError::Lagged(count)
+    }
+}
+
+impl Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watcher")
+            .field("backlog", &self.backlog.len())
+            .finish()
+    }
+}
+
+
+enum FrameKind {
+    Metadata,
+    VideoSequenceHeader,
+    AudioSequenceHeader,
+    Keyframe,
+    Audio,
+    Discontinuity,
+    Other,
+}
+
+impl FrameKind {
+    fn of(packet: &Packet) -> Self {
+        match packet.kind {
+            PacketType::Meta => Self::Metadata,
+            PacketType::Video => match VideoData::try_from(packet.as_ref()) {
+                Ok(video) if video.is_sequence_header() => Self::VideoSequenceHeader,
+                Ok(video) if video.is_keyframe() => Self::Keyframe,
+                _ => Self::Other,
+            },
+            PacketType::Audio => match AudioData::try_from(packet.as_ref()) {
+                Ok(audio) if audio.is_sequence_header() => Self::AudioSequenceHeader,
+                _ => Self::Audio,
+            },
+            PacketType::Discontinuity => Self::Discontinuity,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUDIO_SEQ_HEADER: [u8; 4] = [0xAF, 0x00, 0x12, 0x10];
+    const VIDEO_SEQ_HEADER: [u8; 5] = [0x17, 0x00, 0x00, 0x00, 0x00];
+    const INTER_FRAME: [u8; 6] = [0x27, 0x01, 0x00, 0x00, 0x00, 0xBB];
+
+    fn audio_frame(n: u8) -> Packet {
+        Packet::new_audio(u32::from(n), vec![0xAF, 0x01, n])
+    }
+
+    fn is_lagged<T>(result: &Result<T, broadcast::RecvError>) -> bool {
+        matches!(result, Err(broadcast::RecvError::Lagged(_)))
+    }
+
+    #[tokio::test]
+    async fn lagging_audio_only_watcher_resumes_with_next_frame() {
+        let (sender, receiver) = broadcast::channel(2);
+        let mut watcher = Watcher::new(receiver, Vec::new());
+
+        assert!(sender.send(Packet::new_audio(0u32, AUDIO_SEQ_HEADER.to_vec())).is_ok());
+        assert_eq!(watcher.recv().await.unwrap().payload.as_ref(), &AUDIO_SEQ_HEADER);
+
+        for n in 1..=5 {
+            assert!(sender.send(audio_frame(n)).is_ok());
+        }
+        drop(sender);
+
+        assert!(is_lagged(&watcher.recv().await));
+        assert_eq!(watcher.recv().await.unwrap().payload.as_ref(), &AUDIO_SEQ_HEADER);
+        assert_eq!(watcher.recv().await.unwrap().payload.as_ref(), &[0xAF, 0x01, 4]);
+        assert_eq!(watcher.recv().await.unwrap().payload.as_ref(), &[0xAF, 0x01, 5]);
+        assert!(watcher.behind_for().is_some());
+    }
+
+    #[tokio::test]
+    async fn lagging_watcher_with_video_waits_for_keyframe() {
+        let (sender, receiver) = broadcast::channel(2);
+        let mut watcher = Watcher::new(receiver, Vec::new());
+
+        assert!(sender.send(Packet::new_video(0u32, VIDEO_SEQ_HEADER.to_vec())).is_ok());
+        assert_eq!(watcher.recv().await.unwrap().payload.as_ref(), &VIDEO_SEQ_HEADER);
+
+        for n in 1..=4 {
+            assert!(sender.send(audio_frame(n)).is_ok());
+        }
+        assert!(sender.send(Packet::new_video(5u32, INTER_FRAME.to_vec())).is_ok());
+        drop(sender);
+
+        assert!(is_lagged(&watcher.recv().await));
+        assert_eq!(watcher.recv().await.unwrap().payload.as_ref(), &VIDEO_SEQ_HEADER);
+        assert!(matches!(watcher.recv().await, Err(broadcast::RecvError::Closed)));
+    }
+}
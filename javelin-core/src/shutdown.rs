@@ -0,0 +1,66 @@
+use {
+    std::time::Duration,
+    serde::{Serialize, Deserialize},
+    tokio::sync::watch,
+};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// How long services get to finish their work before the process exits.
+    #[serde(default = "default_timeout")]
+    pub timeout: Duration,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+
+/// Starts the shutdown of all services holding a [`Shutdown`](Shutdown).
+pub struct Trigger {
+    sender: watch::Sender<bool>,
+}
+
+impl Trigger {
+    pub fn trigger(self) {
+        let _ = self.sender.broadcast(true);
+    }
+}
+
+
+/// Notifies a service that the server is stopping.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Completes once shutdown was triggered, or the trigger is gone.
+    pub async fn wait(&mut self) {
+        while !self.is_triggered() {
+            if self.receiver.recv().await.is_none() {
+                return;
+            }
+        }
+    }
+}
+
+
+pub fn channel() -> (Trigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (Trigger { sender }, Shutdown { receiver })
+}
@@ -0,0 +1,335 @@
+use {
+    std::{future::Future, net::SocketAddr, pin::Pin, time::Duration},
+    anyhow::{Result, bail},
+    chrono::Utc,
+    serde::{Serialize, Deserialize},
+    crate::{
+        session::{self, ManagerMessage, Event, EventKind},
+        config::Updates,
+    },
+};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Publish requests are sent here instead of checking the stream key in the database
+    #[serde(default)]
+    pub publish_url: Option<String>,
+
+    /// Play requests are sent here
+    #[serde(default)]
+    pub play_url: Option<String>,
+
+    /// Session start and end events are sent here
+    #[serde(default)]
+    pub events_url: Option<String>,
+
+    #[serde(default = "default_timeout")]
+    pub timeout: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            publish_url: None,
+            play_url: None,
+            events_url: None,
+            timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Publish,
+    Play,
+}
+
+
+#[derive(Serialize)]
+struct Request {
+    action: Action,
+    stream: String,
+    key: Option<String>,
+    addr: String,
+}
+
+
+#[derive(Default, Deserialize)]
+struct Response {
+    /// Use a different stream name
+    #[serde(default)]
+    stream: Option<String>,
+
+    /// Identity of the publisher
+    #[serde(default)]
+    user: Option<String>,
+}
+
+
+/// A pending webhook request, independent of the [`Webhooks`](Webhooks) it was created by.
+pub type Authorization = Pin<Box<dyn Future<Output = Result<Grant>> + Send>>;
+
+
+/// Result of an allowed request.
+#[derive(Debug)]
+pub struct Grant {
+    pub stream: String,
+    pub user: Option<String>,
+}
+
+
+/// Asks an HTTP endpoint whether publish and play requests are allowed.
+///
+/// A 2xx response allows the request, every other response or error denies it.
+/// The response body can optionally be a JSON object with a different `stream`
+/// name to use and the `user` that is publishing.
+pub struct Webhooks {
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Result<Self> {
+        let client = build_client(&config)?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Returns `None` if no webhook is configured for the action.
+    pub fn authorize(&self, action: Action, stream: &str, key: Option<&str>, addr: SocketAddr) -> Option<Authorization> {
+        let url = self.url(action)?.to_string();
+        let request = Request {
+            action,
+            stream: stream.to_string(),
+            key: key.map(ToString::to_string),
+            addr: addr.ip().to_string(),
+        };
+
+        Some(Box::pin(send_request(self.client.clone(), url, request)))
+    }
+
+    fn url(&self, action: Action) -> Option<&str> {
+        match action {
+            Action::Publish => self.config.publish_url.as_deref(),
+            Action::Play => self.config.play_url.as_deref(),
+        }
+    }
+}
+
+
+async fn send_request(client: reqwest::Client, url: String, request: Request) -> Result<Grant> {
+    let response = client.post(&url).json(&request).send().await?;
+    let status = response.status();
+
+    if !status.is_success() {
+        bail!("Webhook denied {:?} of {} ({})", request.action, request.stream, status);
+    }
+
+    let body = response.text().await?;
+    let response: Response = if body.trim().is_empty() {
+        Response::default()
+    } else {
+        serde_json::from_str(&body)?
+    };
+
+    let stream = match response.stream {
+        Some(stream) if !session::is_valid_name(&stream) => {
+            bail!("Webhook returned invalid stream name {}", stream);
+        },
+        Some(stream) => stream,
+        None => request.stream,
+    };
+
+    Ok(Grant { stream, user: response.user })
+}
+
+
+#[derive(Serialize)]
+struct EventPayload<'a> {
+    event: &'a str,
+    stream: &'a str,
+    timestamp: String,
+}
+
+
+/// Posts session start and end events to the configured endpoint.
+pub struct EventNotifier {
+    client: reqwest::Client,
+    config: WebhookConfig,
+    session_manager: session::ManagerHandle,
+}
+
+impl EventNotifier {
+    pub fn new(session_manager: session::ManagerHandle, config: WebhookConfig) -> Result<Self> {
+        let client = build_client(&config)?;
+
+        Ok(Self { client, config, session_manager })
+    }
+
+    pub async fn run(mut self, mut updates: Updates<WebhookConfig>) {
+        let (trigger, mut trigger_handle) = session::trigger_channel();
+
+        for kind in &[EventKind::SessionCreated, EventKind::SessionReleased] {
+            if self.session_manager.send(ManagerMessage::RegisterTrigger(*kind, trigger.clone())).is_err() {
+                log::error!("Failed to register session trigger");
+                return;
+            }
+        }
+
+        loop {
+            let event = tokio::select! {
+                event = trigger_handle.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                Some(config) = updates.changed() => {
+                    match build_client(&config) {
+                        Ok(client) => {
+                            self.config = (*config).clone();
+                            self.client = client;
+                        },
+                        Err(why) => log::error!("Keeping previous webhook settings: {}", why),
+                    }
+                    continue;
+                },
+            };
+
+            let url = match &self.config.events_url {
+                Some(url) => url,
+                None => continue,
+            };
+
+            let name = match &event {
+                Event::SessionCreated(..) => "session_started",
+                Event::SessionReleased(_) => "session_ended",
+                _ => continue,
+            };
+
+            let payload = EventPayload {
+                event: name,
+                stream: event.app_name(),
+                timestamp: Utc::now().to_rfc3339(),
+            };
+
+            // events are sent in order, a slow endpoint only delays other events
+            if let Err(why) = self.client.post(url).json(&payload).send().await {
+                log::error!("Failed to send {} event to webhook: {}", name, why);
+            }
+        }
+    }
+}
+
+
+fn build_client(config: &WebhookConfig) -> Result<reqwest::Client> {
+    let client = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .build()?;
+
+    Ok(client)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::net::TcpListener,
+        tokio::time::delay_for,
+        warp::{Filter, Rejection, http::StatusCode},
+        super::*,
+    };
+
+    const STREAM: &str = "live/test";
+
+    /// Serve every POST request with the same response.
+    fn stub(status: u16, body: &'static str, delay: Duration) -> String {
+        let route = warp::post().and_then(move || async move {
+            delay_for(delay).await;
+            let status = StatusCode::from_u16(status).unwrap();
+            Ok::<_, Rejection>(warp::reply::with_status(body, status))
+        });
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        format!("http://{}/auth", addr)
+    }
+
+    fn webhooks(url: String, timeout: Duration) -> Webhooks {
+        Webhooks::new(WebhookConfig {
+            publish_url: Some(url),
+            timeout,
+            ..WebhookConfig::default()
+        }).unwrap()
+    }
+
+    async fn authorize(webhooks: &Webhooks) -> Result<Grant> {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1935));
+        webhooks.authorize(Action::Publish, STREAM, Some("secret"), addr).unwrap().await
+    }
+
+    #[tokio::test]
+    async fn success_allows_request() {
+        let webhooks = webhooks(stub(204, "", Duration::default()), default_timeout());
+
+        let grant = authorize(&webhooks).await.unwrap();
+        assert_eq!(grant.stream, STREAM);
+        assert!(grant.user.is_none());
+    }
+
+    #[tokio::test]
+    async fn client_error_denies_request() {
+        let webhooks = webhooks(stub(403, "", Duration::default()), default_timeout());
+
+        assert!(authorize(&webhooks).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn timeout_denies_request() {
+        let url = stub(200, "", Duration::from_millis(500));
+        let webhooks = webhooks(url, Duration::from_millis(50));
+
+        assert!(authorize(&webhooks).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connection_error_denies_request() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let webhooks = webhooks(format!("http://{}/auth", addr), default_timeout());
+
+        assert!(authorize(&webhooks).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn response_redirects_stream_and_user() {
+        let body = r#"{"stream": "live/other", "user": "alice"}"#;
+        let webhooks = webhooks(stub(200, body, Duration::default()), default_timeout());
+
+        let grant = authorize(&webhooks).await.unwrap();
+        assert_eq!(grant.stream, "live/other");
+        assert_eq!(grant.user.as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn invalid_redirected_stream_denies_request() {
+        let body = r#"{"stream": "live/../other"}"#;
+        let webhooks = webhooks(stub(200, body, Duration::default()), default_timeout());
+
+        assert!(authorize(&webhooks).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn action_without_url_is_not_handled() {
+        let webhooks = webhooks(stub(200, "", Duration::default()), default_timeout());
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1935));
+
+        assert!(webhooks.authorize(Action::Play, STREAM, None, addr).is_none());
+    }
+}
@@ -2,12 +2,14 @@ use {
     std::{
         path::PathBuf,
         net::SocketAddr,
+        time::Duration,
     },
-    serde::Deserialize,
+    serde::{Serialize, Deserialize},
 };
 
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default = "default_root_dir")]
     pub root_dir: PathBuf,
@@ -17,6 +19,14 @@ pub struct Config {
 
     #[serde(default)]
     pub web: WebConfig,
+
+    /// Minimum length of a segment, segments always start with a keyframe
+    #[serde(default = "default_segment_duration")]
+    pub segment_duration: Duration,
+
+    /// How much of a stream is kept in the playlist
+    #[serde(default = "default_playlist_duration")]
+    pub playlist_duration: Duration,
 }
 
 impl Default for Config {
@@ -25,12 +35,15 @@ impl Default for Config {
             root_dir: default_root_dir(),
             enabled: default_enabled(),
             web: WebConfig::default(),
+            segment_duration: default_segment_duration(),
+            playlist_duration: default_playlist_duration(),
         }
     }
 }
 
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct WebConfig {
     #[serde(default = "default_web_addr")]
     pub addr: SocketAddr,
@@ -53,6 +66,14 @@ fn default_root_dir() -> PathBuf {
     PathBuf::from("./tmp/stream")
 }
 
+fn default_segment_duration() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_playlist_duration() -> Duration {
+    Duration::from_secs(30)
+}
+
 fn default_web_addr() -> SocketAddr {
     SocketAddr::from(([0, 0, 0, 0], 8080))
 }
@@ -1,10 +1,11 @@
 use {
-    std::{path::PathBuf, fs},
+    std::{collections::HashMap, path::PathBuf, fs},
     tokio::{
         stream::StreamExt,
         sync::mpsc,
         time::{DelayQueue, Instant, Duration},
     },
+    javelin_core::metrics::METRICS,
 };
 
 
@@ -15,39 +16,80 @@ type Receiver = mpsc::UnboundedReceiver<Message>;
 
 
 pub struct FileCleaner {
-    items: DelayQueue<Batch>,
-    sender: Sender,
+    /// Ids of the queued batches, by the time they expire
+    items: DelayQueue<u64>,
+    batches: HashMap<u64, (Instant, Batch)>,
+    next_id: u64,
     receiver: Receiver,
 }
 
 impl FileCleaner {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    /// The cleaner runs until every sender is gone.
+    pub fn new() -> (Self, Sender) {
         let (sender, receiver) = mpsc::unbounded_channel();
 
-        Self {
+        let cleaner = Self {
             items: DelayQueue::new(),
-            sender,
+            batches: HashMap::new(),
+            next_id: 0,
             receiver,
-        }
+        };
+
+        (cleaner, sender)
     }
 
     pub async fn run(mut self) {
-        while let Some((duration, files)) = self.receiver.recv().await {
-            let timestamp = Instant::now() + ((duration / 100) * 150);
-            log::debug!("{} files queued for cleanup at {:?}", files.len(), timestamp);
-            self.items.insert_at(files, timestamp);
-
-            match self.items.next().await {
-                Some(Ok(expired)) => remove_files(expired.get_ref()),
-                Some(Err(why)) => log::error!("{}", why),
-                None => ()
+        loop {
+            tokio::select! {
+                message = self.receiver.recv() => match message {
+                    Some((duration, files)) => self.schedule(duration, files),
+                    None => break,
+                },
+                Some(expired) = self.items.next(), if !self.items.is_empty() => match expired {
+                    Ok(expired) => {
+                        if let Some((_, files)) = self.batches.remove(expired.get_ref()) {
+                            remove_files(&files);
+                        }
+                    },
+                    Err(why) => log::error!("{}", why),
+                },
             }
+
+            METRICS.hls_cleanup_backlog.set(self.items.len() as i64);
         }
+
+        self.drain();
     }
 
-    pub fn sender(&self) -> Sender {
-        self.sender.clone()
+    fn schedule(&mut self, duration: Duration, files: Batch) {
+        let timestamp = Instant::now() + ((duration / 100) * 150);
+        log::debug!("{} files queued for cleanup at {:?}", files.len(), timestamp);
+        self.items.insert_at(self.next_id, timestamp);
+        self.batches.insert(self.next_id, (timestamp, files));
+        self.next_id += 1;
+    }
+
+    /// Remove the files that are already due right away.
+    ///
+    /// Files that are not due yet can still be referenced by a final playlist, so they are kept.
+    fn drain(&mut self) {
+        let now = Instant::now();
+        let batch_count = self.batches.len();
+
+        self.batches.retain(|_, (timestamp, files)| {
+            if *timestamp > now {
+                return true;
+            }
+            remove_files(files);
+            false
+        });
+
+        log::debug!("Drained {} of {} queued file batches", batch_count - self.batches.len(), batch_count);
+
+        self.batches.clear();
+        self.items.clear();
+
+        METRICS.hls_cleanup_backlog.set(0);
     }
 }
 
@@ -65,3 +107,26 @@ fn remove_file(path: &PathBuf) {
         log::error!("Failed to remove file '{}': {}", path.display(), why);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_keeps_files_that_are_not_due() {
+        let dir = tempfile::tempdir().unwrap();
+        let due = dir.path().join("due.ts");
+        let pending = dir.path().join("pending.ts");
+        fs::write(&due, b"").unwrap();
+        fs::write(&pending, b"").unwrap();
+
+        let (mut cleaner, _sender) = FileCleaner::new();
+        cleaner.schedule(Duration::from_secs(0), vec![due.clone()]);
+        cleaner.schedule(Duration::from_secs(60), vec![pending.clone()]);
+        cleaner.drain();
+
+        assert!(!due.exists());
+        assert!(pending.exists());
+    }
+}
@@ -2,7 +2,11 @@ mod config;
 mod m3u8;
 mod writer;
 mod file_cleaner;
+mod web;
 pub mod service;
 
 
-pub use self::service::Service;
+pub use self::{
+    config::Config,
+    service::Service,
+};
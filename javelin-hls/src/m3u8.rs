@@ -13,16 +13,18 @@ use std::os::unix::fs::PermissionsExt;
 pub struct Playlist {
     file_path: PathBuf,
     current_duration: u64,
+    /// Segments are removed from the playlist once it gets longer than this (milliseconds)
+    cache_duration: u64,
     cleanup_started: bool,
+    discontinuity: bool,
     playlist: MediaPlaylist,
     file_cleaner: file_cleaner::Sender,
 }
 
 impl Playlist {
     const DEFAULT_TARGET_DURATION: f32 = 6.0;
-    const PLAYLIST_CACHE_DURATION: u64 = 30000; // milliseconds
 
-    pub fn new<P>(path: P, file_cleaner: file_cleaner::Sender) -> Self
+    pub fn new<P>(path: P, cache_duration: Duration, file_cleaner: file_cleaner::Sender) -> Self
         where P: Into<PathBuf>
     {
         let mut playlist = MediaPlaylist::default();
@@ -33,7 +35,9 @@ impl Playlist {
         Self {
             file_path: path.into(),
             current_duration: 0,
+            cache_duration: cache_duration.as_millis() as u64,
             cleanup_started: false,
+            discontinuity: false,
             playlist,
             file_cleaner,
         }
@@ -43,8 +47,16 @@ impl Playlist {
         self.playlist.target_duration = (duration as f64 / 1000.0) as f32;
     }
 
+    /// Mark the next segment as discontinuous to the previous one.
+    pub fn add_discontinuity(&mut self) {
+        self.discontinuity = true;
+    }
+
     fn schedule_for_deletion(&mut self, amount: usize, delete_after: u64) {
         let segments_to_delete: Vec<_> = self.playlist.segments.drain(..amount).collect();
+        let discontinuities = segments_to_delete.iter().filter(|seg| seg.discontinuity).count();
+        self.playlist.discontinuity_sequence += discontinuities as i32;
+
         let paths: Vec<_> = segments_to_delete.iter()
             .map(|seg| {
                 self.current_duration -= (seg.duration * 1000.0) as u64;
@@ -63,11 +75,12 @@ impl Playlist {
         segment.duration = (duration as f64 / 1000.0) as f32;
         segment.title = Some("".into()); // adding empty title here, because implementation is broken
         segment.uri = uri.into();
-
+        segment.discontinuity = self.discontinuity;
+        self.discontinuity = false;
 
         if self.cleanup_started {
-            self.schedule_for_deletion(1, Self::PLAYLIST_CACHE_DURATION);
-        } else if self.current_duration >= Self::PLAYLIST_CACHE_DURATION {
+            self.schedule_for_deletion(1, self.cache_duration);
+        } else if self.current_duration >= self.cache_duration {
             self.cleanup_started = true;
         }
 
@@ -1,14 +1,16 @@
 use {
-    std::{fs, path::Path},
+    std::{fs, path::Path, sync::Arc},
     anyhow::{Result, bail},
-    warp::Filter,
     javelin_core::{
-        session::{self, ManagerMessage},
-        Config,
+        session::{self, ManagerMessage, Event, EventKind},
+        playback::Authorizer,
+        shutdown::Shutdown,
+        config::Updates,
     },
     crate::{
         config::Config as HlsConfig,
         file_cleaner,
+        web,
         writer::Writer,
     },
 };
@@ -17,19 +19,20 @@ use {
 pub struct Service {
     config: HlsConfig,
     session_manager: session::ManagerHandle,
+    authorizer: Arc<Authorizer>,
 }
 
 
 impl Service {
-    pub fn new(session_manager: session::ManagerHandle, config: &Config) -> Self {
-        let config = config.get("hls").unwrap_or_default();
+    pub fn new(session_manager: session::ManagerHandle, config: HlsConfig, authorizer: Arc<Authorizer>) -> Self {
         Self {
             config,
             session_manager,
+            authorizer,
         }
     }
 
-    pub async fn run(self)  {
+    pub async fn run(mut self, mut shutdown: Shutdown, mut updates: Updates<HlsConfig>) {
         let hls_root = self.config.root_dir.clone();
         log::info!("HLS directory located at '{}'", hls_root.display());
 
@@ -38,17 +41,15 @@ impl Service {
             return
         }
 
-        let fcleaner = file_cleaner::FileCleaner::new();
-        let fcleaner_sender = fcleaner.sender();
-        tokio::spawn(async move {
+        let (fcleaner, fcleaner_sender) = file_cleaner::FileCleaner::new();
+        let fcleaner = tokio::spawn(async move {
             fcleaner.run().await
         });
 
         if self.config.web.enabled {
             let addr = self.config.web.addr;
 
-            let routes = warp::path("hls")
-                .and(warp::fs::dir(hls_root));
+            let routes = web::routes(hls_root, Arc::clone(&self.authorizer));
 
             tokio::spawn(async move {
                 warp::serve(routes).run(addr).await;
@@ -57,21 +58,56 @@ impl Service {
 
         let (trigger, mut trigger_handle) = session::trigger_channel();
 
-        if let Err(_) = self.session_manager.send(ManagerMessage::RegisterTrigger("create_session", trigger)) {
+        if let Err(_) = self.session_manager.send(ManagerMessage::RegisterTrigger(EventKind::SessionCreated, trigger)) {
             log::error!("Failed to register session trigger");
             return;
         }
 
-        while let Some((app_name, watcher)) = trigger_handle.recv().await {
+        loop {
+            let event = tokio::select! {
+                event = trigger_handle.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                Some(config) = updates.changed() => {
+                    self.reload(&config);
+                    continue;
+                },
+                _ = shutdown.wait() => break,
+            };
+
+            let (app_name, watcher) = match event {
+                Event::SessionCreated(app_name, watcher) => (app_name, watcher),
+                _ => continue,
+            };
+
             match Writer::create(app_name, watcher, fcleaner_sender.clone(), &self.config) {
                 Ok(writer) => {
+                    let shutdown = shutdown.clone();
                     tokio::spawn(async move {
-                        writer.run().await.unwrap()
+                        if let Err(why) = writer.run(shutdown).await {
+                            log::error!("HLS writer failed: {:?}", why);
+                        }
                     });
                 },
                 Err(why) => log::error!("Failed to create writer: {:?}", why),
             }
         }
+
+        // the cleaner stops once all writers are done with their playlists
+        drop(fcleaner_sender);
+        if fcleaner.await.is_err() {
+            log::error!("HLS file cleaner failed");
+        }
+
+        log::info!("HLS service stopped");
+    }
+
+    /// Segment settings apply to streams that start after the reload.
+    fn reload(&mut self, config: &HlsConfig) {
+        self.config.segment_duration = config.segment_duration;
+        self.config.playlist_duration = config.playlist_duration;
+        log::debug!("Reloaded HLS settings");
     }
 }
 
@@ -0,0 +1,200 @@
+use {
+    std::{path::PathBuf, sync::Arc},
+    percent_encoding::percent_decode_str,
+    warp::{
+        http::StatusCode,
+        path::Peek,
+        reject::{self, Reject},
+        Filter, Rejection, Reply,
+    },
+    javelin_core::playback::Authorizer,
+};
+
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+
+/// Serves the HLS directory, protected streams require a signed URL.
+pub fn routes(root_dir: PathBuf, authorizer: Arc<Authorizer>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let authorized = resolved_path()
+        .and(raw_query())
+        .and_then(move |path: String, query: String| {
+            let authorizer = Arc::clone(&authorizer);
+            async move {
+                if authorizer.authorize(stream_name(&path), &query) {
+                    Ok(())
+                } else {
+                    Err(reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one();
+
+    let playlist = {
+        let root_dir = root_dir.clone();
+        resolved_path()
+            .and(raw_query())
+            .and_then(move |path: String, query: String| serve_playlist(root_dir.clone(), path, query))
+    };
+
+    warp::path("hls")
+        .and(authorized)
+        .and(playlist.or(warp::fs::dir(root_dir)))
+        .recover(handle_rejection)
+}
+
+
+/// The requested file the way `warp::fs::dir` resolves it,
+/// so protected streams can not be reached through another spelling of their path.
+fn resolved_path() -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::path::peek()
+        .and_then(|path: Peek| async move {
+            resolve_path(path.as_str()).ok_or_else(reject::not_found)
+        })
+}
+
+fn raw_query() -> impl Filter<Extract = (String,), Error = std::convert::Infallible> + Clone {
+    warp::query::raw()
+        .or(warp::any().map(String::new))
+        .unify()
+}
+
+/// Segments are requested without the query string of the playlist,
+/// so it has to be added to every segment URI.
+async fn serve_playlist(root_dir: PathBuf, path: String, query: String) -> Result<impl Reply, Rejection> {
+    if query.is_empty() || !path.ends_with(".m3u8") {
+        return Err(reject::not_found());
+    }
+
+    let playlist = tokio::fs::read_to_string(root_dir.join(path)).await
+        .map_err(|_| reject::not_found())?;
+
+    let playlist = playlist
+        .lines()
+        .map(|line| {
+            if line.is_empty() || line.starts_with('#') {
+                line.to_string()
+            } else {
+                format!("{}?{}", line, query)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(warp::reply::with_header(playlist, "content-type", "application/vnd.apple.mpegurl"))
+}
+
+async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+    if err.find::<Unauthorized>().is_some() {
+        return Ok(StatusCode::FORBIDDEN);
+    }
+
+    Err(err)
+}
+
+/// Decodes the path and drops empty and `.` segments, paths leaving the root are rejected.
+fn resolve_path(path: &str) -> Option<String> {
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let mut segments = Vec::new();
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => continue,
+            _ if segment.starts_with("..") || segment.contains('\\') => return None,
+            _ => segments.push(segment),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+/// Stream names are the directory of the requested file.
+fn stream_name(path: &str) -> &str {
+    path.rsplitn(2, '/').nth(1).unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::fs,
+        javelin_core::playback::PlaybackConfig,
+        warp::test::request,
+    };
+
+    const PLAYLIST: &str = "#EXTM3U\nsegment.ts\n";
+
+    fn setup() -> (tempfile::TempDir, Arc<Authorizer>) {
+        let root = tempfile::tempdir().unwrap();
+
+        for stream in &["live/secret", "live/public"] {
+            let dir = root.path().join(stream);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("playlist.m3u8"), PLAYLIST).unwrap();
+            fs::write(dir.join("segment.ts"), "data").unwrap();
+        }
+
+        let authorizer = Authorizer::new(PlaybackConfig {
+            secret: "secret".to_string(),
+            protected: vec!["live/secret".to_string()],
+        });
+
+        (root, Arc::new(authorizer))
+    }
+
+    async fn status(path: &str) -> StatusCode {
+        let (root, authorizer) = setup();
+        let filter = routes(root.path().to_path_buf(), authorizer);
+        request().path(path).reply(&filter).await.status()
+    }
+
+    #[test]
+    fn paths_are_resolved_like_the_file_server() {
+        assert_eq!(resolve_path("live/secret/playlist.m3u8").unwrap(), "live/secret/playlist.m3u8");
+        assert_eq!(resolve_path("%6Cive/secret/playlist.m3u8").unwrap(), "live/secret/playlist.m3u8");
+        assert_eq!(resolve_path("live//secret/./playlist.m3u8").unwrap(), "live/secret/playlist.m3u8");
+        assert_eq!(resolve_path("live%2Fsecret/playlist.m3u8").unwrap(), "live/secret/playlist.m3u8");
+        assert!(resolve_path("live/../secret/playlist.m3u8").is_none());
+        assert!(resolve_path("live/%2E%2E/secret/playlist.m3u8").is_none());
+    }
+
+    #[tokio::test]
+    async fn protected_streams_require_a_signature() {
+        assert_eq!(status("/hls/live/secret/playlist.m3u8").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/hls/live/secret/segment.ts").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/hls/live/public/playlist.m3u8").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn encoded_paths_are_protected() {
+        assert_eq!(status("/hls/%6Cive/secret/playlist.m3u8").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/hls/live/%73ecret/segment.ts").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/hls/live%2Fsecret/playlist.m3u8").await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn paths_with_empty_segments_are_protected() {
+        assert_eq!(status("/hls/live//secret/playlist.m3u8").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/hls//live/secret/segment.ts").await, StatusCode::FORBIDDEN);
+        assert_eq!(status("/hls/live/./secret/playlist.m3u8").await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn signed_playlists_are_served_with_signed_segments() {
+        let (root, authorizer) = setup();
+        let query = authorizer.sign("live/secret", std::time::Duration::from_secs(60)).unwrap();
+        let filter = routes(root.path().to_path_buf(), authorizer);
+
+        let response = request()
+            .path(&format!("/hls/live//secret/playlist.m3u8?{}", query))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &format!("#EXTM3U\nsegment.ts?{}", query));
+    }
+}
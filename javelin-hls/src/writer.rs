@@ -6,16 +6,18 @@ use {
     },
     chrono::Utc,
     anyhow::{Result, bail},
+    tokio::sync::broadcast::RecvError,
     javelin_codec::{
         FormatReader,
         FormatWriter,
         avc::{self, AvcCoder},
+        hevc::{self, HevcCoder},
         aac::{self, AacCoder},
-        flv,
+        flv::{self, tag::{VideoCodec, VideoPacketType, AudioFormat, AudioPacketType}},
         mpegts::TransportStream,
     },
     javelin_types::{Packet, PacketType},
-    javelin_core::session,
+    javelin_core::{session, metrics::METRICS, shutdown::Shutdown},
     crate::{
         config::Config,
         file_cleaner,
@@ -29,17 +31,21 @@ pub struct Writer {
     write_interval: u64,
     next_write: u64,
     last_keyframe: u64,
+    last_timestamp: u64,
     keyframe_counter: usize,
     buffer: TransportStream,
     playlist: Playlist,
     stream_path: PathBuf,
+    video_codec: Option<VideoCodec>,
+    audio_format: Option<AudioFormat>,
     avc_coder: AvcCoder,
+    hevc_coder: HevcCoder,
     aac_coder: AacCoder,
 }
 
 impl Writer {
     pub fn create(app_name: String, watcher: session::Watcher, fcleaner_sender: file_cleaner::Sender, config: &Config) -> Result<Self> {
-        let write_interval = 2000; // milliseconds
+        let write_interval = config.segment_duration.as_millis() as u64; // milliseconds
         let next_write = write_interval; // milliseconds
 
         let hls_root = config.root_dir.clone();
@@ -53,22 +59,51 @@ impl Writer {
             write_interval,
             next_write,
             last_keyframe: 0,
+            last_timestamp: 0,
             keyframe_counter: 0,
             buffer: TransportStream::new(),
-            playlist: Playlist::new(playlist_path, fcleaner_sender),
+            playlist: Playlist::new(playlist_path, config.playlist_duration, fcleaner_sender),
+            video_codec: None,
+            audio_format: None,
             avc_coder: AvcCoder::new(),
+            hevc_coder: HevcCoder::new(),
             aac_coder: AacCoder::new(),
             stream_path,
         })
     }
 
-    pub async fn run(mut self) -> Result<()> {
-        while let Ok(packet) = self.watcher.recv().await {
+    pub async fn run(mut self, mut shutdown: Shutdown) -> Result<()> {
+        loop {
+            let received = tokio::select! {
+                received = self.watcher.recv() => received,
+                _ = shutdown.wait() => break,
+            };
+
+            let packet = match received {
+                Ok(packet) => packet,
+                Err(RecvError::Lagged(count)) => {
+                    log::warn!("HLS writer for {} lagged behind by {} packets", self.stream_path.display(), count);
+                    METRICS.dropped_packets.inc_by(count as i64);
+                    continue;
+                },
+                Err(RecvError::Closed) => break,
+            };
+
             if let Err(why) = self.handle_packet(packet) {
                 log::error!("{:?}", why);
             }
         }
 
+        // the playlist gets its end tag once dropped
+        self.flush()
+    }
+
+    /// Write the buffered data as a segment, if there is any.
+    fn flush(&mut self) -> Result<()> {
+        if self.keyframe_counter > 0 && !self.buffer.is_empty() {
+            self.write_segment(self.last_timestamp.saturating_sub(self.last_keyframe))?;
+        }
+
         Ok(())
     }
 
@@ -81,7 +116,29 @@ impl Writer {
         let payload = &flv_packet.body;
 
         if flv_packet.is_sequence_header() {
-            self.avc_coder.set_dcr(payload.as_ref())?;
+            match flv_packet.codec {
+                VideoCodec::Avc => self.avc_coder.set_dcr(payload.as_ref())?,
+                VideoCodec::Hevc => self.hevc_coder.set_dcr(payload.as_ref())?,
+                VideoCodec::Av1 => {
+                    log::warn!("AV1 is not supported by HLS, no segments are written for {}", self.stream_path.display());
+                },
+            }
+
+            if let Err(why) = self.buffer.set_video_codec(flv_packet.codec) {
+                log::debug!("{}", why);
+            }
+            self.video_codec = Some(flv_packet.codec);
+            return Ok(())
+        }
+
+        // end of sequence and metadata packets carry no frames
+        if flv_packet.packet_type != VideoPacketType::CodedFrames {
+            return Ok(())
+        }
+
+        // frames of another codec than the sequence header can not be decoded
+        let codec_changed = self.video_codec.map_or(false, |codec| codec != flv_packet.codec);
+        if codec_changed || flv_packet.codec == VideoCodec::Av1 {
             return Ok(())
         }
 
@@ -95,10 +152,7 @@ impl Writer {
             }
 
             if timestamp >= self.next_write {
-                let filename = format!("{}-{}.mpegts", Utc::now().timestamp(), self.keyframe_counter);
-                let path = self.stream_path.join(&filename);
-                self.buffer.write_to_file(&path)?;
-                self.playlist.add_media_segment(filename, keyframe_duration);
+                self.write_segment(keyframe_duration)?;
                 self.next_write += self.write_interval;
                 self.last_keyframe = timestamp;
             }
@@ -106,12 +160,19 @@ impl Writer {
             self.keyframe_counter += 1;
         }
 
-        let video = match self.avc_coder.read_format(avc::Avcc, &payload)? {
-            Some(avc) => self.avc_coder.write_format(avc::AnnexB, avc)?,
-            None => return Ok(())
+        let video = match flv_packet.codec {
+            VideoCodec::Avc => match self.avc_coder.read_format(avc::Avcc, &payload)? {
+                Some(avc) => self.avc_coder.write_format(avc::AnnexB, avc)?,
+                None => return Ok(())
+            },
+            VideoCodec::Hevc => match self.hevc_coder.read_format(hevc::Hvcc, &payload)? {
+                Some(hevc) => self.hevc_coder.write_format(hevc::AnnexB, hevc)?,
+                None => return Ok(())
+            },
+            VideoCodec::Av1 => return Ok(()),
         };
 
-        let comp_time = flv_packet.composition_time as u64;
+        let comp_time = flv_packet.composition_time;
 
         if let Err(why) = self.buffer.push_video(timestamp, comp_time, keyframe, video) {
             log::warn!("Failed to put data into buffer: {:?}", why);
@@ -125,20 +186,32 @@ impl Writer {
     {
         let timestamp: u64 = timestamp.into();
 
-        let flv = flv::tag::AudioData::try_from(bytes).unwrap();
+        let flv = flv::tag::AudioData::try_from(bytes)?;
 
-        if flv.is_sequence_header() {
+        // MP3 has no sequence header, the format is picked up from any packet
+        if self.audio_format != Some(flv.format) {
+            if let Err(why) = self.buffer.set_audio_format(flv.format) {
+                log::warn!("{}, skipping audio of {}", why, self.stream_path.display());
+            }
+            self.audio_format = Some(flv.format);
+        }
+
+        if flv.format == AudioFormat::Aac && flv.is_sequence_header() {
             self.aac_coder.set_asc(flv.body.as_ref())?;
             return Ok(())
         }
 
-        if self.keyframe_counter == 0 {
+        if self.keyframe_counter == 0 || flv.packet_type != AudioPacketType::Raw {
             return Ok(());
         }
 
-        let audio = match self.aac_coder.read_format(aac::Raw, &flv.body)? {
-            Some(raw_aac) => self.aac_coder.write_format(aac::AudioDataTransportStream, raw_aac)?,
-            None => return Ok(())
+        let audio = match flv.format {
+            AudioFormat::Aac => match self.aac_coder.read_format(aac::Raw, &flv.body)? {
+                Some(raw_aac) => self.aac_coder.write_format(aac::AudioDataTransportStream, raw_aac)?,
+                None => return Ok(())
+            },
+            AudioFormat::Mp3 | AudioFormat::Mp3_8k => flv.body.to_vec(),
+            _ => return Ok(()),
         };
 
         if let Err(why) = self.buffer.push_audio(timestamp, audio) {
@@ -148,7 +221,35 @@ impl Writer {
         Ok(())
     }
 
+    fn handle_discontinuity(&mut self) -> Result<()> {
+        log::debug!("Discontinuity in stream {}", self.stream_path.display());
+
+        self.flush()?;
+
+        self.playlist.add_discontinuity();
+        self.next_write = self.write_interval;
+        self.last_keyframe = 0;
+        self.last_timestamp = 0;
+        self.keyframe_counter = 0;
+
+        Ok(())
+    }
+
+    fn write_segment(&mut self, duration: u64) -> Result<()> {
+        let filename = format!("{}-{}.mpegts", Utc::now().timestamp(), self.keyframe_counter);
+        let path = self.stream_path.join(&filename);
+        self.buffer.write_to_file(&path)?;
+        self.playlist.add_media_segment(filename, duration);
+        METRICS.hls_segments_written.inc();
+        METRICS.hls_segment_duration.observe(duration as f64 / 1000.0);
+        Ok(())
+    }
+
     fn handle_packet(&mut self, packet: Packet) -> Result<()> {
+        if let Some(timestamp) = packet.timestamp {
+            self.last_timestamp = timestamp.into();
+        }
+
         match packet.kind {
             PacketType::Video => {
                 self.handle_video(packet.timestamp.unwrap(), packet.as_ref())
@@ -156,6 +257,7 @@ impl Writer {
             PacketType::Audio => {
                 self.handle_audio(packet.timestamp.unwrap(), packet.as_ref())
             }
+            PacketType::Discontinuity => self.handle_discontinuity(),
             _ => Ok(())
         }
     }
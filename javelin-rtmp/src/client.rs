@@ -0,0 +1,231 @@
+use {
+    std::{
+        convert::TryInto,
+        io,
+        net::SocketAddr,
+        time::Duration,
+    },
+    bytes::{Bytes, BytesMut},
+    futures::SinkExt,
+    tokio::{
+        net::TcpStream,
+        stream::StreamExt,
+        time::timeout,
+    },
+    tokio_util::codec::{Framed, BytesCodec},
+    rml_rtmp::{
+        handshake::{Handshake, PeerType, HandshakeProcessResult},
+        sessions::{
+            ClientSession,
+            ClientSessionConfig,
+            ClientSessionResult,
+            ClientSessionEvent,
+            PublishRequestType,
+        },
+        time::RtmpTimestamp,
+    },
+    javelin_types::{Packet, PacketType},
+    crate::{
+        convert,
+        error::Error,
+        proto::Error as ProtocolError,
+        url::UpstreamUrl,
+    },
+};
+
+
+/// Delay before reconnecting, doubled after every failed attempt up to `MAX_BACKOFF`.
+pub const MIN_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+
+/// Outgoing connection to another RTMP server.
+pub struct Client {
+    conn: Framed<TcpStream, BytesCodec>,
+    session: ClientSession,
+    addr: SocketAddr,
+    url: UpstreamUrl,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Connects to the app of `url`, `timeout_after` applies to every response of the server.
+    pub async fn connect(url: &UpstreamUrl, timeout_after: Duration) -> Result<Self, Error> {
+        let addr = (url.host.as_str(), url.port);
+        let stream = timeout(timeout_after, TcpStream::connect(addr)).await??;
+        stream.set_keepalive(Some(Duration::from_secs(30)))?;
+        let addr = stream.peer_addr()?;
+        let mut conn = Framed::new(stream, BytesCodec::new());
+
+        let remaining_bytes = handshake(&mut conn, timeout_after).await?;
+
+        let (session, results) = ClientSession::new(ClientSessionConfig::new())
+            .map_err(|_| ProtocolError::SessionInitializationFailed)?;
+
+        let mut client = Self {
+            conn,
+            session,
+            addr,
+            url: url.clone(),
+            timeout: timeout_after,
+        };
+
+        client.send_results(results).await?;
+
+        if !remaining_bytes.is_empty() {
+            client.handle_input(&remaining_bytes).await?;
+        }
+
+        let result = client.session
+            .request_connection(url.app.clone())
+            .map_err(|_| ProtocolError::RequestRejected)?;
+        client.send_results(vec![result]).await?;
+        client.wait_for(|event| match event {
+            ClientSessionEvent::ConnectionRequestAccepted => true,
+            _ => false,
+        }).await?;
+
+        Ok(client)
+    }
+
+    /// Address of the server.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub async fn publish(&mut self) -> Result<(), Error> {
+        let result = self.session
+            .request_publishing(self.url.stream_key.clone(), PublishRequestType::Live)
+            .map_err(|_| ProtocolError::RequestRejected)?;
+        self.send_results(vec![result]).await?;
+        self.wait_for(|event| match event {
+            ClientSessionEvent::PublishRequestAccepted => true,
+            _ => false,
+        }).await
+    }
+
+    pub async fn play(&mut self) -> Result<(), Error> {
+        let result = self.session
+            .request_playback(self.url.stream_key.clone())
+            .map_err(|_| ProtocolError::RequestRejected)?;
+        self.send_results(vec![result]).await?;
+        self.wait_for(|event| match event {
+            ClientSessionEvent::PlaybackRequestAccepted => true,
+            _ => false,
+        }).await
+    }
+
+    pub async fn send_packet(&mut self, packet: Packet) -> Result<(), Error> {
+        let timestamp = RtmpTimestamp::new(packet.timestamp.map(|v| v.into()).unwrap_or(0));
+
+        let result = match packet.kind {
+            PacketType::Meta => {
+                let metadata = match packet.try_into() {
+                    Ok(metadata) => convert::into_metadata(metadata),
+                    Err(_) => return Ok(()),
+                };
+                self.session.publish_metadata(&metadata)
+            },
+            PacketType::Video => self.session.publish_video_data(packet.payload, timestamp, false),
+            PacketType::Audio => self.session.publish_audio_data(packet.payload, timestamp, false),
+            PacketType::Discontinuity => return Ok(()),
+        };
+
+        let result = result.map_err(|_| ProtocolError::InvalidInput)?;
+        self.send_results(vec![result]).await?;
+
+        Ok(())
+    }
+
+    /// Waits for data of the server without a timeout.
+    ///
+    /// Can be cancelled without losing data, unlike [`handle_input`](Client::handle_input).
+    pub async fn receive(&mut self) -> Result<BytesMut, Error> {
+        self.conn.try_next().await?.ok_or_else(connection_closed)
+    }
+
+    pub async fn receive_timeout(&mut self) -> Result<BytesMut, Error> {
+        timeout(self.timeout, self.receive()).await?
+    }
+
+    /// Answers the server where required and returns raised events.
+    pub async fn handle_input(&mut self, data: &[u8]) -> Result<Vec<ClientSessionEvent>, Error> {
+        let results = self.session
+            .handle_input(data)
+            .map_err(|_| ProtocolError::InvalidInput)?;
+        self.send_results(results).await
+    }
+
+    /// Handles responses of the server until `accepted` matches one of its events.
+    async fn wait_for<F>(&mut self, accepted: F) -> Result<(), Error>
+        where F: Fn(&ClientSessionEvent) -> bool
+    {
+        loop {
+            let data = self.receive_timeout().await?;
+
+            for event in self.handle_input(&data).await? {
+                match event {
+                    ClientSessionEvent::ConnectionRequestRejected { description } => {
+                        log::warn!("{} rejected the connection: {}", self.url, description);
+                        return Err(ProtocolError::RequestRejected.into());
+                    },
+                    event if accepted(&event) => return Ok(()),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    async fn send_results(&mut self, results: Vec<ClientSessionResult>) -> Result<Vec<ClientSessionEvent>, Error> {
+        let mut events = Vec::new();
+
+        for result in results {
+            match result {
+                ClientSessionResult::OutboundResponse(packet) => {
+                    self.conn.send(Bytes::from(packet.bytes)).await?;
+                },
+                ClientSessionResult::RaisedEvent(event) => events.push(event),
+                ClientSessionResult::UnhandleableMessageReceived(_) => (),
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+
+/// Returns bytes received after the handshake completed.
+async fn handshake(conn: &mut Framed<TcpStream, BytesCodec>, timeout_after: Duration) -> Result<Vec<u8>, Error> {
+    let mut handshake = Handshake::new(PeerType::Client);
+    let p0_and_p1 = handshake
+        .generate_outbound_p0_and_p1()
+        .map_err(|_| ProtocolError::HandshakeFailed)?;
+    conn.send(Bytes::from(p0_and_p1)).await?;
+
+    loop {
+        let data = timeout(timeout_after, conn.try_next())
+            .await??
+            .ok_or_else(connection_closed)?;
+        let result = handshake
+            .process_bytes(&data)
+            .map_err(|_| ProtocolError::HandshakeFailed)?;
+
+        match result {
+            HandshakeProcessResult::InProgress { response_bytes } => {
+                if !response_bytes.is_empty() {
+                    conn.send(Bytes::from(response_bytes)).await?;
+                }
+            },
+            HandshakeProcessResult::Completed { response_bytes, remaining_bytes } => {
+                if !response_bytes.is_empty() {
+                    conn.send(Bytes::from(response_bytes)).await?;
+                }
+                return Ok(remaining_bytes);
+            },
+        }
+    }
+}
+
+fn connection_closed() -> Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, "Server closed the connection").into()
+}
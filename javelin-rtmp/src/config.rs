@@ -4,39 +4,94 @@ use {
         net::SocketAddr,
         time::Duration,
     },
-    serde::Deserialize,
+    serde::{Serialize, Deserialize},
+    crate::url::UpstreamUrl,
 };
 
 
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default = "default_addr")]
     pub addr: SocketAddr,
 
-    #[serde(default)]
-    pub stream_keys: HashMap<String, String>, // TODO: move to database
-
     #[serde(default = "default_conn_timeout")]
     pub connection_timeout: Duration,
 
+    /// Viewers that can not keep up with a stream for longer than this are disconnected.
+    #[serde(default = "default_max_viewer_lag")]
+    pub max_viewer_lag: Duration,
+
     #[cfg(feature = "rtmps")]
     #[serde(default)]
     pub tls: tls::Config,
+
+    /// Accepted without the `rtmps` feature, so a config file works for every build
+    #[cfg(not(feature = "rtmps"))]
+    #[serde(default, skip_serializing)]
+    tls: Option<serde::de::IgnoredAny>,
+
+    /// Sessions pushed to other RTMP servers
+    #[serde(default)]
+    pub relay: Vec<RelayConfig>,
+
+    /// Remote streams published as local sessions
+    #[serde(default)]
+    pub pull: Vec<PullConfig>,
+
+    /// Stream keys of older versions, loaded by the `file` database backend
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub stream_keys: HashMap<String, String>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             addr: default_addr(),
-            stream_keys: HashMap::new(),
             connection_timeout: default_conn_timeout(),
-            #[cfg(feature = "rtmps")]
+            max_viewer_lag: default_max_viewer_lag(),
             tls: Default::default(),
+            relay: Vec::new(),
+            pull: Vec::new(),
+            stream_keys: HashMap::new(),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayConfig {
+    /// Session name, `live` includes every `live/<stream>` as well
+    pub app: String,
+
+    /// `rtmp://<host>[:<port>]/<app>/<stream key>`
+    pub urls: Vec<UpstreamUrl>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PullConfig {
+    /// Name of the local session, e.g. `live/mystream`
+    pub name: String,
+
+    /// `rtmp://<host>[:<port>]/<app>/<stream>` to play
+    pub url: UpstreamUrl,
+
+    /// Stream key of a user that is allowed to publish to `name`
+    pub key: String,
+
+    /// Only pull while the session is watched, instead of all the time
+    #[serde(default)]
+    pub on_demand: bool,
+
+    /// On demand sessions are stopped once they had no viewers for this long
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: Duration,
+}
+
+
 fn default_addr() -> SocketAddr {
     SocketAddr::from(([0, 0, 0, 0], 1935))
 }
@@ -45,6 +100,14 @@ fn default_conn_timeout() -> Duration {
     Duration::from_secs(5)
 }
 
+fn default_max_viewer_lag() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_idle_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
 
 #[cfg(feature = "rtmps")]
 mod tls {
@@ -55,11 +118,12 @@ mod tls {
             io::Read,
             net::SocketAddr,
         },
-        serde::Deserialize,
+        serde::{Serialize, Deserialize},
         anyhow::{Result, Context},
     };
 
-    #[derive(Debug, Clone, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
     pub struct Config {
         #[serde(default)]
         pub enabled: bool,
@@ -23,6 +23,9 @@ pub enum Error {
     #[error("Failed to join session")]
     SessionJoinFailed,
 
+    #[error("Failed to leave session")]
+    SessionLeaveFailed,
+
     #[error("Failed to send to session")]
     SessionSendFailed,
 
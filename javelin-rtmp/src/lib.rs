@@ -1,12 +1,19 @@
 mod convert;
 mod proto;
+mod stream_name;
 mod peer;
 mod config;
+mod relay;
+mod pull;
+mod client;
+mod url;
 pub mod error;
 pub mod service;
 
 
 pub use self::{
+    config::{Config, RelayConfig, PullConfig},
     error::Error,
+    url::{UpstreamUrl, UrlError},
     service::Service,
 };
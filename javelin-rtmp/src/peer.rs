@@ -1,4 +1,5 @@
 use {
+    std::{net::SocketAddr, sync::Arc},
     futures::SinkExt,
     tokio::{
         prelude::*,
@@ -8,11 +9,16 @@ use {
     },
     tokio_util::codec::{Framed, BytesCodec},
     javelin_types::{Packet, PacketType},
-    javelin_core::session::{self, Message, ManagerMessage},
+    javelin_core::{
+        session::{self, Message, ManagerMessage},
+        metrics::{METRICS, GaugeGuard, app_label},
+        playback::Authorizer,
+        shutdown::Shutdown,
+    },
     crate::{
         config::Config,
         error::Error,
-        proto::{Protocol, Event},
+        proto::{Protocol, Event, Error as ProtocolError},
     },
 };
 
@@ -22,7 +28,7 @@ type ReturnQueue<P> = (mpsc::Sender<P>, mpsc::Receiver<P>);
 enum State {
     Initializing,
     Publishing(session::Handle),
-    Playing(session::Handle, session::Watcher),
+    Playing(session::Handle, Box<session::Watcher>),
     Disconnecting,
 }
 
@@ -31,6 +37,7 @@ pub struct Peer<S>
     where S: AsyncRead + AsyncWrite + Unpin
 {
     id: u64,
+    addr: SocketAddr,
     bytes_stream: Framed<S, BytesCodec>,
     session_manager: session::ManagerHandle,
     return_queue: ReturnQueue<Packet>,
@@ -38,25 +45,51 @@ pub struct Peer<S>
     config: Config,
     app_name: Option<String>,
     state: State,
+    active_gauge: Option<GaugeGuard>,
+    shutdown: Shutdown,
 }
 
 impl<S> Peer<S>
     where S: AsyncRead + AsyncWrite + Unpin
 {
-    pub fn new(id: u64, stream: S, session_manager: session::ManagerHandle, config: Config) -> Self {
+    pub fn new(
+        id: u64,
+        stream: S,
+        addr: SocketAddr,
+        session_manager: session::ManagerHandle,
+        authorizer: Arc<Authorizer>,
+        shutdown: Shutdown,
+        config: Config,
+    ) -> Self {
         Self {
             id,
+            addr,
             bytes_stream: Framed::new(stream, BytesCodec::new()),
             session_manager,
             return_queue: mpsc::channel(64),
-            proto: Protocol::new(),
+            proto: Protocol::new(authorizer),
             config,
             app_name: None,
             state: State::Initializing,
+            active_gauge: None,
+            shutdown,
         }
     }
 
     pub async fn run(mut self) -> Result<(), Error> {
+        let res = self.run_loop().await;
+
+        // release the session even if the connection failed or timed out
+        if res.is_err() {
+            if let Err(why) = self.disconnect() {
+                log::error!("Failed to disconnect client {}: {}", self.id, why);
+            }
+        }
+
+        res
+    }
+
+    async fn run_loop(&mut self) -> Result<(), Error> {
         loop {
             while let Ok(packet) = self.return_queue.1.try_recv() {
                if self.handle_return_packet(packet).await.is_err() {
@@ -66,10 +99,17 @@ impl<S> Peer<S>
 
             match &mut self.state {
                 State::Initializing | State::Publishing(_) => {
-                    let val = self.bytes_stream.try_next();
-                    match timeout(self.config.connection_timeout, val).await? {
-                        Ok(Some(data)) => {
-                            for event in self.proto.handle_bytes(&data).unwrap() {
+                    let val = timeout(self.config.connection_timeout, self.bytes_stream.try_next());
+                    let res = tokio::select! {
+                        res = val => Some(res?),
+                        _ = self.shutdown.wait() => None,
+                    };
+
+                    match res {
+                        None => self.shutdown_disconnect().await?,
+                        Some(Ok(Some(data))) => {
+                            METRICS.bytes_received.inc_by(data.len() as i64);
+                            for event in self.handle_bytes(&data)? {
                                 self.handle_event(event).await?;
                             }
                         },
@@ -78,10 +118,28 @@ impl<S> Peer<S>
                 },
                 State::Playing(_, watcher) => {
                     use sync::broadcast::RecvError;
-                    match watcher.recv().await {
-                        Ok(packet ) => self.send_back(packet).await?,
-                        Err(RecvError::Closed) => self.disconnect()?,
-                        Err(_) => ()
+                    let res = tokio::select! {
+                        res = watcher.recv() => Some(res),
+                        _ = self.shutdown.wait() => None,
+                    };
+
+                    match res {
+                        None => self.shutdown_disconnect().await?,
+                        Some(Ok(packet)) => self.send_back(packet).await?,
+                        Some(Err(RecvError::Closed)) => self.disconnect()?,
+                        Some(Err(RecvError::Lagged(count))) => {
+                            METRICS.dropped_packets.inc_by(count as i64);
+                            let behind_for = watcher.behind_for().unwrap_or_default();
+
+                            if behind_for > self.config.max_viewer_lag {
+                                log::warn!("Client {} lagged behind for {:?}, disconnecting", self.id, behind_for);
+                                METRICS.slow_viewers_disconnected.inc();
+                                self.disconnect()?
+                            } else {
+                                log::warn!("Client {} lagged behind by {} packets, skipping to next keyframe", self.id, count);
+                                METRICS.lagging_viewers.inc();
+                            }
+                        },
                     }
                 }
                 State::Disconnecting => {
@@ -97,15 +155,29 @@ impl<S> Peer<S>
             PacketType::Meta => self.proto.pack_metadata(packet)?,
             PacketType::Video => self.proto.pack_video(packet)?,
             PacketType::Audio => self.proto.pack_audio(packet)?,
+            PacketType::Discontinuity => return Ok(()),
         };
         let duration = self.config.connection_timeout;
+        METRICS.bytes_sent.inc_by(bytes.len() as i64);
         let res = timeout(duration, self.bytes_stream.send(bytes.into())).await?;
         Ok(res?)
     }
 
+    fn handle_bytes(&mut self, data: &[u8]) -> Result<Vec<Event>, Error> {
+        self.proto.handle_bytes(data).map_err(|why| {
+            match why {
+                ProtocolError::HandshakeFailed => METRICS.handshake_failures.inc(),
+                ProtocolError::PlaybackUnauthorized(_) => METRICS.connections_rejected.inc(),
+                _ => (),
+            }
+            why.into()
+        })
+    }
+
     async fn handle_event(&mut self, event: Event) -> Result<(), Error> {
         match event {
             Event::ReturnData(data) => {
+                METRICS.bytes_sent.inc_by(data.len() as i64);
                 self.bytes_stream.send(data).await.expect("Failed to return data");
             },
             Event::SendPacket(packet) => {
@@ -116,66 +188,113 @@ impl<S> Peer<S>
                 }
             },
             Event::AcquireSession { app_name, stream_key } => {
-                self.app_name = Some(app_name.clone());
                 let (request, response) = oneshot::channel();
                 self.session_manager
-                    .send(ManagerMessage::CreateSession((app_name, stream_key, request)))
-                    .map_err(|_| Error::SessionCreationFailed)?;
-                let session_sender = response.await
+                    .send(ManagerMessage::CreateSession((app_name, stream_key, self.addr, request)))
                     .map_err(|_| Error::SessionCreationFailed)?;
+                let session_sender = response.await.map_err(|_| {
+                    METRICS.connections_rejected.inc();
+                    Error::SessionCreationFailed
+                })?;
+                let gauge = METRICS.active_publishers.with_label_values(&[app_label(session_sender.name())]);
+                self.active_gauge = Some(GaugeGuard::new(gauge));
+                self.app_name = Some(session_sender.name().to_string());
                 self.state = State::Publishing(session_sender);
             },
-            Event::JoinSession { app_name, .. } => {
+            Event::JoinSession { app_name, legacy_name } => {
+                let app_name = self.resolve_play_name(app_name, legacy_name).await;
                 let (request, response) = oneshot::channel();
                 self.session_manager
-                    .send(ManagerMessage::JoinSession((app_name, request)))
+                    .send(ManagerMessage::JoinSession((app_name, self.addr, request)))
                     .map_err(|_| Error::SessionJoinFailed)?;
 
                 match response.await {
                     Ok((session_sender, session_receiver)) => {
-                        self.state = State::Playing(session_sender, session_receiver);
+                        let gauge = METRICS.active_viewers.with_label_values(&[app_label(session_sender.name())]);
+                        self.active_gauge = Some(GaugeGuard::new(gauge));
+                        self.app_name = Some(session_sender.name().to_string());
+                        self.state = State::Playing(session_sender, Box::new(session_receiver));
                     }
-                    Err(_) => self.disconnect()?
-                }
-            },
-            Event::SendInitData { .. } => {
-                // TODO: better initialization handling
-                if let State::Playing(session, _) = &mut self.state {
-                    let (request, response) = oneshot::channel();
-                    session
-                        .send(Message::GetInitData(request))
-                        .map_err(|_| Error::SessionSendFailed)?;
-
-                    if let Ok((Some(meta), Some(video), Some(audio))) = response.await {
-                        self.send_back(meta).await?;
-                        self.send_back(video).await?;
-                        self.send_back(audio).await?;
+                    Err(_) => {
+                        METRICS.connections_rejected.inc();
+                        self.disconnect()?
                     }
                 }
-            }
+            },
             Event::ReleaseSession | Event::LeaveSession => self.disconnect()?,
         }
 
         Ok(())
     }
 
+    /// Fall back to the legacy name if only a session with that name exists.
+    async fn resolve_play_name(&self, app_name: String, legacy_name: Option<String>) -> String {
+        let legacy_name = match legacy_name {
+            Some(legacy_name) => legacy_name,
+            None => return app_name,
+        };
+
+        if !self.session_exists(&app_name).await && self.session_exists(&legacy_name).await {
+            legacy_name
+        } else {
+            app_name
+        }
+    }
+
+    async fn session_exists(&self, app_name: &str) -> bool {
+        let (request, response) = oneshot::channel();
+        if self.session_manager.send(ManagerMessage::GetSession((app_name.to_string(), request))).is_err() {
+            return false;
+        }
+
+        matches!(response.await, Ok(Some(_)))
+    }
+
     async fn send_back(&mut self, packet: Packet) -> Result<(), Error> {
         self.return_queue.0
             .send_timeout(packet, self.config.connection_timeout).await
             .map_err(|_| Error::ReturnPacketFailed(self.id))
     }
 
+    async fn shutdown_disconnect(&mut self) -> Result<(), Error> {
+        log::info!("Server is shutting down, disconnecting client {}", self.id);
+
+        match self.proto.pack_close_status() {
+            Ok(Some(bytes)) => {
+                // sending flushes the message before the connection is dropped
+                let res = timeout(self.config.connection_timeout, self.bytes_stream.send(bytes.into())).await;
+                if !matches!(res, Ok(Ok(()))) {
+                    log::debug!("Failed to notify client {} about the shutdown", self.id);
+                }
+            },
+            Ok(None) => (),
+            Err(why) => log::debug!("Failed to create close status for client {}: {}", self.id, why),
+        }
+
+        self.disconnect()
+    }
+
     fn disconnect(&mut self) -> Result<(), Error> {
-        if let State::Publishing(session) = &mut self.state {
-            let app_name = self.app_name.clone().unwrap();
-            session.send(Message::Disconnect).map_err(|_| Error::SessionSendFailed)?;
+        match &mut self.state {
+            State::Publishing(session) => {
+                let app_name = self.app_name.clone().unwrap();
+                session.send(Message::Disconnect).map_err(|_| Error::SessionSendFailed)?;
 
-            self.session_manager
-                .send(ManagerMessage::ReleaseSession(app_name))
-                .map_err(|_| Error::SessionReleaseFailed)?;
+                self.session_manager
+                    .send(ManagerMessage::ReleaseSession((app_name, session.id())))
+                    .map_err(|_| Error::SessionReleaseFailed)?;
+            },
+            State::Playing(..) => {
+                let app_name = self.app_name.clone().unwrap();
+                self.session_manager
+                    .send(ManagerMessage::LeaveSession(app_name))
+                    .map_err(|_| Error::SessionLeaveFailed)?;
+            },
+            _ => (),
         }
 
         self.state = State::Disconnecting;
+        self.active_gauge = None;
 
         Ok(())
     }
@@ -188,3 +307,170 @@ impl<S> Drop for Peer<S>
         log::info!("Client {} disconnected", self.id);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::time::Duration,
+        chrono::{DateTime, Utc},
+        tokio::net::{TcpListener, TcpStream},
+        javelin_types::{
+            async_trait,
+            models::{
+                UserRepository, User, StreamKey, StreamPermission,
+                SessionHistory, SessionRecord, HistoryFilter, Error as DbError,
+            },
+        },
+        javelin_core::{
+            config,
+            playback::PlaybackConfig,
+            session::{Manager, config::Config as SessionConfig},
+            shutdown,
+            webhook::WebhookConfig,
+        },
+        crate::stream_name,
+        super::*,
+    };
+
+    const STREAM_NAME: &str = "live/test";
+    const PUBLISHER: &str = "alice";
+    const KEY: &str = "secret";
+
+    /// A single user that may publish to every stream with a plaintext key.
+    #[derive(Clone)]
+    struct Users;
+
+    #[async_trait]
+    impl UserRepository for Users {
+        async fn user_by_name(&self, name: &str) -> Result<Option<User>, DbError> {
+            let key = StreamKey {
+                name: "default".to_string(),
+                hash: KEY.to_string(),
+                expires_at: None,
+                last_used_at: None,
+            };
+
+            Ok(Some(User { name: name.to_string(), keys: vec![key] }))
+        }
+
+        async fn users(&self) -> Result<Vec<User>, DbError> {
+            Ok(Vec::new())
+        }
+
+        async fn delete_user(&self, _name: &str) -> Result<bool, DbError> {
+            Ok(false)
+        }
+
+        async fn put_key(&self, _user_name: &str, _key: &StreamKey) -> Result<(), DbError> {
+            Ok(())
+        }
+
+        async fn revoke_key(&self, _user_name: &str, _key_name: &str) -> Result<bool, DbError> {
+            Ok(false)
+        }
+
+        async fn touch_key(&self, _user_name: &str, _key_name: &str, _used_at: DateTime<Utc>) -> Result<bool, DbError> {
+            Ok(true)
+        }
+
+        async fn permissions(&self, _user_name: &str) -> Result<Vec<StreamPermission>, DbError> {
+            Ok(Vec::new())
+        }
+
+        async fn stream_publishers(&self, _app: &str, _stream: &str) -> Result<Vec<String>, DbError> {
+            Ok(vec![PUBLISHER.to_string()])
+        }
+
+        async fn allow_publish(&self, _user_name: &str, _permission: &StreamPermission) -> Result<(), DbError> {
+            Ok(())
+        }
+
+        async fn disallow_publish(&self, _user_name: &str, _permission: &StreamPermission) -> Result<bool, DbError> {
+            Ok(false)
+        }
+    }
+
+    #[async_trait]
+    impl SessionHistory for Users {
+        async fn record_session(&self, _record: &SessionRecord) -> Result<(), DbError> {
+            Ok(())
+        }
+
+        async fn sessions(&self, _filter: &HistoryFilter) -> Result<Vec<SessionRecord>, DbError> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn start_manager() -> session::ManagerHandle {
+        let (_, session_updates) = config::updates(SessionConfig::default());
+        let (_, webhook_updates) = config::updates(WebhookConfig::default());
+        let manager = Manager::new(Users, SessionConfig::default(), WebhookConfig::default()).unwrap();
+        let handle = manager.handle();
+        tokio::spawn(manager.run(session_updates, webhook_updates));
+        handle
+    }
+
+    async fn create_session(manager: &session::ManagerHandle, name: &str, addr: SocketAddr) -> Option<session::Handle> {
+        let (request, response) = oneshot::channel();
+        let message = ManagerMessage::CreateSession((name.to_string(), KEY.to_string(), addr, request));
+        manager.send(message).ok()?;
+        response.await.ok()
+    }
+
+    /// A peer with a connected client that never sends anything.
+    async fn connected_peer(manager: &session::ManagerHandle) -> (Peer<TcpStream>, TcpStream, shutdown::Trigger) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, addr) = listener.accept().await.unwrap();
+
+        let (trigger, shutdown) = shutdown::channel();
+        let config = Config { connection_timeout: Duration::from_millis(50), ..Config::default() };
+        let authorizer = Arc::new(Authorizer::new(PlaybackConfig::default()));
+        let peer = Peer::new(0, stream, addr, manager.clone(), authorizer, shutdown, config);
+
+        (peer, client, trigger)
+    }
+
+    #[tokio::test]
+    async fn publisher_can_republish_after_timeout() {
+        let manager = start_manager();
+        let (mut peer, _client, _trigger) = connected_peer(&manager).await;
+        let addr = peer.addr;
+
+        let session = create_session(&manager, STREAM_NAME, addr).await.expect("first publish rejected");
+        peer.app_name = Some(STREAM_NAME.to_string());
+        peer.state = State::Publishing(session);
+
+        let res = peer.run().await;
+        assert!(matches!(res, Err(Error::ConnectionTimeout(_))));
+
+        assert!(create_session(&manager, STREAM_NAME, addr).await.is_some(), "republish rejected");
+    }
+
+    #[tokio::test]
+    async fn legacy_play_finds_legacy_session() {
+        let manager = start_manager();
+        let (peer, _client, _trigger) = connected_peer(&manager).await;
+
+        let (name, _) = stream_name::publish_target(PUBLISHER, KEY).unwrap();
+        let _session = create_session(&manager, &name, peer.addr).await.expect("legacy publish rejected");
+
+        let target = stream_name::play_target(PUBLISHER, "stream").unwrap();
+        let name = peer.resolve_play_name(target.name, target.legacy_name).await;
+        assert_eq!(name, PUBLISHER);
+    }
+
+    #[tokio::test]
+    async fn play_prefers_app_stream_session() {
+        let manager = start_manager();
+        let (peer, _client, _trigger) = connected_peer(&manager).await;
+
+        let _legacy = create_session(&manager, "live", peer.addr).await.unwrap();
+        let _session = create_session(&manager, STREAM_NAME, peer.addr).await.unwrap();
+
+        let target = stream_name::play_target("live", "test").unwrap();
+        let name = peer.resolve_play_name(target.name, target.legacy_name).await;
+        assert_eq!(name, STREAM_NAME);
+    }
+}
@@ -1,17 +1,23 @@
 use {
     std::{
+        collections::HashMap,
         convert::{TryFrom, TryInto},
         rc::Rc,
+        sync::Arc,
     },
     thiserror::Error,
     bytes::Bytes,
+    rml_amf0::Amf0Value,
     rml_rtmp::{
         sessions::{ServerSession, ServerSessionConfig, ServerSessionResult, ServerSessionEvent},
         handshake::{Handshake, PeerType, HandshakeProcessResult},
+        chunk_io::ChunkSerializer,
+        messages::RtmpMessage,
         time::RtmpTimestamp,
     },
     javelin_types::{Packet, PacketType},
-    crate::convert,
+    javelin_core::playback::Authorizer,
+    crate::{convert, stream_name},
 };
 
 
@@ -37,6 +43,12 @@ pub enum Error {
 
     #[error("Application name cannot be empty")]
     EmptyAppName,
+
+    #[error("Invalid stream name {0}")]
+    InvalidStreamName(String),
+
+    #[error("Not authorized to play {0}")]
+    PlaybackUnauthorized(String),
 }
 
 
@@ -44,8 +56,7 @@ pub enum Event {
     ReturnData(Bytes),
     SendPacket(Packet),
     AcquireSession { app_name: String, stream_key: String },
-    JoinSession { app_name: String, stream_key: String },
-    SendInitData { app_name: String },
+    JoinSession { app_name: String, legacy_name: Option<String> },
     ReleaseSession,
     LeaveSession,
 }
@@ -65,11 +76,21 @@ pub struct Protocol {
     return_queue: Vec<Event>,
     handshake: Handshake,
     session: Option<ServerSession>,
+    /// Size of the chunks sent to the client, as announced by the session
+    chunk_size: u32,
+    authorizer: Arc<Authorizer>,
 }
 
 impl Protocol {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(authorizer: Arc<Authorizer>) -> Self {
+        Self {
+            state: State::HandshakePending,
+            return_queue: Vec::with_capacity(8),
+            handshake: Handshake::new(PeerType::Server),
+            session: None,
+            chunk_size: 0,
+            authorizer,
+        }
     }
 
     pub fn handle_bytes(&mut self, input: &[u8]) -> Result<Vec<Event>, Error> {
@@ -123,6 +144,7 @@ impl Protocol {
 
     fn initialize_session(&mut self) -> Result<(), Error> {
         let config = ServerSessionConfig::new();
+        self.chunk_size = config.chunk_size;
         let (session, results) = ServerSession::new(config)
             .map_err( |_| Error::SessionInitializationFailed)?;
         self.session = Some(session);
@@ -175,6 +197,43 @@ impl Protocol {
             .map(|v| v.bytes)
     }
 
+    /// Status message that tells the client the server closes the connection.
+    pub fn pack_close_status(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let (stream_id, code) = match self.state {
+            State::Publishing => (0, "NetConnection.Connect.Closed"),
+            State::Playing { stream_id } => (stream_id, "NetStream.Play.Stop"),
+            _ => return Ok(None),
+        };
+
+        let mut properties = HashMap::new();
+        properties.insert("level".to_string(), Amf0Value::Utf8String("status".to_string()));
+        properties.insert("code".to_string(), Amf0Value::Utf8String(code.to_string()));
+        properties.insert("description".to_string(), Amf0Value::Utf8String("Server is shutting down".to_string()));
+
+        let message = RtmpMessage::Amf0Command {
+            command_name: "onStatus".to_string(),
+            transaction_id: 0.0,
+            command_object: Amf0Value::Null,
+            additional_arguments: vec![Amf0Value::Object(properties)],
+        };
+
+        let timestamp = RtmpTimestamp::new(0);
+        let payload = message
+            .into_message_payload(timestamp, stream_id)
+            .map_err(|_| Error::InvalidInput)?;
+
+        // the session does not expose its serializer, so this one has to use the same chunk size
+        let mut serializer = ChunkSerializer::new();
+        serializer
+            .set_max_chunk_size(self.chunk_size, timestamp)
+            .map_err(|_| Error::InvalidInput)?;
+        let packet = serializer
+            .serialize(&payload, true, false)
+            .map_err(|_| Error::InvalidInput)?;
+
+        Ok(Some(packet.bytes))
+    }
+
     fn handle_results(&mut self, results: Vec<ServerSessionResult>) -> Result<(), Error> {
         for result in results {
             match result {
@@ -203,6 +262,7 @@ impl Protocol {
                 self.accept_request(request_id)?;
             },
             PublishStreamRequested { request_id, app_name, stream_key, ..} => {
+                let (app_name, stream_key) = stream_name::publish_target(&app_name, &stream_key)?;
                 self.emit(Event::AcquireSession { app_name, stream_key });
                 self.accept_request(request_id)?;
                 self.state = State::Publishing;
@@ -212,9 +272,19 @@ impl Protocol {
                 self.state = State::Finished;
             },
             PlayStreamRequested { request_id, app_name, stream_key, stream_id, .. } => {
-                self.emit(Event::JoinSession { app_name: app_name.clone(), stream_key });
+                let target = stream_name::play_target(&app_name, &stream_key)?;
+                let query = &target.query;
+                let legacy_name = target.legacy_name
+                    .filter(|legacy_name| self.authorizer.authorize(legacy_name, query));
+
+                let (app_name, legacy_name) = match legacy_name {
+                    _ if self.authorizer.authorize(&target.name, query) => (target.name, legacy_name),
+                    Some(legacy_name) => (legacy_name, None),
+                    None => return Err(Error::PlaybackUnauthorized(target.name)),
+                };
+
+                self.emit(Event::JoinSession { app_name, legacy_name });
                 self.accept_request(request_id)?;
-                self.emit(Event::SendInitData { app_name });
                 self.state = State::Playing { stream_id };
             },
             PlayStreamFinished { .. } => {
@@ -256,14 +326,3 @@ impl Protocol {
         self.session.as_mut().ok_or(Error::SessionNotInitialized)
     }
 }
-
-impl Default for Protocol {
-    fn default() -> Self {
-        Self {
-            state: State::HandshakePending,
-            return_queue: Vec::with_capacity(8),
-            handshake: Handshake::new(PeerType::Server),
-            session: None,
-        }
-    }
-}
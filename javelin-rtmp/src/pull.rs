@@ -0,0 +1,246 @@
+use {
+    std::{
+        collections::{HashMap, HashSet},
+        convert::TryFrom,
+        time::{Duration, Instant},
+    },
+    bytes::Bytes,
+    tokio::{
+        sync::oneshot,
+        time::{delay_for, interval},
+    },
+    rml_rtmp::sessions::ClientSessionEvent,
+    javelin_types::{Packet, PacketType},
+    javelin_core::{
+        session::{self, Event, EventKind, ManagerMessage, Message},
+        shutdown::Shutdown,
+        config::Updates,
+    },
+    crate::{
+        client::{Client, MIN_BACKOFF, MAX_BACKOFF},
+        config::{Config as RtmpConfig, PullConfig},
+        convert,
+        error::Error,
+    },
+};
+
+
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+
+/// Publishes remote streams as local sessions.
+pub struct Pull {
+    session_manager: session::ManagerHandle,
+    /// Dropping the sender stops the source.
+    sources: HashMap<PullConfig, oneshot::Sender<()>>,
+}
+
+impl Pull {
+    pub fn new(session_manager: session::ManagerHandle) -> Self {
+        Self {
+            session_manager,
+            sources: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self, mut shutdown: Shutdown, mut updates: Updates<RtmpConfig>) {
+        self.configure(&updates.current(), &shutdown);
+
+        loop {
+            tokio::select! {
+                Some(config) = updates.changed() => self.configure(&config, &shutdown),
+                _ = shutdown.wait() => break,
+            }
+        }
+    }
+
+    /// Sources that were removed or changed are stopped, including their session.
+    fn configure(&mut self, config: &RtmpConfig, shutdown: &Shutdown) {
+        let sources = config.pull.iter().cloned().collect::<HashSet<_>>();
+
+        self.sources.retain(|source, _| sources.contains(source));
+
+        for source_config in sources {
+            if self.sources.contains_key(&source_config) {
+                continue;
+            }
+
+            let requests = if source_config.on_demand {
+                let (trigger, trigger_handle) = session::trigger_channel();
+                if self.session_manager.send(ManagerMessage::RegisterTrigger(EventKind::SessionRequested, trigger)).is_err() {
+                    log::error!("Failed to register pull trigger");
+                    return;
+                }
+                Some(trigger_handle)
+            } else {
+                None
+            };
+
+            log::info!("Pulling {} from {}", source_config.name, source_config.url);
+
+            let (stop, stopped) = oneshot::channel();
+            let source = Source {
+                config: source_config.clone(),
+                session_manager: self.session_manager.clone(),
+                connection_timeout: config.connection_timeout,
+                backoff: MIN_BACKOFF,
+            };
+            tokio::spawn(source.run(requests, stopped, shutdown.clone()));
+            self.sources.insert(source_config, stop);
+        }
+    }
+}
+
+
+/// Releases the session once pulling stopped for any reason.
+struct Publishing {
+    handle: session::Handle,
+    session_manager: session::ManagerHandle,
+}
+
+impl Drop for Publishing {
+    fn drop(&mut self) {
+        let _ = self.handle.send(Message::Disconnect);
+        let name = self.handle.name().to_string();
+        let _ = self.session_manager.send(ManagerMessage::ReleaseSession((name, self.handle.id())));
+    }
+}
+
+
+struct Source {
+    config: PullConfig,
+    session_manager: session::ManagerHandle,
+    connection_timeout: Duration,
+    backoff: Duration,
+}
+
+impl Source {
+    /// Always-on sources are pulled until they are stopped, on demand sources
+    /// wait for `requests` and go back to waiting once idle or failed.
+    async fn run(
+        mut self,
+        mut requests: Option<session::TriggerHandle>,
+        mut stopped: oneshot::Receiver<()>,
+        mut shutdown: Shutdown,
+    ) {
+        loop {
+            if let Some(requests) = &mut requests {
+                let event = tokio::select! {
+                    event = requests.recv() => match event {
+                        Some(event) => event,
+                        None => return,
+                    },
+                    _ = &mut stopped => return,
+                    _ = shutdown.wait() => return,
+                };
+
+                match event {
+                    Event::SessionRequested(name) if name == self.config.name => {
+                        log::info!("Session {} was requested, connecting to {}", name, self.config.url);
+                    },
+                    _ => continue,
+                }
+            }
+
+            let res = tokio::select! {
+                res = self.pull() => res,
+                _ = &mut stopped => return,
+                _ = shutdown.wait() => return,
+            };
+
+            match res {
+                Ok(()) => {
+                    log::info!("Session {} had no viewers, stopped pulling from {}", self.config.name, self.config.url);
+                    continue;
+                },
+                Err(why) if self.config.on_demand => {
+                    log::warn!("Pulling {} from {} failed: {}", self.config.name, self.config.url, why);
+                    continue;
+                },
+                Err(why) => {
+                    log::warn!("Pulling {} from {} failed, retrying in {:?}: {}", self.config.name, self.config.url, self.backoff, why);
+                },
+            }
+
+            tokio::select! {
+                _ = delay_for(self.backoff) => (),
+                _ = &mut stopped => return,
+                _ = shutdown.wait() => return,
+            }
+
+            self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Only returns without an error once an on demand session is idle.
+    async fn pull(&mut self) -> Result<(), Error> {
+        let mut client = Client::connect(&self.config.url, self.connection_timeout).await?;
+        client.play().await?;
+
+        let (request, response) = oneshot::channel();
+        let name = self.config.name.clone();
+        let key = self.config.key.clone();
+        self.session_manager
+            .send(ManagerMessage::CreateSession((name, key, client.addr(), request)))
+            .map_err(|_| Error::SessionCreationFailed)?;
+        let handle = response.await.map_err(|_| Error::SessionCreationFailed)?;
+        let session = Publishing { handle, session_manager: self.session_manager.clone() };
+
+        log::info!("Publishing {} from {}", session.handle.name(), self.config.url);
+        self.backoff = MIN_BACKOFF;
+
+        let mut idle_check = interval(IDLE_CHECK_INTERVAL);
+        let mut idle_since = None;
+
+        loop {
+            let data = tokio::select! {
+                res = client.receive_timeout() => res?,
+                _ = idle_check.tick(), if self.config.on_demand => {
+                    if self.is_idle(session.handle.name(), &mut idle_since).await {
+                        return Ok(());
+                    }
+                    continue;
+                },
+            };
+
+            for event in client.handle_input(&data).await? {
+                let packet = match event {
+                    ClientSessionEvent::StreamMetadataReceived { metadata, .. } => {
+                        let metadata = convert::from_metadata(metadata);
+                        match Bytes::try_from(metadata) {
+                            Ok(payload) => Packet::new::<u32, Bytes>(PacketType::Meta, None, payload),
+                            Err(_) => continue,
+                        }
+                    },
+                    ClientSessionEvent::VideoDataReceived { data, timestamp, .. } => {
+                        Packet::new_video(timestamp.value, data)
+                    },
+                    ClientSessionEvent::AudioDataReceived { data, timestamp, .. } => {
+                        Packet::new_audio(timestamp.value, data)
+                    },
+                    _ => continue,
+                };
+
+                session.handle
+                    .send(Message::Packet(packet))
+                    .map_err(|_| Error::SessionSendFailed)?;
+            }
+        }
+    }
+
+    async fn is_idle(&self, name: &str, idle_since: &mut Option<Instant>) -> bool {
+        let (request, response) = oneshot::channel();
+        if self.session_manager.send(ManagerMessage::GetSession((name.to_string(), request))).is_err() {
+            return false;
+        }
+
+        match response.await {
+            Ok(Some(info)) if info.viewers > 0 => {
+                *idle_since = None;
+                false
+            },
+            Ok(_) => idle_since.get_or_insert_with(Instant::now).elapsed() >= self.config.idle_timeout,
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,128 @@
+mod upstream;
+
+
+use {
+    std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    },
+    tokio::sync::oneshot,
+    javelin_core::{
+        session::{self, Event, EventKind, ManagerMessage},
+        shutdown::Shutdown,
+        config::Updates,
+    },
+    crate::{
+        config::Config as RtmpConfig,
+        url::UpstreamUrl,
+    },
+    self::upstream::Upstream,
+};
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Target {
+    app: String,
+    url: UpstreamUrl,
+}
+
+impl Target {
+    /// `live` matches the session `live` and every `live/<stream>`.
+    fn matches(&self, name: &str) -> bool {
+        name == self.app
+            || (name.starts_with(self.app.as_str()) && name[self.app.len()..].starts_with('/'))
+    }
+}
+
+
+/// Pushes live sessions to upstream RTMP servers.
+///
+/// Every target registers its own trigger to get a separate watcher for each session.
+pub struct Relay {
+    session_manager: session::ManagerHandle,
+    /// Dropping the sender stops a target from picking up new sessions.
+    targets: HashMap<Target, oneshot::Sender<()>>,
+}
+
+impl Relay {
+    pub fn new(session_manager: session::ManagerHandle) -> Self {
+        Self {
+            session_manager,
+            targets: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self, mut shutdown: Shutdown, mut updates: Updates<RtmpConfig>) {
+        self.configure(&updates.current(), &shutdown);
+
+        loop {
+            tokio::select! {
+                Some(config) = updates.changed() => self.configure(&config, &shutdown),
+                _ = shutdown.wait() => break,
+            }
+        }
+    }
+
+    /// Removed targets keep relaying sessions that are already active.
+    fn configure(&mut self, config: &RtmpConfig, shutdown: &Shutdown) {
+
+        let targets = config.relay
+            .iter()
+            .flat_map(|relay| relay.urls.iter().map(move |url| Target {
+                app: relay.app.clone(),
+                url: url.clone(),
+            }))
+            .collect::<HashSet<_>>();
+
+        self.targets.retain(|target, _| targets.contains(target));
+
+        for target in targets {
+            if self.targets.contains_key(&target) {
+                continue;
+            }
+
+            let (trigger, trigger_handle) = session::trigger_channel();
+            if self.session_manager.send(ManagerMessage::RegisterTrigger(EventKind::SessionCreated, trigger)).is_err() {
+                log::error!("Failed to register relay trigger");
+                return;
+            }
+
+            log::info!("Relaying sessions of {} to {}", target.app, target.url);
+
+            let (stop, stopped) = oneshot::channel();
+            let connection_timeout = config.connection_timeout;
+            tokio::spawn(watch_sessions(target.clone(), trigger_handle, stopped, shutdown.clone(), connection_timeout));
+            self.targets.insert(target, stop);
+        }
+    }
+}
+
+
+async fn watch_sessions(
+    target: Target,
+    mut trigger_handle: session::TriggerHandle,
+    mut stopped: oneshot::Receiver<()>,
+    shutdown: Shutdown,
+    connection_timeout: Duration,
+) {
+    loop {
+        let event = tokio::select! {
+            event = trigger_handle.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = &mut stopped => break,
+        };
+
+        if let Event::SessionCreated(name, watcher) = event {
+            if !target.matches(&name) {
+                continue;
+            }
+
+            let upstream = Upstream::new(name, target.url.clone(), watcher, connection_timeout);
+            tokio::spawn(upstream.run(shutdown.clone()));
+        }
+    }
+
+    log::debug!("Stopped relaying sessions of {} to {}", target.app, target.url);
+}
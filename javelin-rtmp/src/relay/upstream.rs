@@ -0,0 +1,180 @@
+use {
+    std::{convert::TryFrom, time::Duration},
+    bytes::BytesMut,
+    tokio::{sync::broadcast::RecvError, time::delay_for},
+    javelin_types::{Packet, PacketType},
+    javelin_codec::flv::tag::{VideoData, AudioData},
+    javelin_core::{session::Watcher, shutdown::Shutdown},
+    crate::{
+        client::{Client, MIN_BACKOFF, MAX_BACKOFF},
+        error::Error,
+        url::UpstreamUrl,
+    },
+};
+
+
+enum FrameKind {
+    Header,
+    Keyframe,
+    Video,
+    Other,
+}
+
+
+/// Packets an upstream needs before it can decode the stream.
+#[derive(Default)]
+struct InitData {
+    metadata: Option<Packet>,
+    video_seq_header: Option<Packet>,
+    audio_seq_header: Option<Packet>,
+}
+
+impl InitData {
+    fn update(&mut self, packet: &Packet) -> FrameKind {
+        match packet.kind {
+            PacketType::Meta => {
+                self.metadata = Some(packet.clone());
+                FrameKind::Header
+            },
+            PacketType::Video => match VideoData::try_from(packet.as_ref()) {
+                Ok(video) if video.is_sequence_header() => {
+                    self.video_seq_header = Some(packet.clone());
+                    FrameKind::Header
+                },
+                Ok(video) if video.is_keyframe() => FrameKind::Keyframe,
+                _ => FrameKind::Video,
+            },
+            PacketType::Audio => match AudioData::try_from(packet.as_ref()) {
+                Ok(audio) if audio.is_sequence_header() => {
+                    self.audio_seq_header = Some(packet.clone());
+                    FrameKind::Header
+                },
+                _ => FrameKind::Other,
+            },
+            PacketType::Discontinuity => FrameKind::Other,
+        }
+    }
+
+    fn packets(&self) -> Vec<Packet> {
+        [&self.metadata, &self.video_seq_header, &self.audio_seq_header]
+            .iter()
+            .filter_map(|packet| packet.as_ref().cloned())
+            .collect()
+    }
+}
+
+
+enum Input {
+    Packet(Result<Packet, RecvError>),
+    Data(BytesMut),
+}
+
+
+/// Publishes a single session to an upstream server.
+///
+/// The connection is re-established with an increasing delay until the session ends.
+pub struct Upstream {
+    name: String,
+    url: UpstreamUrl,
+    watcher: Watcher,
+    init: InitData,
+    connection_timeout: Duration,
+    backoff: Duration,
+}
+
+impl Upstream {
+    pub fn new(name: String, url: UpstreamUrl, watcher: Watcher, connection_timeout: Duration) -> Self {
+        Self {
+            name,
+            url,
+            watcher,
+            init: InitData::default(),
+            connection_timeout,
+            backoff: MIN_BACKOFF,
+        }
+    }
+
+    pub async fn run(mut self, mut shutdown: Shutdown) {
+        loop {
+            let res = tokio::select! {
+                res = self.publish() => res,
+                _ = shutdown.wait() => return,
+            };
+
+            match res {
+                Ok(()) => {
+                    log::info!("Session {} ended, stopped relaying to {}", self.name, self.url);
+                    return;
+                },
+                Err(why) => {
+                    log::warn!("Relaying {} to {} failed, retrying in {:?}: {}", self.name, self.url, self.backoff, why);
+                },
+            }
+
+            if !self.wait_for_retry(&mut shutdown).await {
+                return;
+            }
+
+            self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Keeps up with the session while disconnected, returns `false` once it ended.
+    async fn wait_for_retry(&mut self, shutdown: &mut Shutdown) -> bool {
+        let mut retry = delay_for(self.backoff);
+
+        loop {
+            let res = tokio::select! {
+                _ = &mut retry => return true,
+                _ = shutdown.wait() => return false,
+                res = self.watcher.recv() => res,
+            };
+
+            match res {
+                Ok(packet) => { self.init.update(&packet); },
+                Err(RecvError::Closed) => return false,
+                Err(RecvError::Lagged(_)) => (),
+            }
+        }
+    }
+
+    async fn publish(&mut self) -> Result<(), Error> {
+        let mut client = Client::connect(&self.url, self.connection_timeout).await?;
+        client.publish().await?;
+
+        log::info!("Relaying {} to {}", self.name, self.url);
+        self.backoff = MIN_BACKOFF;
+
+        for packet in self.init.packets() {
+            client.send_packet(packet).await?;
+        }
+
+        // the upstream can only decode video starting with a keyframe
+        let mut waiting_for_keyframe = true;
+
+        loop {
+            let input = tokio::select! {
+                res = self.watcher.recv() => Input::Packet(res),
+                res = client.receive() => Input::Data(res?),
+            };
+
+            match input {
+                Input::Packet(Ok(packet)) => {
+                    match self.init.update(&packet) {
+                        FrameKind::Keyframe => waiting_for_keyframe = false,
+                        FrameKind::Video if waiting_for_keyframe => continue,
+                        _ => (),
+                    }
+                    client.send_packet(packet).await?;
+                },
+                Input::Packet(Err(RecvError::Closed)) => return Ok(()),
+                Input::Packet(Err(RecvError::Lagged(count))) => {
+                    log::warn!("Relay of {} to {} lagged behind by {} packets", self.name, self.url, count);
+                },
+                // acknowledgements and pings of the upstream
+                Input::Data(data) => { client.handle_input(&data).await?; },
+            }
+        }
+    }
+}
+
@@ -1,7 +1,11 @@
 use {
     std::{
         io::ErrorKind as IoErrorKind,
-        sync::atomic::{AtomicUsize, Ordering},
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
         time::Duration,
         fmt::{self, Display},
     },
@@ -9,11 +13,20 @@ use {
     tokio::{
         prelude::*,
         net::TcpListener,
+        sync::mpsc,
+    },
+    javelin_core::{
+        session,
+        metrics::METRICS,
+        playback::Authorizer,
+        shutdown::Shutdown,
+        config::Updates,
     },
-    javelin_core::{session, Config},
     crate::{
         config::Config as RtmpConfig,
         peer::Peer,
+        relay::Relay,
+        pull::Pull,
         Error,
     },
 };
@@ -46,50 +59,89 @@ impl From<&ClientId> for u64 {
 }
 
 
+/// Shared with every new peer.
+#[derive(Clone)]
+struct PeerContext {
+    /// Held by every peer task, the service is done once all of them are dropped.
+    guard: mpsc::Sender<()>,
+    shutdown: Shutdown,
+    updates: Updates<RtmpConfig>,
+}
+
+
 pub struct Service {
     config: RtmpConfig,
     session_manager: session::ManagerHandle,
+    authorizer: Arc<Authorizer>,
     client_id: ClientId,
 }
 
 impl Service {
-    pub fn new(session_manager: session::ManagerHandle, config: &Config) -> Self {
+    pub fn new(session_manager: session::ManagerHandle, config: RtmpConfig, authorizer: Arc<Authorizer>) -> Self {
         Self {
             session_manager,
-            config: config.get("rtmp").unwrap_or_default(),
+            authorizer,
+            config,
             client_id: ClientId::default()
         }
     }
 
-    pub async fn run(self) {
-        #[cfg(not(feature = "rtmps"))]
-        let res = self.handle_rtmp().await;
-        #[cfg(feature = "rtmps")]
-        let res = tokio::try_join!(
-            self.handle_rtmp(),
-            self.handle_rtmps()
-        );
-
-        if let Err(err) = res {
-            log::error!("{}", err);
+    pub async fn run(self, mut shutdown: Shutdown, updates: Updates<RtmpConfig>) {
+        let (guard, mut peers_done) = mpsc::channel(1);
+
+        let relay = Relay::new(self.session_manager.clone());
+        tokio::spawn(relay.run(shutdown.clone(), updates.clone()));
+
+        let pull = Pull::new(self.session_manager.clone());
+        tokio::spawn(pull.run(shutdown.clone(), updates.clone()));
+
+        {
+            let context = PeerContext { guard, shutdown: shutdown.clone(), updates };
+
+            #[cfg(not(feature = "rtmps"))]
+            let listeners = self.handle_rtmp(&context);
+            #[cfg(feature = "rtmps")]
+            let listeners = async {
+                tokio::try_join!(
+                    self.handle_rtmp(&context),
+                    self.handle_rtmps(&context)
+                )
+            };
+
+            tokio::select! {
+                res = listeners => {
+                    if let Err(err) = res {
+                        log::error!("{}", err);
+                    }
+                },
+                _ = shutdown.wait() => {
+                    log::info!("No longer accepting RTMP connections");
+                },
+            }
         }
+
+        // peers disconnect on their own once shutdown was triggered
+        let _ = peers_done.recv().await;
+
+        log::info!("RTMP service stopped");
     }
 
-    async fn handle_rtmp(&self) -> Result<()> {
+    async fn handle_rtmp(&self, context: &PeerContext) -> Result<()> {
         let addr = &self.config.addr;
         let mut listener = TcpListener::bind(addr).await?;
         log::info!("Listening for RTMP connections on {}", addr);
 
         loop {
-            let (tcp_stream, _addr) = listener.accept().await?;
+            let (tcp_stream, addr) = listener.accept().await?;
             tcp_stream.set_keepalive(Some(Duration::from_secs(30)))?;
-            self.process(tcp_stream);
+            METRICS.connections_accepted.inc();
+            self.process(tcp_stream, addr, context.clone());
             self.client_id.increment();
         }
     }
 
     #[cfg(feature = "rtmps")]
-    async fn handle_rtmps(&self) -> Result<()> {
+    async fn handle_rtmps(&self, context: &PeerContext) -> Result<()> {
         if !self.config.tls.enabled {
             return Ok(())
         }
@@ -106,19 +158,39 @@ impl Service {
         };
 
         loop {
-            let (tcp_stream, _addr) = listener.accept().await?;
+            let (tcp_stream, addr) = listener.accept().await?;
             tcp_stream.set_keepalive(Some(Duration::from_secs(30)))?;
-            let tls_stream = tls_acceptor.accept(tcp_stream).await?;
-            self.process(tls_stream);
+            let tls_stream = match tls_acceptor.accept(tcp_stream).await {
+                Ok(tls_stream) => tls_stream,
+                Err(why) => {
+                    log::error!("TLS handshake with {} failed: {}", addr, why);
+                    METRICS.handshake_failures.inc();
+                    continue;
+                },
+            };
+            METRICS.connections_accepted.inc();
+            self.process(tls_stream, addr, context.clone());
         }
     }
 
-    fn process<S>(&self, stream: S)
+    fn process<S>(&self, stream: S, addr: SocketAddr, context: PeerContext)
         where S: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static
     {
-        log::info!("New client connection: {}", &self.client_id);
+        log::info!("New client connection: {} ({})", &self.client_id, addr);
         let id = (&self.client_id).into();
-        let peer = Peer::new(id, stream, self.session_manager.clone(), self.config.clone());
+        let PeerContext { guard, shutdown, updates } = context;
+
+        // reloaded settings only apply to new connections
+        let config = (*updates.current()).clone();
+
+        let peer = Peer::new(
+            id,
+            stream,
+            addr,
+            self.session_manager.clone(),
+            Arc::clone(&self.authorizer),
+            shutdown,
+            config);
 
         tokio::spawn(async move {
             if let Err(err) = peer.run().await {
@@ -127,6 +199,7 @@ impl Service {
                     e => log::error!("{}", e)
                 }
             }
+            drop(guard);
         });
     }
 }
@@ -0,0 +1,69 @@
+use {
+    javelin_core::{playback::query_param, session},
+    crate::proto::Error,
+};
+
+
+/// Resolve the session name and stream key of a publish request.
+///
+/// Supported forms are:
+/// - app `live/<stream>` with the key as stream key
+/// - app `live` with `<stream>?key=<key>` as stream key
+/// - app `<user>` with the key as stream key (used by older versions)
+pub fn publish_target(app_name: &str, stream_key: &str) -> Result<(String, String), Error> {
+    let (stream, query) = split_query(stream_key);
+
+    let (name, key) = match query {
+        Some(query) => {
+            let key = query_param(query, "key").unwrap_or_default();
+            (format!("{}/{}", app_name, stream), key.to_string())
+        },
+        None => (app_name.to_string(), stream.to_string()),
+    };
+
+    validate(&name)?;
+
+    Ok((name, key))
+}
+
+/// Session names a play request could refer to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PlayTarget {
+    pub name: String,
+    /// Sessions published by older versions are named by app only
+    pub legacy_name: Option<String>,
+    pub query: String,
+}
+
+/// Resolve the session name and query string of a play request.
+///
+/// App `live` with stream `<stream>` refers to `live/<stream>`, or to a session
+/// named `live` if it was published the way older versions did.
+pub fn play_target(app_name: &str, stream_key: &str) -> Result<PlayTarget, Error> {
+    let (stream, query) = split_query(stream_key);
+
+    let (name, legacy_name) = if stream.is_empty() || app_name.contains('/') {
+        (app_name.to_string(), None)
+    } else {
+        (format!("{}/{}", app_name, stream), Some(app_name.to_string()))
+    };
+
+    validate(&name)?;
+
+    Ok(PlayTarget { name, legacy_name, query: query.unwrap_or_default().to_string() })
+}
+
+
+fn split_query(stream_key: &str) -> (&str, Option<&str>) {
+    let mut parts = stream_key.splitn(2, '?');
+    let stream = parts.next().unwrap_or_default();
+    (stream, parts.next())
+}
+
+fn validate(name: &str) -> Result<(), Error> {
+    if !session::is_valid_name(name) {
+        return Err(Error::InvalidStreamName(name.to_string()));
+    }
+
+    Ok(())
+}
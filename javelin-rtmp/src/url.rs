@@ -0,0 +1,108 @@
+use {
+    std::{
+        convert::TryFrom,
+        fmt::{self, Display},
+    },
+    serde::{Serialize, Deserialize},
+    thiserror::Error,
+};
+
+
+const SCHEME: &str = "rtmp://";
+const DEFAULT_PORT: u16 = 1935;
+
+
+#[derive(Error, Debug)]
+pub enum UrlError {
+    #[error("Only rtmp:// URLs are supported: {0}")]
+    UnsupportedScheme(String),
+
+    #[error("Missing host in {0}")]
+    MissingHost(String),
+
+    #[error("Invalid port in {0}")]
+    InvalidPort(String),
+
+    #[error("Expected rtmp://<host>[:<port>]/<app>/<stream key>, got {0}")]
+    MissingPath(String),
+}
+
+
+/// Target of a relay, `rtmp://<host>[:<port>]/<app>/<stream key>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct UpstreamUrl {
+    pub host: String,
+    pub port: u16,
+    pub app: String,
+    pub stream_key: String,
+}
+
+impl UpstreamUrl {
+    /// The `tcUrl` sent with the connect request.
+    pub fn tc_url(&self) -> String {
+        if self.host.contains(':') {
+            format!("{}[{}]:{}/{}", SCHEME, self.host, self.port, self.app)
+        } else {
+            format!("{}{}:{}/{}", SCHEME, self.host, self.port, self.app)
+        }
+    }
+}
+
+impl TryFrom<String> for UpstreamUrl {
+    type Error = UrlError;
+
+    fn try_from(url: String) -> Result<Self, Self::Error> {
+        if !url.starts_with(SCHEME) {
+            return Err(UrlError::UnsupportedScheme(url));
+        }
+
+        let rest = &url[SCHEME.len()..];
+        let mut parts = rest.splitn(2, '/');
+        let authority = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+
+        // the stream key may contain slashes and query parameters
+        let mut path_parts = path.splitn(2, '/');
+        let app = path_parts.next().unwrap_or_default();
+        let stream_key = path_parts.next().unwrap_or_default();
+
+        if app.is_empty() || stream_key.is_empty() {
+            return Err(UrlError::MissingPath(url));
+        }
+
+        // IPv6 addresses are enclosed in brackets, e.g. [::1]:1935
+        let (host, port) = match authority.rfind(':') {
+            Some(pos) if !authority[pos..].contains(']') => {
+                let port = authority[pos + 1..].parse().map_err(|_| UrlError::InvalidPort(url.clone()))?;
+                (&authority[..pos], port)
+            },
+            _ => (authority, DEFAULT_PORT),
+        };
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(UrlError::MissingHost(url));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            app: app.to_string(),
+            stream_key: stream_key.to_string(),
+        })
+    }
+}
+
+impl From<UpstreamUrl> for String {
+    fn from(url: UpstreamUrl) -> Self {
+        format!("{}/{}", url.tc_url(), url.stream_key)
+    }
+}
+
+/// Leaves out the stream key, so it does not end up in the logs.
+impl Display for UpstreamUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tc_url())
+    }
+}
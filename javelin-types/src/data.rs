@@ -44,7 +44,7 @@ impl From<Timestamp> for u64 {
 }
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metadata(StringMap);
 
 impl Metadata {
@@ -1,9 +1,17 @@
 use {
+    std::sync::Arc,
+    chrono::{DateTime, Utc},
+    rand::{Rng, thread_rng, distributions::Alphanumeric},
     thiserror::Error,
-    crate::async_trait,
+    tokio::task,
+    crate::{async_trait, Metadata},
 };
 
 
+/// Name of the key that is used if none was specified.
+pub const DEFAULT_KEY_NAME: &str = "default";
+
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Database lookup failed")]
@@ -11,25 +19,357 @@ pub enum Error {
 
     #[error("Database update failed")]
     UpdateFailed,
+
+    #[error("Failed to hash stream key")]
+    HashingFailed,
 }
 
 
 pub struct User {
     pub name: String,
-    pub key: String,
+    pub keys: Vec<StreamKey>,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct StreamKey {
+    pub name: String,
+    /// Salted hash of the key, plaintext for keys stored by older versions
+    pub hash: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
 }
 
+impl StreamKey {
+    pub async fn new(name: &str, key: &str, expires_at: Option<DateTime<Utc>>) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.to_string(),
+            hash: hash_key_blocking(key).await?,
+            expires_at,
+            last_used_at: None,
+        })
+    }
+
+    /// Keys stored by older versions have not been hashed yet.
+    pub fn is_plaintext(&self) -> bool {
+        !self.hash.starts_with("$argon2")
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.map_or(false, |expires_at| expires_at <= now)
+    }
+
+    /// Compare the key in constant time.
+    pub fn verify(&self, key: &str) -> bool {
+        if self.is_plaintext() {
+            return constant_time_eq::constant_time_eq(self.hash.as_bytes(), key.as_bytes());
+        }
+
+        argon2::verify_encoded(&self.hash, key.as_bytes()).unwrap_or(false)
+    }
+}
+
+
+/// Allows a user to publish to a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamPermission {
+    pub app: String,
+    /// `*` allows all streams of the app
+    pub stream: String,
+}
+
+impl StreamPermission {
+    pub const ANY_STREAM: &'static str = "*";
+
+    /// Parse a permission given as `app/stream`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(2, '/');
+
+        match (parts.next(), parts.next()) {
+            (Some(app), Some(stream)) if !app.is_empty() && !stream.is_empty() => {
+                Some(Self { app: app.to_string(), stream: stream.to_string() })
+            },
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, app: &str, stream: &str) -> bool {
+        self.app == app && (self.stream == stream || self.stream == Self::ANY_STREAM)
+    }
+}
+
+
 #[async_trait]
-pub trait UserRepository {
+pub trait UserRepository: Send + Sync {
     async fn user_by_name(&self, name: &str) -> Result<Option<User>, Error>;
 
-    async fn add_user_with_key(&mut self, name: &str, key: &str) -> Result<(), Error>;
+    async fn users(&self) -> Result<Vec<User>, Error>;
+
+    /// Delete a user and all of its keys, returns `false` if no such user exists.
+    async fn delete_user(&self, name: &str) -> Result<bool, Error>;
 
+    /// Store a key, replacing any key of the user with the same name.
+    ///
+    /// The user is created if it does not exist yet.
+    async fn put_key(&self, user_name: &str, key: &StreamKey) -> Result<(), Error>;
+
+    /// Returns `false` if the user has no key with this name.
+    async fn revoke_key(&self, user_name: &str, key_name: &str) -> Result<bool, Error>;
+
+    /// Only update the last use of a key, returns `false` if the user has no key with this name.
+    async fn touch_key(&self, user_name: &str, key_name: &str, used_at: DateTime<Utc>) -> Result<bool, Error>;
+
+    async fn add_user_key(&self, name: &str, key_name: &str, key: &str, expires_at: Option<DateTime<Utc>>) -> Result<(), Error> {
+        let key = StreamKey::new(key_name, key, expires_at).await?;
+        self.put_key(name, &key).await
+    }
+
+    async fn add_user_with_key(&self, name: &str, key: &str) -> Result<(), Error> {
+        self.add_user_key(name, DEFAULT_KEY_NAME, key, None).await
+    }
+
+    /// Streams a user is allowed to publish to.
+    async fn permissions(&self, user_name: &str) -> Result<Vec<StreamPermission>, Error>;
+
+    /// Names of all users allowed to publish to a stream, including wildcard permissions.
+    async fn stream_publishers(&self, app: &str, stream: &str) -> Result<Vec<String>, Error>;
+
+    async fn allow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<(), Error>;
+
+    /// Returns `false` if the user had no such permission.
+    async fn disallow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<bool, Error>;
+
+    /// Find the user that is allowed to publish to a stream with this key.
+    async fn authenticate_publisher(&self, app: &str, stream: &str, key: &str) -> Result<Option<String>, Error> {
+        for user_name in self.stream_publishers(app, stream).await? {
+            if self.user_has_key(&user_name, key).await? {
+                return Ok(Some(user_name));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check if any unexpired key of the user matches.
+    ///
+    /// The matching key is marked as used, plaintext keys get hashed.
     async fn user_has_key(&self, name: &str, key: &str) -> Result<bool, Error> {
-        if let Some(user) = self.user_by_name(name).await? {
-            return Ok(&user.key == key)
+        let user = match self.user_by_name(name).await? {
+            Some(user) => user,
+            None => return Ok(false),
+        };
+
+        let now = Utc::now();
+
+        let mut stream_key = match find_key(user.keys, key, now).await? {
+            Some(stream_key) => stream_key,
+            None => return Ok(false),
+        };
+
+        if stream_key.is_plaintext() {
+            log::info!("Hashing plaintext key {} of user {}", stream_key.name, name);
+            stream_key.hash = hash_key_blocking(key).await?;
+            stream_key.last_used_at = Some(now);
+            self.put_key(name, &stream_key).await?;
+        } else {
+            self.touch_key(name, &stream_key.name, now).await?;
         }
 
-        Ok(false)
+        Ok(true)
+    }
+}
+
+
+#[async_trait]
+impl<T> UserRepository for Arc<T>
+    where T: UserRepository + Send + Sync + ?Sized
+{
+    async fn user_by_name(&self, name: &str) -> Result<Option<User>, Error> {
+        (**self).user_by_name(name).await
     }
+
+    async fn users(&self) -> Result<Vec<User>, Error> {
+        (**self).users().await
+    }
+
+    async fn delete_user(&self, name: &str) -> Result<bool, Error> {
+        (**self).delete_user(name).await
+    }
+
+    async fn put_key(&self, user_name: &str, key: &StreamKey) -> Result<(), Error> {
+        (**self).put_key(user_name, key).await
+    }
+
+    async fn revoke_key(&self, user_name: &str, key_name: &str) -> Result<bool, Error> {
+        (**self).revoke_key(user_name, key_name).await
+    }
+
+    async fn touch_key(&self, user_name: &str, key_name: &str, used_at: DateTime<Utc>) -> Result<bool, Error> {
+        (**self).touch_key(user_name, key_name, used_at).await
+    }
+
+    async fn add_user_key(&self, name: &str, key_name: &str, key: &str, expires_at: Option<DateTime<Utc>>) -> Result<(), Error> {
+        (**self).add_user_key(name, key_name, key, expires_at).await
+    }
+
+    async fn add_user_with_key(&self, name: &str, key: &str) -> Result<(), Error> {
+        (**self).add_user_with_key(name, key).await
+    }
+
+    async fn permissions(&self, user_name: &str) -> Result<Vec<StreamPermission>, Error> {
+        (**self).permissions(user_name).await
+    }
+
+    async fn stream_publishers(&self, app: &str, stream: &str) -> Result<Vec<String>, Error> {
+        (**self).stream_publishers(app, stream).await
+    }
+
+    async fn allow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<(), Error> {
+        (**self).allow_publish(user_name, permission).await
+    }
+
+    async fn disallow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<bool, Error> {
+        (**self).disallow_publish(user_name, permission).await
+    }
+
+    async fn authenticate_publisher(&self, app: &str, stream: &str, key: &str) -> Result<Option<String>, Error> {
+        (**self).authenticate_publisher(app, stream, key).await
+    }
+
+    async fn user_has_key(&self, name: &str, key: &str) -> Result<bool, Error> {
+        (**self).user_has_key(name, key).await
+    }
+}
+
+
+
+/// A finished publish session.
+#[derive(Debug, Clone)]
+pub struct SessionRecord {
+    pub user: String,
+    /// Name of the session, `app/stream` for most publishers
+    pub app: String,
+    pub client_addr: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub peak_viewers: u64,
+    pub bytes_received: u64,
+    pub codec: CodecParameters,
+}
+
+impl SessionRecord {
+    pub fn duration(&self) -> chrono::Duration {
+        self.ended_at - self.started_at
+    }
+}
+
+
+/// Stream parameters announced by the publisher.
+#[derive(Debug, Clone, Default)]
+pub struct CodecParameters {
+    pub video_codec: Option<String>,
+    pub video_width: Option<u32>,
+    pub video_height: Option<u32>,
+    pub video_frame_rate: Option<f64>,
+    /// Kilobits per second
+    pub video_bitrate: Option<u32>,
+    pub audio_codec: Option<String>,
+    pub audio_sample_rate: Option<u32>,
+    pub audio_channels: Option<u32>,
+    /// Kilobits per second
+    pub audio_bitrate: Option<u32>,
+    pub encoder: Option<String>,
+}
+
+impl From<&Metadata> for CodecParameters {
+    fn from(val: &Metadata) -> Self {
+        Self {
+            video_codec: val.get("video.codec"),
+            video_width: val.get("video.width"),
+            video_height: val.get("video.height"),
+            video_frame_rate: val.get("video.frame_rate"),
+            video_bitrate: val.get("video.bitrate"),
+            audio_codec: val.get("audio.codec"),
+            audio_sample_rate: val.get("audio.sampling_rate"),
+            audio_channels: val.get("audio.channels"),
+            audio_bitrate: val.get("audio.bitrate"),
+            encoder: val.get("encoder"),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub user: Option<String>,
+    pub app: Option<String>,
+    /// Only sessions that started at or after this time
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+}
+
+
+#[async_trait]
+pub trait SessionHistory {
+    async fn record_session(&self, record: &SessionRecord) -> Result<(), Error>;
+
+    /// Matching sessions, the most recent first.
+    async fn sessions(&self, filter: &HistoryFilter) -> Result<Vec<SessionRecord>, Error>;
+}
+
+#[async_trait]
+impl<T> SessionHistory for Arc<T>
+    where T: SessionHistory + Send + Sync + ?Sized
+{
+    async fn record_session(&self, record: &SessionRecord) -> Result<(), Error> {
+        (**self).record_session(record).await
+    }
+
+    async fn sessions(&self, filter: &HistoryFilter) -> Result<Vec<SessionRecord>, Error> {
+        (**self).sessions(filter).await
+    }
+}
+
+
+/// Generate a new random key.
+pub fn generate_key() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .collect()
+}
+
+pub fn hash_key(key: &str) -> Result<String, Error> {
+    let salt: [u8; 16] = thread_rng().gen();
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        ..argon2::Config::default()
+    };
+
+    argon2::hash_encoded(key.as_bytes(), &salt, &config)
+        .map_err(|_| Error::HashingFailed)
+}
+
+/// Same as [`hash_key`](hash_key), but on the blocking thread pool.
+pub async fn hash_key_blocking(key: &str) -> Result<String, Error> {
+    let key = key.to_string();
+
+    task::spawn_blocking(move || hash_key(&key))
+        .await
+        .map_err(|_| Error::HashingFailed)?
+}
+
+/// Find the first unexpired key that matches.
+///
+/// Argon2 is slow on purpose, so keys are verified on the blocking thread pool
+/// instead of stalling the executor.
+pub async fn find_key(keys: Vec<StreamKey>, key: &str, now: DateTime<Utc>) -> Result<Option<StreamKey>, Error> {
+    let key = key.to_string();
+
+    task::spawn_blocking(move || {
+            keys.into_iter()
+                .find(|stream_key| !stream_key.is_expired(now) && stream_key.verify(&key))
+        })
+        .await
+        .map_err(|_| Error::LookupFailed)
 }
@@ -11,6 +11,8 @@ pub enum PacketType {
     Meta,
     Video,
     Audio,
+    /// Marks that the following packets are not continuous with the previous ones.
+    Discontinuity,
 }
 
 
@@ -44,6 +46,10 @@ impl Packet {
         Self::new(PacketType::Audio, Some(timestamp), payload)
     }
 
+    pub fn new_discontinuity() -> Self {
+        Self::new::<u32, Bytes>(PacketType::Discontinuity, None, Bytes::new())
+    }
+
     pub fn pack(&self) -> Result<Bytes, Error> {
         let data = bincode::serialize(&self)?;
         Ok(Bytes::from(data))
@@ -21,9 +21,124 @@ pub fn build<'a>() -> ArgMatches<'a> {
             .help("The directory where all config files are located"))
         .subcommand(SubCommand::with_name("run"))
         .subcommand(SubCommand::with_name("permit-stream")
+            .about("Add a stream key to a user, creating the user if required")
             .arg(Arg::with_name("user").required(true))
             .arg(Arg::with_name("key").required(true))
-        ).get_matches()
+            .arg(key_name_arg())
+            .arg(expires_arg()))
+        .subcommand(SubCommand::with_name("revoke-stream")
+            .about("Remove a stream key from a user")
+            .arg(Arg::with_name("user").required(true))
+            .arg(key_name_arg()))
+        .subcommand(SubCommand::with_name("rotate-key")
+            .about("Replace a stream key with a randomly generated one and print it")
+            .arg(Arg::with_name("user").required(true))
+            .arg(key_name_arg())
+            .arg(expires_arg()))
+        .subcommand(SubCommand::with_name("allow-publish")
+            .about("Allow a user to publish to a stream, creating the user if required")
+            .arg(Arg::with_name("user").required(true))
+            .arg(stream_arg()))
+        .subcommand(SubCommand::with_name("disallow-publish")
+            .about("Remove the permission of a user to publish to a stream")
+            .arg(Arg::with_name("user").required(true))
+            .arg(stream_arg()))
+        .subcommand(SubCommand::with_name("sign-playback")
+            .about("Print the query string of a signed playback URL for a protected stream")
+            .arg(Arg::with_name("stream")
+                .required(true)
+                .value_name("APP/STREAM"))
+            .arg(Arg::with_name("valid_for")
+                .long("valid-for")
+                .value_name("SECONDS")
+                .default_value("3600")
+                .help("How long the URL can be used")))
+        .subcommand(SubCommand::with_name("list-users")
+            .about("List all users"))
+        .subcommand(SubCommand::with_name("show-user")
+            .about("Show a user, its stream keys and the streams it can publish to")
+            .arg(Arg::with_name("user").required(true)))
+        .subcommand(SubCommand::with_name("delete-user")
+            .about("Delete a user and all of its stream keys")
+            .arg(Arg::with_name("user").required(true)))
+        .subcommand(SubCommand::with_name("export-users")
+            .about("Export all users, their hashed stream keys and stream permissions")
+            .arg(file_arg("File to write to, defaults to stdout"))
+            .arg(format_arg()))
+        .subcommand(SubCommand::with_name("import-users")
+            .about("Import users, stream keys and stream permissions, existing keys with the same name are replaced")
+            .arg(file_arg("File to read from, defaults to stdin"))
+            .arg(format_arg()))
+        .subcommand(SubCommand::with_name("history")
+            .about("List past publish sessions, the most recent first")
+            .arg(Arg::with_name("user")
+                .long("user")
+                .value_name("NAME")
+                .help("Only sessions of this user"))
+            .arg(Arg::with_name("stream")
+                .long("stream")
+                .value_name("APP/STREAM")
+                .help("Only sessions of this stream"))
+            .arg(Arg::with_name("since")
+                .long("since")
+                .value_name("RFC3339")
+                .help("Only sessions that started after this time"))
+            .arg(Arg::with_name("limit")
+                .long("limit")
+                .value_name("COUNT")
+                .default_value("20")
+                .help("Maximum number of sessions to list, 0 lists all")))
+        .subcommand(SubCommand::with_name("db")
+            .about("Manage the database schema")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("migrate")
+                .about("Apply all pending schema migrations"))
+            .subcommand(SubCommand::with_name("status")
+                .about("List all schema migrations and when they were applied")))
+        .subcommand(SubCommand::with_name("config")
+            .about("Inspect the configuration")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("check")
+                .about("Validate the configuration file and environment overrides"))
+            .subcommand(SubCommand::with_name("dump")
+                .about("Print the effective configuration, secrets are redacted")))
+        .get_matches()
+}
+
+fn key_name_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("key_name")
+        .long("name")
+        .value_name("NAME")
+        .default_value("default")
+        .help("Name of the key, keys with the same name are replaced")
+}
+
+fn expires_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("expires")
+        .long("expires")
+        .value_name("RFC3339")
+        .help("Time after which the key is no longer valid")
+}
+
+fn stream_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("stream")
+        .required(true)
+        .value_name("APP/STREAM")
+        .help("Stream to publish to, use * as stream name to allow all streams of an app")
+}
+
+fn file_arg<'a, 'b>(help: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name("file")
+        .value_name("FILE")
+        .help(help)
+}
+
+fn format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .long("format")
+        .value_name("FORMAT")
+        .possible_values(&["json", "csv"])
+        .help("Defaults to the file extension, or json")
 }
 
 fn capitalize(string: &str) -> String {
@@ -1,19 +1,154 @@
-#[cfg(all(
-    feature = "db-sqlite",
-    feature = "db-mongo")
-)]
-compile_error!("Cannot enable multiple database backends simultaneously");
+#[cfg(feature = "db-sqlite")]
+mod sqlite;
+#[cfg(feature = "db-mongo")]
+mod mongo;
+mod memory;
+mod file;
 
 
-#[cfg(not(any(
-    feature = "db-sqlite",
-    feature = "db-mongo")
-))]
-compile_error!("One database backend is required");
+use {
+    std::{
+        net::SocketAddr,
+        path::PathBuf,
+        sync::Arc,
+    },
+    anyhow::{Result, bail},
+    serde::{Serialize, Deserialize},
+    javelin_types::models::{UserRepository, SessionHistory},
+    crate::settings::Settings,
+};
 
 
-#[cfg_attr(feature = "db-sqlite", path = "database/sqlite.rs")]
-#[cfg_attr(feature = "db-mongo", path = "database/mongo.rs")]
-mod backend;
+#[cfg(feature = "db-sqlite")]
+pub use self::sqlite::Sqlite;
+pub use self::file::UserConfig;
 
-pub use self::backend::Database;
+
+/// Everything a database backend has to store.
+pub trait Repository: UserRepository + SessionHistory {}
+
+impl<T> Repository for T
+    where T: UserRepository + SessionHistory
+{}
+
+
+pub type Database = Arc<dyn Repository + Send + Sync>;
+
+
+#[cfg(feature = "db-sqlite")]
+const DEFAULT_BACKEND: &str = "sqlite";
+#[cfg(not(feature = "db-sqlite"))]
+const DEFAULT_BACKEND: &str = "memory";
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// One of `sqlite`, `mongo`, `memory` or `file`
+    #[serde(default = "default_backend")]
+    pub backend: String,
+
+    #[serde(default)]
+    pub sqlite: SqliteConfig,
+
+    #[serde(default)]
+    pub mongo: MongoConfig,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            backend: default_backend(),
+            sqlite: SqliteConfig::default(),
+            mongo: MongoConfig::default(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteConfig {
+    /// Required if the SQLite backend is used
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MongoConfig {
+    #[serde(default = "default_mongo_addr")]
+    pub addr: SocketAddr,
+
+    #[serde(default = "default_mongo_dbname")]
+    pub dbname: String,
+
+    #[serde(default)]
+    pub password: String,
+
+    #[serde(default)]
+    pub username: String,
+}
+
+impl Default for MongoConfig {
+    fn default() -> Self {
+        Self {
+            addr: default_mongo_addr(),
+            dbname: default_mongo_dbname(),
+            password: String::new(),
+            username: String::new(),
+        }
+    }
+}
+
+
+fn default_backend() -> String {
+    DEFAULT_BACKEND.to_string()
+}
+
+fn default_mongo_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 27017))
+}
+
+fn default_mongo_dbname() -> String {
+    "javelin".to_string()
+}
+
+
+/// Open the backend selected by `database.backend`.
+pub async fn open(settings: &Settings) -> Result<Database> {
+    let config = &settings.database;
+    let backend = &config.backend;
+
+    log::debug!("Using {} database backend", backend);
+
+    let database: Database = match backend.as_str() {
+        #[cfg(feature = "db-sqlite")]
+        "sqlite" => Arc::new(sqlite::Sqlite::new(&config.sqlite).await?),
+        #[cfg(feature = "db-mongo")]
+        "mongo" => Arc::new(mongo::Mongo::new(config.mongo.clone()).await?),
+        "memory" => {
+            log::warn!("Using in-memory database, users will be lost on exit");
+            Arc::new(memory::Memory::new())
+        },
+        "file" => Arc::new(file::File::new(&settings.users, settings.legacy_stream_keys()).await?),
+        #[cfg(not(feature = "db-sqlite"))]
+        "sqlite" => bail!("Database backend sqlite requires the db-sqlite feature"),
+        #[cfg(not(feature = "db-mongo"))]
+        "mongo" => bail!("Database backend mongo requires the db-mongo feature"),
+        other => bail!("Unknown database backend '{}'", other),
+    };
+
+    Ok(database)
+}
+
+/// Open the SQLite database without applying any migrations.
+#[cfg(feature = "db-sqlite")]
+pub fn open_sqlite(config: &DatabaseConfig) -> Result<Sqlite> {
+    if config.backend != "sqlite" {
+        bail!("Database backend {} has no schema migrations", config.backend);
+    }
+
+    Sqlite::open(&config.sqlite)
+}
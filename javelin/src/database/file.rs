@@ -0,0 +1,165 @@
+use {
+    std::collections::HashMap,
+    anyhow::{Result, bail},
+    chrono::{DateTime, Utc},
+    serde::{Serialize, Deserialize},
+    javelin_types::{
+        async_trait,
+        models::{
+            self, UserRepository, User, StreamKey, StreamPermission,
+            SessionHistory, SessionRecord, HistoryFilter, DEFAULT_KEY_NAME,
+        },
+    },
+    super::memory::Memory,
+};
+
+
+/// A user of the `file` backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    name: String,
+
+    #[serde(default)]
+    keys: Vec<KeyConfig>,
+
+    /// Streams given as `app/stream`
+    #[serde(default)]
+    publish: Vec<String>,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyConfig {
+    #[serde(default = "default_key_name")]
+    name: String,
+
+    /// Either plaintext or an argon2 hash as created by `hash_key`
+    key: String,
+
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_key_name() -> String {
+    DEFAULT_KEY_NAME.to_string()
+}
+
+
+/// Read-only users from the `users` section of the configuration file.
+///
+/// The session history is only kept in memory.
+pub struct File {
+    users: Memory,
+}
+
+impl File {
+    /// `legacy_keys` are stream keys of older versions, the app name is used as user name.
+    pub async fn new(user_configs: &[UserConfig], legacy_keys: HashMap<String, String>) -> Result<Self> {
+        let users = Memory::new();
+
+        for user in user_configs {
+            for key in &user.keys {
+                let stream_key = StreamKey {
+                    name: key.name.clone(),
+                    hash: key.key.clone(),
+                    expires_at: key.expires_at,
+                    last_used_at: None,
+                };
+                users.put_key(&user.name, &stream_key).await?;
+            }
+
+            for stream in &user.publish {
+                let permission = match StreamPermission::parse(stream) {
+                    Some(permission) => permission,
+                    None => bail!("Invalid stream '{}' for user {}, expected APP/STREAM", stream, user.name),
+                };
+                users.allow_publish(&user.name, &permission).await?;
+            }
+        }
+
+        for (app_name, key) in legacy_keys {
+            let stream_key = StreamKey {
+                name: DEFAULT_KEY_NAME.to_string(),
+                hash: key,
+                expires_at: None,
+                last_used_at: None,
+            };
+            users.put_key(&app_name, &stream_key).await?;
+        }
+
+        Ok(Self { users })
+    }
+}
+
+#[async_trait]
+impl UserRepository for File {
+    async fn user_by_name(&self, name: &str) -> Result<Option<User>, models::Error> {
+        self.users.user_by_name(name).await
+    }
+
+    async fn users(&self) -> Result<Vec<User>, models::Error> {
+        self.users.users().await
+    }
+
+    async fn delete_user(&self, _name: &str) -> Result<bool, models::Error> {
+        read_only()
+    }
+
+    async fn put_key(&self, _user_name: &str, _key: &StreamKey) -> Result<(), models::Error> {
+        read_only()
+    }
+
+    async fn revoke_key(&self, _user_name: &str, _key_name: &str) -> Result<bool, models::Error> {
+        read_only()
+    }
+
+    async fn touch_key(&self, _user_name: &str, _key_name: &str, _used_at: DateTime<Utc>) -> Result<bool, models::Error> {
+        read_only()
+    }
+
+    async fn permissions(&self, user_name: &str) -> Result<Vec<StreamPermission>, models::Error> {
+        self.users.permissions(user_name).await
+    }
+
+    async fn stream_publishers(&self, app: &str, stream: &str) -> Result<Vec<String>, models::Error> {
+        self.users.stream_publishers(app, stream).await
+    }
+
+    async fn allow_publish(&self, _user_name: &str, _permission: &StreamPermission) -> Result<(), models::Error> {
+        read_only()
+    }
+
+    async fn disallow_publish(&self, _user_name: &str, _permission: &StreamPermission) -> Result<bool, models::Error> {
+        read_only()
+    }
+
+    /// Same as the default, but key usage is not recorded.
+    async fn user_has_key(&self, name: &str, key: &str) -> Result<bool, models::Error> {
+        let user = match self.users.user_by_name(name).await? {
+            Some(user) => user,
+            None => return Ok(false),
+        };
+
+        let stream_key = models::find_key(user.keys, key, Utc::now()).await?;
+
+        Ok(stream_key.is_some())
+    }
+}
+
+#[async_trait]
+impl SessionHistory for File {
+    async fn record_session(&self, record: &SessionRecord) -> Result<(), models::Error> {
+        self.users.record_session(record).await
+    }
+
+    async fn sessions(&self, filter: &HistoryFilter) -> Result<Vec<SessionRecord>, models::Error> {
+        self.users.sessions(filter).await
+    }
+}
+
+fn read_only<T>() -> Result<T, models::Error> {
+    log::error!("Users of the file database backend can only be changed in the configuration file");
+    Err(models::Error::UpdateFailed)
+}
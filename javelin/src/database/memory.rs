@@ -0,0 +1,185 @@
+use {
+    std::{
+        collections::BTreeMap,
+        sync::{Arc, RwLock},
+    },
+    chrono::{DateTime, Utc},
+    javelin_types::{
+        async_trait,
+        models::{
+            UserRepository, User, StreamKey, StreamPermission,
+            SessionHistory, SessionRecord, HistoryFilter, Error,
+        },
+    },
+};
+
+
+#[derive(Default)]
+struct Entry {
+    keys: Vec<StreamKey>,
+    permissions: Vec<StreamPermission>,
+}
+
+
+/// Keeps all users and the session history in memory, nothing is persisted.
+#[derive(Clone, Default)]
+pub struct Memory {
+    users: Arc<RwLock<BTreeMap<String, Entry>>>,
+    history: Arc<RwLock<Vec<SessionRecord>>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserRepository for Memory {
+    async fn user_by_name(&self, name: &str) -> Result<Option<User>, Error> {
+        let users = self.users.read().map_err(|_| Error::LookupFailed)?;
+
+        Ok(users.get(name).map(|entry| User {
+            name: name.to_string(),
+            keys: entry.keys.clone(),
+        }))
+    }
+
+    async fn users(&self) -> Result<Vec<User>, Error> {
+        let users = self.users.read().map_err(|_| Error::LookupFailed)?;
+
+        Ok(users
+            .iter()
+            .map(|(name, entry)| User { name: name.clone(), keys: entry.keys.clone() })
+            .collect())
+    }
+
+    async fn delete_user(&self, name: &str) -> Result<bool, Error> {
+        let mut users = self.users.write().map_err(|_| Error::UpdateFailed)?;
+        Ok(users.remove(name).is_some())
+    }
+
+    async fn put_key(&self, user_name: &str, key: &StreamKey) -> Result<(), Error> {
+        let mut users = self.users.write().map_err(|_| Error::UpdateFailed)?;
+        let entry = users.entry(user_name.to_string()).or_default();
+
+        match entry.keys.iter_mut().find(|stream_key| stream_key.name == key.name) {
+            Some(stream_key) => *stream_key = key.clone(),
+            None => entry.keys.push(key.clone()),
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_key(&self, user_name: &str, key_name: &str) -> Result<bool, Error> {
+        let mut users = self.users.write().map_err(|_| Error::UpdateFailed)?;
+
+        let entry = match users.get_mut(user_name) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        let count = entry.keys.len();
+        entry.keys.retain(|stream_key| stream_key.name != key_name);
+
+        Ok(entry.keys.len() != count)
+    }
+
+    async fn touch_key(&self, user_name: &str, key_name: &str, used_at: DateTime<Utc>) -> Result<bool, Error> {
+        let mut users = self.users.write().map_err(|_| Error::UpdateFailed)?;
+
+        let stream_key = users
+            .get_mut(user_name)
+            .and_then(|entry| entry.keys.iter_mut().find(|stream_key| stream_key.name == key_name));
+
+        match stream_key {
+            Some(stream_key) => {
+                stream_key.last_used_at = Some(used_at);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn permissions(&self, user_name: &str) -> Result<Vec<StreamPermission>, Error> {
+        let users = self.users.read().map_err(|_| Error::LookupFailed)?;
+
+        Ok(users
+            .get(user_name)
+            .map(|entry| entry.permissions.clone())
+            .unwrap_or_default())
+    }
+
+    async fn stream_publishers(&self, app: &str, stream: &str) -> Result<Vec<String>, Error> {
+        let users = self.users.read().map_err(|_| Error::LookupFailed)?;
+
+        let mut exact = Vec::new();
+        let mut wildcard = Vec::new();
+
+        for (name, entry) in users.iter() {
+            let permissions = &entry.permissions;
+
+            // exact matches are preferred over wildcards
+            if permissions.iter().any(|permission| permission.app == app && permission.stream == stream) {
+                exact.push(name.clone());
+            } else if permissions.iter().any(|permission| permission.matches(app, stream)) {
+                wildcard.push(name.clone());
+            }
+        }
+
+        exact.append(&mut wildcard);
+        Ok(exact)
+    }
+
+    async fn allow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<(), Error> {
+        let mut users = self.users.write().map_err(|_| Error::UpdateFailed)?;
+        let entry = users.entry(user_name.to_string()).or_default();
+
+        if !entry.permissions.contains(permission) {
+            entry.permissions.push(permission.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn disallow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<bool, Error> {
+        let mut users = self.users.write().map_err(|_| Error::UpdateFailed)?;
+
+        let entry = match users.get_mut(user_name) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        let count = entry.permissions.len();
+        entry.permissions.retain(|allowed| allowed != permission);
+
+        Ok(entry.permissions.len() != count)
+    }
+}
+
+#[async_trait]
+impl SessionHistory for Memory {
+    async fn record_session(&self, record: &SessionRecord) -> Result<(), Error> {
+        let mut history = self.history.write().map_err(|_| Error::UpdateFailed)?;
+        history.push(record.clone());
+        Ok(())
+    }
+
+    async fn sessions(&self, filter: &HistoryFilter) -> Result<Vec<SessionRecord>, Error> {
+        let history = self.history.read().map_err(|_| Error::LookupFailed)?;
+        let limit = filter.limit.map_or(usize::MAX, |limit| limit as usize);
+
+        let mut records = history
+            .iter()
+            .filter(|record| filter.user.as_ref().map_or(true, |user| &record.user == user))
+            .filter(|record| filter.app.as_ref().map_or(true, |app| &record.app == app))
+            .filter(|record| filter.since.map_or(true, |since| record.started_at >= since))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        records.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        records.truncate(limit);
+
+        Ok(records)
+    }
+}
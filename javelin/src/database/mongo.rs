@@ -1,36 +1,27 @@
 use {
-    std::{
-        collections::HashSet,
-        net::SocketAddr,
-    },
-    anyhow::Result,
+    std::collections::HashSet,
+    anyhow::{Result, Context},
+    chrono::{DateTime, Utc},
     javelin_types::{
         async_trait,
-        models::{UserRepository, User, Error},
+        models::{
+            UserRepository, User, StreamKey, StreamPermission,
+            SessionHistory, SessionRecord, CodecParameters, HistoryFilter,
+            Error, DEFAULT_KEY_NAME,
+        },
+    },
+    tokio::stream::StreamExt,
+    mongodb::{
+        Client,
+        bson::{doc, Bson, Document},
+        options::{FindOptions, UpdateOptions},
     },
-    serde::Deserialize,
-    javelin_core::Config,
-    mongodb::{Client, bson::doc},
+    super::MongoConfig,
 };
 
 
-#[derive(Clone, Deserialize)]
-struct DatabaseConfig {
-    #[serde(default = "default_db_addr")]
-    addr: SocketAddr,
-
-    #[serde(default = "default_db_name")]
-    dbname: String,
-
-    #[serde(default)]
-    password: String,
-
-    #[serde(default)]
-    username: String,
-}
-
-impl DatabaseConfig {
-    pub fn uri(&self) -> String {
+impl MongoConfig {
+    fn uri(&self) -> String {
         let mut auth = String::new();
 
         if !self.username.is_empty() {
@@ -45,51 +36,29 @@ impl DatabaseConfig {
     }
 }
 
-impl Default for DatabaseConfig {
-    fn default() -> Self {
-        Self {
-            addr: default_db_addr(),
-            dbname: default_db_name(),
-            ..Default::default()
-        }
-    }
-}
-
-fn default_db_addr() -> SocketAddr {
-    SocketAddr::from(([127, 0, 0, 1], 27017))
-}
-
-fn default_db_name() -> String {
-    "javelin".to_string()
-}
-
-
 const COLLECTION_NAMES: &'static [&'static str] = &[
     "users",
+    "session_history",
 ];
 
 
 #[derive(Clone)]
-pub struct Database {
-    config: DatabaseConfig,
+pub struct Mongo {
+    config: MongoConfig,
     client: Client,
 }
 
-impl Database {
-    pub async fn new(config: &Config) -> Self {
-        let config: DatabaseConfig = config
-            .get("database.mongo")
-            .unwrap_or_default();
-
+impl Mongo {
+    pub async fn new(config: MongoConfig) -> Result<Self> {
         let client = Client::with_uri_str(&config.uri()).await
-            .expect("Failed to connect to MongoDB");
-
-        log::info!("Connected to mongodb at {}", config.addr);
+            .context("Failed to connect to MongoDB")?;
 
         initialize_collections(&client, &config.dbname).await
-            .expect("Failed to initialize database");
+            .context("Failed to initialize database")?;
 
-        Self { config, client }
+        log::info!("Connected to mongodb at {}", config.addr);
+
+        Ok(Self { config, client })
     }
 }
 
@@ -114,7 +83,7 @@ async fn initialize_collections(client: &Client, dbname: &str) -> Result<()> {
 }
 
 #[async_trait]
-impl UserRepository for Database {
+impl UserRepository for Mongo {
     async fn user_by_name(&self, name: &str) -> Result<Option<User>, Error> {
         let db = self.client.database(&self.config.dbname);
         let res = db
@@ -122,21 +91,361 @@ impl UserRepository for Database {
             .find_one(doc!{ "name": name }, None).await
             .map_err(|_| Error::LookupFailed)?;
 
-        if let Some(doc) = res {
-            let key = doc.get_str("key").map_err(|_| Error::LookupFailed)?;
-            return Ok(Some(User { name: name.to_string(), key: key.to_string() }));
+        match res {
+            Some(doc) => Ok(Some(user_from_doc(&doc)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn users(&self) -> Result<Vec<User>, Error> {
+        let db = self.client.database(&self.config.dbname);
+        let mut cursor = db
+            .collection("users")
+            .find(None, None).await
+            .map_err(|_| Error::LookupFailed)?;
+
+        let mut users = Vec::new();
+        while let Some(doc) = cursor.next().await {
+            let doc = doc.map_err(|_| Error::LookupFailed)?;
+            users.push(user_from_doc(&doc)?);
+        }
+
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(users)
+    }
+
+    async fn delete_user(&self, name: &str) -> Result<bool, Error> {
+        let db = self.client.database(&self.config.dbname);
+        let res = db
+            .collection("users")
+            .delete_one(doc!{ "name": name }, None).await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        Ok(res.deleted_count > 0)
+    }
+
+    async fn put_key(&self, user_name: &str, key: &StreamKey) -> Result<(), Error> {
+        let db = self.client.database(&self.config.dbname);
+        let users = db.collection("users");
+        let upsert = UpdateOptions::builder().upsert(true).build();
+
+        users
+            .update_one(
+                doc!{ "name": user_name },
+                doc!{ "$pull": { "keys": { "name": &key.name } } },
+                upsert)
+            .await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        let mut update = doc!{ "$push": { "keys": stream_key_to_doc(key) } };
+        if key.name == DEFAULT_KEY_NAME {
+            update.insert("$unset", doc!{ "key": "" });
         }
 
-        Ok(None)
+        users
+            .update_one(doc!{ "name": user_name }, update, None)
+            .await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        Ok(())
     }
 
-    async fn add_user_with_key(&mut self, name: &str, key: &str) -> Result<(), Error> {
+    async fn revoke_key(&self, user_name: &str, key_name: &str) -> Result<bool, Error> {
         let db = self.client.database(&self.config.dbname);
 
+        let mut update = doc!{ "$pull": { "keys": { "name": key_name } } };
+        if key_name == DEFAULT_KEY_NAME {
+            update.insert("$unset", doc!{ "key": "" });
+        }
+
+        let res = db.collection("users")
+            .update_one(doc!{ "name": user_name }, update, None)
+            .await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        Ok(res.modified_count > 0)
+    }
+
+    async fn touch_key(&self, user_name: &str, key_name: &str, used_at: DateTime<Utc>) -> Result<bool, Error> {
+        let db = self.client.database(&self.config.dbname);
+
+        let res = db.collection("users")
+            .update_one(
+                doc!{ "name": user_name, "keys.name": key_name },
+                doc!{ "$set": { "keys.$.last_used_at": used_at } },
+                None)
+            .await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        Ok(res.matched_count > 0)
+    }
+
+    async fn permissions(&self, user_name: &str) -> Result<Vec<StreamPermission>, Error> {
+        let db = self.client.database(&self.config.dbname);
+        let res = db
+            .collection("users")
+            .find_one(doc!{ "name": user_name }, None).await
+            .map_err(|_| Error::LookupFailed)?;
+
+        match res {
+            Some(doc) => permissions_from_doc(&doc),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn stream_publishers(&self, app: &str, stream: &str) -> Result<Vec<String>, Error> {
+        let db = self.client.database(&self.config.dbname);
+        let filter = doc!{
+            "streams": {
+                "$elemMatch": {
+                    "app": app,
+                    "stream": { "$in": [stream, StreamPermission::ANY_STREAM] },
+                },
+            },
+        };
+
+        let mut cursor = db
+            .collection("users")
+            .find(filter, None).await
+            .map_err(|_| Error::LookupFailed)?;
+
+        let mut exact = Vec::new();
+        let mut wildcard = Vec::new();
+
+        while let Some(doc) = cursor.next().await {
+            let doc = doc.map_err(|_| Error::LookupFailed)?;
+            let name = doc.get_str("name").map_err(|_| Error::LookupFailed)?.to_string();
+            let permissions = permissions_from_doc(&doc)?;
+
+            // exact matches are preferred over wildcards
+            if permissions.iter().any(|permission| permission.app == app && permission.stream == stream) {
+                exact.push(name);
+            } else {
+                wildcard.push(name);
+            }
+        }
+
+        exact.extend(wildcard);
+        Ok(exact)
+    }
+
+    async fn allow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<(), Error> {
+        let db = self.client.database(&self.config.dbname);
+        let upsert = UpdateOptions::builder().upsert(true).build();
+
         db.collection("users")
-            .insert_one(doc! { "name": name, "key": key }, None).await
+            .update_one(
+                doc!{ "name": user_name },
+                doc!{ "$addToSet": { "streams": permission_to_doc(permission) } },
+                upsert)
+            .await
             .map_err(|_| Error::UpdateFailed)?;
 
         Ok(())
     }
+
+    async fn disallow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<bool, Error> {
+        let db = self.client.database(&self.config.dbname);
+
+        let res = db.collection("users")
+            .update_one(
+                doc!{ "name": user_name },
+                doc!{ "$pull": { "streams": permission_to_doc(permission) } },
+                None)
+            .await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        Ok(res.modified_count > 0)
+    }
+}
+
+#[async_trait]
+impl SessionHistory for Mongo {
+    async fn record_session(&self, record: &SessionRecord) -> Result<(), Error> {
+        let db = self.client.database(&self.config.dbname);
+
+        db.collection("session_history")
+            .insert_one(session_to_doc(record), None).await
+            .map_err(|_| Error::UpdateFailed)?;
+
+        Ok(())
+    }
+
+    async fn sessions(&self, filter: &HistoryFilter) -> Result<Vec<SessionRecord>, Error> {
+        let db = self.client.database(&self.config.dbname);
+
+        let mut query = Document::new();
+        if let Some(user) = &filter.user {
+            query.insert("user", user);
+        }
+        if let Some(app) = &filter.app {
+            query.insert("app", app);
+        }
+        if let Some(since) = filter.since {
+            query.insert("started_at", doc!{ "$gte": since });
+        }
+
+        let mut options = FindOptions::default();
+        options.sort = Some(doc!{ "started_at": -1 });
+        options.limit = filter.limit.map(i64::from);
+
+        let mut cursor = db
+            .collection("session_history")
+            .find(query, options).await
+            .map_err(|_| Error::LookupFailed)?;
+
+        let mut records = Vec::new();
+        while let Some(doc) = cursor.next().await {
+            let doc = doc.map_err(|_| Error::LookupFailed)?;
+            records.push(session_from_doc(&doc)?);
+        }
+
+        Ok(records)
+    }
+}
+
+
+fn permissions_from_doc(doc: &Document) -> Result<Vec<StreamPermission>, Error> {
+    match doc.get_array("streams") {
+        Ok(streams) => streams
+            .iter()
+            .filter_map(Bson::as_document)
+            .map(permission_from_doc)
+            .collect(),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+fn permission_from_doc(doc: &Document) -> Result<StreamPermission, Error> {
+    Ok(StreamPermission {
+        app: doc.get_str("app").map_err(|_| Error::LookupFailed)?.to_string(),
+        stream: doc.get_str("stream").map_err(|_| Error::LookupFailed)?.to_string(),
+    })
+}
+
+fn permission_to_doc(permission: &StreamPermission) -> Document {
+    doc!{ "app": &permission.app, "stream": &permission.stream }
+}
+
+fn user_from_doc(doc: &Document) -> Result<User, Error> {
+    let name = doc.get_str("name").map_err(|_| Error::LookupFailed)?;
+
+    let mut keys = match doc.get_array("keys") {
+        Ok(keys) => keys
+            .iter()
+            .filter_map(Bson::as_document)
+            .map(stream_key_from_doc)
+            .collect::<Result<Vec<_>, _>>()?,
+        Err(_) => Vec::new(),
+    };
+
+    // older versions stored a single plaintext key
+    if let Ok(key) = doc.get_str("key") {
+        if !keys.iter().any(|key| key.name == DEFAULT_KEY_NAME) {
+            keys.push(StreamKey {
+                name: DEFAULT_KEY_NAME.to_string(),
+                hash: key.to_string(),
+                expires_at: None,
+                last_used_at: None,
+            });
+        }
+    }
+
+    Ok(User { name: name.to_string(), keys })
+}
+
+fn stream_key_from_doc(doc: &Document) -> Result<StreamKey, Error> {
+    Ok(StreamKey {
+        name: doc.get_str("name").map_err(|_| Error::LookupFailed)?.to_string(),
+        hash: doc.get_str("hash").map_err(|_| Error::LookupFailed)?.to_string(),
+        expires_at: doc.get_datetime("expires_at").ok().cloned(),
+        last_used_at: doc.get_datetime("last_used_at").ok().cloned(),
+    })
+}
+
+fn stream_key_to_doc(key: &StreamKey) -> Document {
+    let mut doc = doc!{ "name": &key.name, "hash": &key.hash };
+
+    if let Some(expires_at) = key.expires_at {
+        doc.insert("expires_at", expires_at);
+    }
+
+    if let Some(last_used_at) = key.last_used_at {
+        doc.insert("last_used_at", last_used_at);
+    }
+
+    doc
+}
+
+fn session_to_doc(record: &SessionRecord) -> Document {
+    let codec = &record.codec;
+
+    let mut doc = doc!{
+        "user": &record.user,
+        "app": &record.app,
+        "client_addr": &record.client_addr,
+        "started_at": record.started_at,
+        "ended_at": record.ended_at,
+        "duration": record.duration().num_seconds(),
+        "peak_viewers": record.peak_viewers as i64,
+        "bytes_received": record.bytes_received as i64,
+    };
+
+    let strings = [
+        ("video_codec", &codec.video_codec),
+        ("audio_codec", &codec.audio_codec),
+        ("encoder", &codec.encoder),
+    ];
+    for (key, value) in strings.iter() {
+        if let Some(value) = value {
+            doc.insert(*key, value);
+        }
+    }
+
+    let numbers = [
+        ("video_width", codec.video_width),
+        ("video_height", codec.video_height),
+        ("video_bitrate", codec.video_bitrate),
+        ("audio_sample_rate", codec.audio_sample_rate),
+        ("audio_channels", codec.audio_channels),
+        ("audio_bitrate", codec.audio_bitrate),
+    ];
+    for (key, value) in numbers.iter() {
+        if let Some(value) = value {
+            doc.insert(*key, i64::from(*value));
+        }
+    }
+
+    if let Some(frame_rate) = codec.video_frame_rate {
+        doc.insert("video_frame_rate", frame_rate);
+    }
+
+    doc
+}
+
+fn session_from_doc(doc: &Document) -> Result<SessionRecord, Error> {
+    let string = |key| doc.get_str(key).ok().map(ToString::to_string);
+    let number = |key| doc.get_i64(key).ok().map(|value| value as u32);
+
+    Ok(SessionRecord {
+        user: doc.get_str("user").map_err(|_| Error::LookupFailed)?.to_string(),
+        app: doc.get_str("app").map_err(|_| Error::LookupFailed)?.to_string(),
+        client_addr: doc.get_str("client_addr").map_err(|_| Error::LookupFailed)?.to_string(),
+        started_at: *doc.get_datetime("started_at").map_err(|_| Error::LookupFailed)?,
+        ended_at: *doc.get_datetime("ended_at").map_err(|_| Error::LookupFailed)?,
+        peak_viewers: doc.get_i64("peak_viewers").map_err(|_| Error::LookupFailed)? as u64,
+        bytes_received: doc.get_i64("bytes_received").map_err(|_| Error::LookupFailed)? as u64,
+        codec: CodecParameters {
+            video_codec: string("video_codec"),
+            video_width: number("video_width"),
+            video_height: number("video_height"),
+            video_frame_rate: doc.get_f64("video_frame_rate").ok(),
+            video_bitrate: number("video_bitrate"),
+            audio_codec: string("audio_codec"),
+            audio_sample_rate: number("audio_sample_rate"),
+            audio_channels: number("audio_channels"),
+            audio_bitrate: number("audio_bitrate"),
+            encoder: string("encoder"),
+        },
+    })
 }
@@ -1,93 +1,430 @@
+mod migrations;
+
+
 use {
-    std::path::PathBuf,
+    anyhow::{Result, Context},
+    chrono::{DateTime, TimeZone, Utc},
+    tokio::task,
     r2d2_sqlite::{
         SqliteConnectionManager,
-        rusqlite::{named_params, params, OptionalExtension},
+        rusqlite::{Connection, Row, named_params, params, OptionalExtension},
     },
     javelin_types::{
         async_trait,
-        models::{UserRepository, User, Error},
+        models::{
+            UserRepository, User, StreamKey, StreamPermission,
+            SessionHistory, SessionRecord, CodecParameters, HistoryFilter,
+            Error, DEFAULT_KEY_NAME,
+        },
     },
-    javelin_core::Config,
+    self::migrations::{Migration, MigrationStatus},
+    super::SqliteConfig,
 };
 
+
 type Pool = r2d2::Pool<SqliteConnectionManager>;
 
 #[derive(Clone)]
-pub struct Database {
+pub struct Sqlite {
     pool: Pool,
 }
 
 
-impl Database {
-    pub async fn new(config: &Config) -> Self {
-        let path: PathBuf = config.get("database.sqlite.path").unwrap();
-        let manager = SqliteConnectionManager::file(path);
+impl Sqlite {
+    /// Open the database and apply all pending migrations.
+    pub async fn new(config: &SqliteConfig) -> Result<Self> {
+        let database = Self::open(config)?;
+
+        log::debug!("Initializing database");
+        database.migrate()?;
+
+        Ok(database)
+    }
+
+    /// Open the database without touching its schema.
+    pub fn open(config: &SqliteConfig) -> Result<Self> {
+        let path = config.path.as_ref().context("No SQLite database path configured (database.sqlite.path)")?;
+        let manager = SqliteConnectionManager::file(path)
+            .with_init(|conn| conn.execute_batch("PRAGMA foreign_keys = ON;"));
         let pool = Pool::builder()
             .max_size(20)
-            .build(manager)
-            .unwrap();
+            .build(manager)?;
 
-        initialize_tables(&pool);
+        Ok(Self { pool })
+    }
 
-        Self { pool }
+    pub fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        let mut conn = self.pool.get()?;
+        migrations::run_pending(&mut conn)
+    }
+
+    pub fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        let conn = self.pool.get()?;
+        migrations::status(&conn)
     }
-}
 
-fn initialize_tables(pool: &Pool) {
-    let conn = pool.get().unwrap();
+    async fn lookup<F, T>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static
+    {
+        self.blocking(f, || Error::LookupFailed).await
+    }
 
-    log::debug!("Initializing database");
+    async fn update<F, T>(&self, f: F) -> Result<T, Error>
+        where F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static
+    {
+        self.blocking(f, || Error::UpdateFailed).await
+    }
 
-    let create_users = include_str!("sqlite/users.sql");
+    /// Run a query on the blocking thread pool, waiting for a connection included.
+    async fn blocking<F, T>(&self, f: F, failed: fn() -> Error) -> Result<T, Error>
+        where F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+              T: Send + 'static
+    {
+        let pool = self.pool.clone();
 
-    conn.execute(create_users, params![])
-        .expect("Failed to create users table");
+        task::spawn_blocking(move || {
+                let conn = pool.get().map_err(|_| failed())?;
+                f(&conn)
+            })
+            .await
+            .map_err(|_| failed())?
+    }
 }
 
 #[async_trait]
-impl UserRepository for Database {
+impl UserRepository for Sqlite {
     async fn user_by_name(&self, name: &str) -> Result<Option<User>, Error> {
-        let conn = self.pool.get()
-            .map_err(|_| Error::LookupFailed)?;
+        let name = name.to_string();
+        self.lookup(move |conn| load_user(conn, &name)).await
+    }
 
-        let mut stmt = conn
-            .prepare("SELECT name, key FROM users WHERE name=?")
-            .map_err(|_| Error::LookupFailed)?;
+    async fn users(&self) -> Result<Vec<User>, Error> {
+        self.lookup(|conn| {
+            let mut stmt = conn
+                .prepare("SELECT name FROM users ORDER BY name")
+                .map_err(|_| Error::LookupFailed)?;
 
-        stmt.query_row(&[name], |row| {
-                Ok(User {
-                    name: row.get(0)?,
-                    key: row.get(1)?
-                })
-            })
-            .optional()
-            .map_err(|_| Error::LookupFailed)
-    }
-
-    async fn add_user_with_key(&mut self, name: &str, key: &str) -> Result<(), Error> {
-        let conn = self.pool.get()
-            .map_err(|_| Error::UpdateFailed)?;
-
-        let stmt = match self.user_by_name(&name).await? {
-            Some(user) if &user.key == &key => {
-                log::debug!("User with key already exists, no update required");
-                return Ok(());
-            },
-            Some(_) => {
-                log::debug!("Updating existing user");
-                conn.prepare("UPDATE users SET key=:key WHERE name=:name")
-            },
-            None => {
-                log::debug!("Creating new user");
-                conn.prepare("INSERT INTO users (name, key) VALUES (:name, :key)")
+            let names = stmt.query_map(params![], |row| row.get::<_, String>(0))
+                .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                .map_err(|_| Error::LookupFailed)?;
+
+            let mut users = Vec::with_capacity(names.len());
+            for name in names {
+                if let Some(user) = load_user(conn, &name)? {
+                    users.push(user);
+                }
             }
-        };
 
-        stmt.expect("Failed to prepare SQL statement")
-            .execute_named(named_params!{":name": name, ":key": key})
-            .map_err(|_| Error::UpdateFailed)?;
+            Ok(users)
+        }).await
+    }
+
+    async fn delete_user(&self, name: &str) -> Result<bool, Error> {
+        let name = name.to_string();
+
+        // keys and permissions are removed by ON DELETE CASCADE
+        self.update(move |conn| {
+            let removed = conn.execute("DELETE FROM users WHERE name=?", &[&name])
+                .map_err(|_| Error::UpdateFailed)?;
 
-        Ok(())
+            Ok(removed > 0)
+        }).await
     }
+
+    async fn put_key(&self, user_name: &str, key: &StreamKey) -> Result<(), Error> {
+        let user_name = user_name.to_string();
+        let key = key.clone();
+
+        self.update(move |conn| {
+            conn.execute("INSERT OR IGNORE INTO users (name) VALUES (?)", &[&user_name])
+                .map_err(|_| Error::UpdateFailed)?;
+
+            conn.execute_named(
+                    "INSERT OR REPLACE INTO stream_keys (user_id, name, hash, expires_at, last_used_at) \
+                     VALUES ((SELECT id FROM users WHERE name=:user), :name, :hash, :expires_at, :last_used_at)",
+                    named_params!{
+                        ":user": user_name,
+                        ":name": key.name,
+                        ":hash": key.hash,
+                        ":expires_at": key.expires_at.map(|time| time.timestamp()),
+                        ":last_used_at": key.last_used_at.map(|time| time.timestamp()),
+                    })
+                .map_err(|_| Error::UpdateFailed)?;
+
+            if key.name == DEFAULT_KEY_NAME {
+                remove_legacy_key(conn, &user_name)?;
+            }
+
+            Ok(())
+        }).await
+    }
+
+    async fn revoke_key(&self, user_name: &str, key_name: &str) -> Result<bool, Error> {
+        let user_name = user_name.to_string();
+        let key_name = key_name.to_string();
+
+        self.update(move |conn| {
+            let mut removed = conn
+                .execute_named(
+                    "DELETE FROM stream_keys WHERE name=:name AND user_id=(SELECT id FROM users WHERE name=:user)",
+                    named_params!{":user": user_name, ":name": key_name})
+                .map_err(|_| Error::UpdateFailed)?;
+
+            if key_name == DEFAULT_KEY_NAME {
+                removed += remove_legacy_key(conn, &user_name)?;
+            }
+
+            Ok(removed > 0)
+        }).await
+    }
+
+    async fn touch_key(&self, user_name: &str, key_name: &str, used_at: DateTime<Utc>) -> Result<bool, Error> {
+        let user_name = user_name.to_string();
+        let key_name = key_name.to_string();
+
+        self.update(move |conn| {
+            let updated = conn
+                .execute_named(
+                    "UPDATE stream_keys SET last_used_at=:last_used_at \
+                     WHERE name=:name AND user_id=(SELECT id FROM users WHERE name=:user)",
+                    named_params!{
+                        ":user": user_name,
+                        ":name": key_name,
+                        ":last_used_at": used_at.timestamp(),
+                    })
+                .map_err(|_| Error::UpdateFailed)?;
+
+            Ok(updated > 0)
+        }).await
+    }
+
+    async fn permissions(&self, user_name: &str) -> Result<Vec<StreamPermission>, Error> {
+        let user_name = user_name.to_string();
+
+        self.lookup(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT app, stream FROM stream_permissions \
+                     WHERE user_id=(SELECT id FROM users WHERE name=?) ORDER BY app, stream")
+                .map_err(|_| Error::LookupFailed)?;
+
+            stmt.query_map(&[&user_name], |row| {
+                    Ok(StreamPermission {
+                        app: row.get(0)?,
+                        stream: row.get(1)?,
+                    })
+                })
+                .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                .map_err(|_| Error::LookupFailed)
+        }).await
+    }
+
+    async fn stream_publishers(&self, app: &str, stream: &str) -> Result<Vec<String>, Error> {
+        let app = app.to_string();
+        let stream = stream.to_string();
+
+        self.lookup(move |conn| {
+            // exact matches are preferred over wildcards
+            let mut stmt = conn
+                .prepare(
+                    "SELECT users.name FROM stream_permissions \
+                     JOIN users ON users.id = stream_permissions.user_id \
+                     WHERE app=:app AND (stream=:stream OR stream=:any) \
+                     ORDER BY stream=:any, users.name")
+                .map_err(|_| Error::LookupFailed)?;
+
+            let params = named_params!{":app": app, ":stream": stream, ":any": StreamPermission::ANY_STREAM};
+            stmt.query_map_named(params, |row| row.get::<_, String>(0))
+                .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                .map_err(|_| Error::LookupFailed)
+        }).await
+    }
+
+    async fn allow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<(), Error> {
+        let user_name = user_name.to_string();
+        let permission = permission.clone();
+
+        self.update(move |conn| {
+            conn.execute("INSERT OR IGNORE INTO users (name) VALUES (?)", &[&user_name])
+                .map_err(|_| Error::UpdateFailed)?;
+
+            conn.execute_named(
+                    "INSERT OR IGNORE INTO stream_permissions (user_id, app, stream) \
+                     VALUES ((SELECT id FROM users WHERE name=:user), :app, :stream)",
+                    named_params!{":user": user_name, ":app": permission.app, ":stream": permission.stream})
+                .map_err(|_| Error::UpdateFailed)?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn disallow_publish(&self, user_name: &str, permission: &StreamPermission) -> Result<bool, Error> {
+        let user_name = user_name.to_string();
+        let permission = permission.clone();
+
+        self.update(move |conn| {
+            let removed = conn
+                .execute_named(
+                    "DELETE FROM stream_permissions \
+                     WHERE app=:app AND stream=:stream AND user_id=(SELECT id FROM users WHERE name=:user)",
+                    named_params!{":user": user_name, ":app": permission.app, ":stream": permission.stream})
+                .map_err(|_| Error::UpdateFailed)?;
+
+            Ok(removed > 0)
+        }).await
+    }
+}
+
+#[async_trait]
+impl SessionHistory for Sqlite {
+    async fn record_session(&self, record: &SessionRecord) -> Result<(), Error> {
+        let record = record.clone();
+
+        self.update(move |conn| {
+            let codec = &record.codec;
+
+            conn.execute_named(
+                    "INSERT INTO session_history (\
+                        user, app, client_addr, started_at, ended_at, duration, peak_viewers, bytes_received, \
+                        video_codec, video_width, video_height, video_frame_rate, video_bitrate, \
+                        audio_codec, audio_sample_rate, audio_channels, audio_bitrate, encoder\
+                     ) VALUES (\
+                        :user, :app, :client_addr, :started_at, :ended_at, :duration, :peak_viewers, :bytes_received, \
+                        :video_codec, :video_width, :video_height, :video_frame_rate, :video_bitrate, \
+                        :audio_codec, :audio_sample_rate, :audio_channels, :audio_bitrate, :encoder\
+                     )",
+                    named_params!{
+                        ":user": record.user,
+                        ":app": record.app,
+                        ":client_addr": record.client_addr,
+                        ":started_at": record.started_at.timestamp(),
+                        ":ended_at": record.ended_at.timestamp(),
+                        ":duration": record.duration().num_seconds(),
+                        ":peak_viewers": record.peak_viewers as i64,
+                        ":bytes_received": record.bytes_received as i64,
+                        ":video_codec": codec.video_codec,
+                        ":video_width": codec.video_width,
+                        ":video_height": codec.video_height,
+                        ":video_frame_rate": codec.video_frame_rate,
+                        ":video_bitrate": codec.video_bitrate,
+                        ":audio_codec": codec.audio_codec,
+                        ":audio_sample_rate": codec.audio_sample_rate,
+                        ":audio_channels": codec.audio_channels,
+                        ":audio_bitrate": codec.audio_bitrate,
+                        ":encoder": codec.encoder,
+                    })
+                .map_err(|_| Error::UpdateFailed)?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn sessions(&self, filter: &HistoryFilter) -> Result<Vec<SessionRecord>, Error> {
+        let filter = filter.clone();
+
+        self.lookup(move |conn| {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT user, app, client_addr, started_at, ended_at, peak_viewers, bytes_received, \
+                        video_codec, video_width, video_height, video_frame_rate, video_bitrate, \
+                        audio_codec, audio_sample_rate, audio_channels, audio_bitrate, encoder \
+                     FROM session_history \
+                     WHERE (:user IS NULL OR user=:user) \
+                       AND (:app IS NULL OR app=:app) \
+                       AND (:since IS NULL OR started_at>=:since) \
+                     ORDER BY started_at DESC \
+                     LIMIT :limit")
+                .map_err(|_| Error::LookupFailed)?;
+
+            let params = named_params!{
+                ":user": filter.user,
+                ":app": filter.app,
+                ":since": filter.since.map(|time| time.timestamp()),
+                ":limit": filter.limit.map_or(-1, i64::from),
+            };
+
+            stmt.query_map_named(params, session_from_row)
+                .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                .map_err(|_| Error::LookupFailed)
+        }).await
+    }
+}
+
+
+fn load_user(conn: &Connection, name: &str) -> Result<Option<User>, Error> {
+    let user: Option<(i64, Option<String>)> = conn
+        .query_row("SELECT id, key FROM users WHERE name=?", &[name], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()
+        .map_err(|_| Error::LookupFailed)?;
+
+    let (user_id, legacy_key) = match user {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    let mut stmt = conn
+        .prepare("SELECT name, hash, expires_at, last_used_at FROM stream_keys WHERE user_id=?")
+        .map_err(|_| Error::LookupFailed)?;
+
+    let mut keys = stmt
+        .query_map(params![user_id], |row| {
+            Ok(StreamKey {
+                name: row.get(0)?,
+                hash: row.get(1)?,
+                expires_at: row.get::<_, Option<i64>>(2)?.map(from_timestamp),
+                last_used_at: row.get::<_, Option<i64>>(3)?.map(from_timestamp),
+            })
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|_| Error::LookupFailed)?;
+
+    if let Some(key) = legacy_key {
+        if !keys.iter().any(|key| key.name == DEFAULT_KEY_NAME) {
+            keys.push(StreamKey {
+                name: DEFAULT_KEY_NAME.to_string(),
+                hash: key,
+                expires_at: None,
+                last_used_at: None,
+            });
+        }
+    }
+
+    Ok(Some(User { name: name.to_string(), keys }))
+}
+
+fn session_from_row(row: &Row) -> r2d2_sqlite::rusqlite::Result<SessionRecord> {
+    Ok(SessionRecord {
+        user: row.get(0)?,
+        app: row.get(1)?,
+        client_addr: row.get(2)?,
+        started_at: from_timestamp(row.get(3)?),
+        ended_at: from_timestamp(row.get(4)?),
+        peak_viewers: row.get::<_, i64>(5)? as u64,
+        bytes_received: row.get::<_, i64>(6)? as u64,
+        codec: CodecParameters {
+            video_codec: row.get(7)?,
+            video_width: row.get(8)?,
+            video_height: row.get(9)?,
+            video_frame_rate: row.get(10)?,
+            video_bitrate: row.get(11)?,
+            audio_codec: row.get(12)?,
+            audio_sample_rate: row.get(13)?,
+            audio_channels: row.get(14)?,
+            audio_bitrate: row.get(15)?,
+            encoder: row.get(16)?,
+        },
+    })
+}
+
+fn remove_legacy_key(conn: &Connection, user_name: &str) -> Result<usize, Error> {
+    conn.execute("UPDATE users SET key=NULL WHERE name=? AND key IS NOT NULL", &[user_name])
+        .map_err(|_| Error::UpdateFailed)
+}
+
+fn from_timestamp(secs: i64) -> DateTime<Utc> {
+    Utc.timestamp(secs, 0)
 }
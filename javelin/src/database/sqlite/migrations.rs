@@ -0,0 +1,123 @@
+use {
+    std::collections::HashMap,
+    anyhow::{Result, Context, bail},
+    chrono::{DateTime, Utc},
+    r2d2_sqlite::rusqlite::{Connection, params},
+    super::from_timestamp,
+};
+
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+/// All schema changes, in the order they have to be applied.
+///
+/// Migrations must never be changed once released, add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_users",
+        sql: include_str!("migrations/0001_create_users.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "relax_users_key",
+        sql: include_str!("migrations/0002_relax_users_key.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "create_stream_keys",
+        sql: include_str!("migrations/0003_create_stream_keys.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "create_stream_permissions",
+        sql: include_str!("migrations/0004_create_stream_permissions.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "create_session_history",
+        sql: include_str!("migrations/0005_create_session_history.sql"),
+    },
+];
+
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+
+/// Apply all pending migrations in a single transaction.
+pub fn run_pending(conn: &mut Connection) -> Result<Vec<&'static Migration>> {
+    let applied = applied_versions(conn)?;
+
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .collect::<Vec<_>>();
+
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+
+    // tables that get rebuilt would otherwise take their references with them,
+    // this can not be changed inside of a transaction
+    conn.execute_batch("PRAGMA foreign_keys = OFF;")?;
+    let result = apply(conn, &pending);
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    result?;
+
+    Ok(pending)
+}
+
+pub fn status(conn: &Connection) -> Result<Vec<MigrationStatus>> {
+    let applied = applied_versions(conn)?;
+
+    Ok(MIGRATIONS
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied_at: applied.get(&migration.version).copied(),
+        })
+        .collect())
+}
+
+fn apply(conn: &mut Connection, migrations: &[&Migration]) -> Result<()> {
+    let tx = conn.transaction()?;
+
+    for migration in migrations {
+        log::info!("Applying database migration {} ({})", migration.version, migration.name);
+
+        tx.execute_batch(migration.sql)
+            .with_context(|| format!("Database migration {} failed", migration.version))?;
+
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)",
+            params![migration.version, migration.name, Utc::now().timestamp()])?;
+    }
+
+    if tx.prepare("PRAGMA foreign_key_check")?.exists(params![])? {
+        bail!("Database migrations left broken references behind");
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn applied_versions(conn: &Connection) -> Result<HashMap<i64, DateTime<Utc>>> {
+    conn.execute_batch(include_str!("schema_migrations.sql"))?;
+
+    let mut stmt = conn.prepare("SELECT version, applied_at FROM schema_migrations")?;
+    let versions = stmt
+        .query_map(params![], |row| Ok((row.get(0)?, from_timestamp(row.get(1)?))))?
+        .collect::<Result<_, _>>()?;
+
+    Ok(versions)
+}
@@ -0,0 +1,9 @@
+-- older versions required a plaintext key for every user
+CREATE TABLE users_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    key TEXT UNIQUE
+);
+INSERT INTO users_new (id, name, key) SELECT id, name, key FROM users;
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
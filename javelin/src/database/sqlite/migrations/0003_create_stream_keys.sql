@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS stream_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    expires_at INTEGER,
+    last_used_at INTEGER,
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS stream_permissions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    app TEXT NOT NULL,
+    stream TEXT NOT NULL,
+    UNIQUE (user_id, app, stream)
+);
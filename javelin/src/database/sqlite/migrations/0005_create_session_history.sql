@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS session_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user TEXT NOT NULL,
+    app TEXT NOT NULL,
+    client_addr TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    peak_viewers INTEGER NOT NULL,
+    bytes_received INTEGER NOT NULL,
+    video_codec TEXT,
+    video_width INTEGER,
+    video_height INTEGER,
+    video_frame_rate REAL,
+    video_bitrate INTEGER,
+    audio_codec TEXT,
+    audio_sample_rate INTEGER,
+    audio_channels INTEGER,
+    audio_bitrate INTEGER,
+    encoder TEXT
+);
+CREATE INDEX IF NOT EXISTS session_history_started_at ON session_history (started_at);
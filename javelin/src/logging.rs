@@ -0,0 +1,321 @@
+mod rotating_file;
+
+
+use {
+    std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{Mutex, RwLock},
+    },
+    anyhow::Result,
+    chrono::{DateTime, Utc, Local as LocalTime},
+    fern::{Dispatch, Output, colors::ColoredLevelConfig},
+    lazy_static::lazy_static,
+    serde::{Serialize, Deserialize, Deserializer, de::Error as _},
+    serde_json::json,
+    log::{Level, LevelFilter, Metadata, Record},
+    self::rotating_file::RotatingFile,
+};
+
+
+lazy_static! {
+    static ref CONFIG: RwLock<LogConfig> = RwLock::new(LogConfig::default());
+    static ref LOG_FILE: Mutex<FileOutput> = Mutex::new(FileOutput::Pending(Vec::new()));
+}
+
+
+/// Entries are kept back until the log directory is known.
+enum FileOutput {
+    Pending(Vec<Entry>),
+    Open(RotatingFile),
+}
+
+
+struct Entry {
+    level: Level,
+    timestamp: DateTime<Utc>,
+    target: String,
+    message: String,
+}
+
+impl Entry {
+    fn format(&self, format: LogFormat) -> String {
+        let timestamp = self.timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ");
+
+        match format {
+            LogFormat::Text => format!(
+                "level={:5} timestamp={} target={}  {}",
+                self.level,
+                timestamp,
+                self.target,
+                self.message
+            ),
+            LogFormat::Json => json!({
+                "level": self.level.to_string(),
+                "timestamp": timestamp.to_string(),
+                "target": self.target,
+                "message": self.message,
+            }).to_string(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level of all targets without their own entry in `targets`
+    #[serde(default = "default_level", deserialize_with = "deserialize_level")]
+    pub level: LevelFilter,
+
+    /// Levels of single crates or modules, e.g. `javelin_rtmp::peer: warn`
+    #[serde(default = "default_targets", deserialize_with = "deserialize_targets")]
+    pub targets: HashMap<String, LevelFilter>,
+
+    /// Directory of `javelin.log` and its rotated files
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+
+    #[serde(default)]
+    pub format: LogFormat,
+
+    #[serde(default)]
+    pub rotation: RotationConfig,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            targets: default_targets(),
+            dir: default_dir(),
+            format: LogFormat::default(),
+            rotation: RotationConfig::default(),
+        }
+    }
+}
+
+
+/// Format of the log file, the console always uses plain text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// `level=INFO timestamp=... target=...  message`
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotationConfig {
+    /// Rotate once the file would get bigger than this many bytes, 0 disables it
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+
+    #[serde(default)]
+    pub interval: RotationInterval,
+
+    /// Number of rotated files to keep
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_max_size(),
+            interval: RotationInterval::default(),
+            keep: default_keep(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationInterval {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Default for RotationInterval {
+    fn default() -> Self {
+        Self::Daily
+    }
+}
+
+
+fn default_level() -> LevelFilter {
+    LevelFilter::Info
+}
+
+fn default_targets() -> HashMap<String, LevelFilter> {
+    let mut targets = HashMap::new();
+    for target in &["javelin", "javelin_rtmp", "javelin_hls", "javelin_api", "javelin_types", "javelin_core"] {
+        targets.insert(target.to_string(), LevelFilter::Debug);
+    }
+    targets.insert("javelin_codec".to_string(), LevelFilter::Warn);
+    targets
+}
+
+// levels are matched case insensitive, unlike enum variants
+fn deserialize_level<'de, D>(deserializer: D) -> Result<LevelFilter, D::Error>
+    where D: Deserializer<'de>
+{
+    let level = String::deserialize(deserializer)?;
+    level.parse().map_err(|_| D::Error::custom(format!("invalid log level '{}'", level)))
+}
+
+fn deserialize_targets<'de, D>(deserializer: D) -> Result<HashMap<String, LevelFilter>, D::Error>
+    where D: Deserializer<'de>
+{
+    HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(target, level)| match level.parse() {
+            Ok(level) => Ok((target, level)),
+            Err(_) => Err(D::Error::custom(format!("invalid log level '{}' for {}", level, target))),
+        })
+        .collect()
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+fn default_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_keep() -> usize {
+    7
+}
+
+
+/// Log file entries are kept back until [`configure`](configure) sets the log directory.
+pub fn init() -> Result<()> {
+    let colors = ColoredLevelConfig::default();
+
+    Dispatch::new()
+        .filter(is_enabled)
+        .chain(Output::call(write_to_file))
+        .chain(Dispatch::new()
+            .format(move |out, msg, record| {
+                out.finish(format_args!(
+                    "[{:5}] {} ({}) {}",
+                    colors.color(record.level()),
+                    LocalTime::now().format("%Y-%m-%d %H:%M:%S"),
+                    record.target(),
+                    msg
+                ))
+            })
+            .chain(std::io::stdout())
+        )
+        .apply()?;
+
+    Ok(())
+}
+
+/// Apply the log settings, can be called again when the configuration changes.
+pub fn configure(config: &LogConfig) {
+    let mut output = LOG_FILE.lock().unwrap();
+
+    match &mut *output {
+        FileOutput::Open(file) if file.dir() == config.dir => {
+            file.set_rotation(config.rotation.clone());
+        },
+        _ => match RotatingFile::new(&config.dir, config.rotation.clone()) {
+            Ok(mut file) => {
+                if let FileOutput::Pending(entries) = &*output {
+                    for entry in entries {
+                        write_line(&mut file, &entry.format(config.format));
+                    }
+                }
+                *output = FileOutput::Open(file);
+            },
+            Err(why) => eprintln!("Failed to open log directory '{}': {}", config.dir.display(), why),
+        },
+    }
+
+    *CONFIG.write().unwrap() = config.clone();
+}
+
+fn is_enabled(metadata: &Metadata<'_>) -> bool {
+    metadata.level() <= level_for(&CONFIG.read().unwrap(), metadata.target())
+}
+
+/// The most specific matching target wins.
+fn level_for(config: &LogConfig, target: &str) -> LevelFilter {
+    config.targets
+        .iter()
+        .filter(|(name, _)| {
+            target == name.as_str()
+                || (target.starts_with(name.as_str()) && target[name.len()..].starts_with("::"))
+        })
+        .max_by_key(|(name, _)| name.len())
+        .map_or(config.level, |(_, level)| *level)
+}
+
+fn write_to_file(record: &Record<'_>) {
+    let entry = Entry {
+        level: record.level(),
+        timestamp: Utc::now(),
+        target: record.target().to_string(),
+        message: record.args().to_string(),
+    };
+
+    match &mut *LOG_FILE.lock().unwrap() {
+        FileOutput::Pending(entries) => entries.push(entry),
+        FileOutput::Open(file) => write_line(file, &entry.format(CONFIG.read().unwrap().format)),
+    }
+}
+
+fn write_line(file: &mut RotatingFile, line: &str) {
+    if let Err(why) = file.write_line(line) {
+        eprintln!("Failed to write log file: {}", why);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(targets: &[(&str, LevelFilter)]) -> LogConfig {
+        LogConfig {
+            level: LevelFilter::Info,
+            targets: targets.iter().map(|(name, level)| (name.to_string(), *level)).collect(),
+            ..LogConfig::default()
+        }
+    }
+
+    #[test]
+    fn longest_matching_target_wins() {
+        let config = config(&[
+            ("javelin_rtmp", LevelFilter::Warn),
+            ("javelin_rtmp::peer", LevelFilter::Debug),
+        ]);
+
+        assert_eq!(level_for(&config, "javelin_rtmp::peer"), LevelFilter::Debug);
+        assert_eq!(level_for(&config, "javelin_rtmp::peer::handshake"), LevelFilter::Debug);
+        assert_eq!(level_for(&config, "javelin_rtmp::service"), LevelFilter::Warn);
+        assert_eq!(level_for(&config, "javelin_rtmp"), LevelFilter::Warn);
+        assert_eq!(level_for(&config, "javelin_hls"), LevelFilter::Info);
+    }
+
+    #[test]
+    fn targets_only_match_whole_modules() {
+        let config = config(&[("javelin", LevelFilter::Error)]);
+
+        assert_eq!(level_for(&config, "javelin::logging"), LevelFilter::Error);
+        assert_eq!(level_for(&config, "javelin_rtmp"), LevelFilter::Info);
+    }
+}
@@ -0,0 +1,227 @@
+use {
+    std::{
+        fs::{self, File, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+    },
+    chrono::{DateTime, Utc},
+    super::{RotationConfig, RotationInterval},
+};
+
+
+const FILE_PREFIX: &str = "javelin";
+const FILE_EXTENSION: &str = "log";
+
+
+/// A log file that is rotated once it gets too big or too old.
+///
+/// Rotated files are renamed to `javelin.<timestamp>.log`, only the newest
+/// `keep` of them are retained.
+pub struct RotatingFile {
+    dir: PathBuf,
+    rotation: RotationConfig,
+    file: Option<File>,
+    size: u64,
+    opened_at: DateTime<Utc>,
+}
+
+impl RotatingFile {
+    pub fn new<P>(dir: P, rotation: RotationConfig) -> io::Result<Self>
+        where P: Into<PathBuf>
+    {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            rotation,
+            file: None,
+            size: 0,
+            opened_at: Utc::now(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_rotation(&mut self, rotation: RotationConfig) {
+        self.rotation = rotation;
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        if self.needs_rotation(line.len() as u64 + 1) {
+            self.rotate()?;
+        }
+
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", line)?;
+            self.size += line.len() as u64 + 1;
+        }
+
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let path = self.current_path();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+
+        self.size = metadata.len();
+        self.opened_at = match metadata.modified() {
+            Ok(modified) if self.size > 0 => modified.into(),
+            _ => Utc::now(),
+        };
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    fn needs_rotation(&self, additional: u64) -> bool {
+        let max_size = self.rotation.max_size;
+        if max_size > 0 && self.size > 0 && self.size + additional > max_size {
+            return true;
+        }
+
+        let period = match self.rotation.interval {
+            RotationInterval::Never => return false,
+            RotationInterval::Hourly => "%Y%m%d%H",
+            RotationInterval::Daily => "%Y%m%d",
+        };
+
+        self.opened_at.format(period).to_string() != Utc::now().format(period).to_string()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+
+        let timestamp = Utc::now().format("%Y%m%d-%H%M%S%.3f");
+        let rotated = self.dir.join(format!("{}.{}.{}", FILE_PREFIX, timestamp, FILE_EXTENSION));
+        fs::rename(self.current_path(), rotated)?;
+
+        self.remove_old_files()?;
+        self.open()
+    }
+
+    fn remove_old_files(&self) -> io::Result<()> {
+        let current = self.current_path();
+        let prefix = format!("{}.", FILE_PREFIX);
+        let suffix = format!(".{}", FILE_EXTENSION);
+
+        let mut rotated = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| *path != current)
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                name.starts_with(&prefix) && name.ends_with(&suffix)
+            })
+            .collect::<Vec<_>>();
+
+        // timestamps sort chronologically, newest last
+        rotated.sort();
+
+        let excess = rotated.len().saturating_sub(self.rotation.keep);
+        for path in rotated.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn current_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.{}", FILE_PREFIX, FILE_EXTENSION))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use {
+        chrono::Duration,
+        super::*,
+    };
+
+    fn rotation(max_size: u64, interval: RotationInterval, keep: usize) -> RotationConfig {
+        RotationConfig { max_size, interval, keep }
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rotates_when_size_is_exceeded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(dir.path(), rotation(10, RotationInterval::Never, 5)).unwrap();
+
+        // an empty file is never rotated, however long the line is
+        assert!(!file.needs_rotation(100));
+
+        file.size = 5;
+        assert!(!file.needs_rotation(5));
+        assert!(file.needs_rotation(6));
+
+        file.set_rotation(rotation(0, RotationInterval::Never, 5));
+        assert!(!file.needs_rotation(100));
+    }
+
+    #[test]
+    fn rotates_when_interval_has_passed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(dir.path(), rotation(0, RotationInterval::Daily, 5)).unwrap();
+
+        file.opened_at = Utc::now() - Duration::days(1);
+        assert!(file.needs_rotation(1));
+
+        file.set_rotation(rotation(0, RotationInterval::Never, 5));
+        assert!(!file.needs_rotation(1));
+    }
+
+    #[test]
+    fn keeps_only_newest_rotated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = RotatingFile::new(dir.path(), rotation(0, RotationInterval::Never, 2)).unwrap();
+
+        let names = [
+            "javelin.log",
+            "javelin.20200101-000000.000.log",
+            "javelin.20200102-000000.000.log",
+            "javelin.20200103-000000.000.log",
+            "other.log",
+        ];
+        for name in &names {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        file.remove_old_files().unwrap();
+
+        assert_eq!(file_names(dir.path()), vec![
+            "javelin.20200102-000000.000.log",
+            "javelin.20200103-000000.000.log",
+            "javelin.log",
+            "other.log",
+        ]);
+    }
+
+    #[test]
+    fn write_line_rotates_full_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(dir.path(), rotation(16, RotationInterval::Never, 5)).unwrap();
+
+        file.write_line("first line").unwrap();
+        file.write_line("second line").unwrap();
+
+        let names = file_names(dir.path());
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"javelin.log".to_string()));
+        assert_eq!(fs::read_to_string(file.current_path()).unwrap(), "second line\n");
+    }
+}
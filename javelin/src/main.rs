@@ -4,35 +4,94 @@
 
 mod args;
 mod database;
+mod logging;
 mod management;
+mod reload;
+mod settings;
 
 
 use {
+    std::sync::Arc,
     anyhow::Result,
+    futures::future,
+    tokio::{task::JoinHandle, time::timeout},
     javelin_core::{
         session,
+        webhook,
+        playback::Authorizer,
+        shutdown,
         config::{self, Config},
     },
-    database::Database,
+    crate::settings::Settings,
 };
 
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    if let Err(why) = init_logger() {
+    if let Err(why) = logging::init() {
         eprintln!("Failed to initialize logger: {}", why);
     };
 
     let args = args::build();
     let config_dir = args.value_of("config_dir").unwrap_or_default();
     let config = config::from_path(config_dir)?;
+    let settings = Settings::from_config(&config)?;
+    logging::configure(&settings.log);
 
     match args.subcommand() {
         ("permit-stream", Some(args)) => {
-            management::permit_stream(args, &config).await?;
+            management::permit_stream(args, &settings).await?;
+        },
+        ("revoke-stream", Some(args)) => {
+            management::revoke_stream(args, &settings).await?;
+        },
+        ("rotate-key", Some(args)) => {
+            management::rotate_key(args, &settings).await?;
+        },
+        ("allow-publish", Some(args)) => {
+            management::allow_publish(args, &settings).await?;
+        },
+        ("disallow-publish", Some(args)) => {
+            management::disallow_publish(args, &settings).await?;
+        },
+        ("sign-playback", Some(args)) => {
+            management::sign_playback(args, &settings)?;
+        },
+        ("list-users", _) => {
+            management::list_users(&settings).await?;
+        },
+        ("show-user", Some(args)) => {
+            management::show_user(args, &settings).await?;
+        },
+        ("delete-user", Some(args)) => {
+            management::delete_user(args, &settings).await?;
+        },
+        ("export-users", Some(args)) => {
+            management::export_users(args, &settings).await?;
+        },
+        ("import-users", Some(args)) => {
+            management::import_users(args, &settings).await?;
+        },
+        ("history", Some(args)) => {
+            management::history(args, &settings).await?;
+        },
+        #[cfg(feature = "db-sqlite")]
+        ("db", Some(args)) => match args.subcommand_name() {
+            Some("migrate") => management::db_migrate(&settings)?,
+            Some("status") => management::db_status(&settings)?,
+            _ => (),
+        },
+        #[cfg(not(feature = "db-sqlite"))]
+        ("db", _) => {
+            anyhow::bail!("db subcommands require the db-sqlite feature");
+        },
+        ("config", Some(args)) => match args.subcommand_name() {
+            Some("check") => management::config_check(&settings),
+            Some("dump") => management::config_dump(&settings)?,
+            _ => (),
         },
         ("run", _) | ("", _) => {
-            run_app(&config).await?;
+            run_app(config_dir, config, settings).await?;
         },
         _ => ()
     }
@@ -40,77 +99,138 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn run_app(config: &Config) -> Result<()> {
-    let mut handles = Vec::new();
+async fn run_app(config_dir: &str, config: Config, settings: Settings) -> Result<()> {
+    let (shutdown_trigger, shutdown) = shutdown::channel();
+    let (updater, updates) = config::updates(settings.clone());
 
-    let database_handle = Database::new(&config).await;
+    // services that have to finish their work before the process exits
+    let mut handles: Vec<JoinHandle<()>> = Vec::new();
 
-    let session = session::Manager::new(database_handle.clone());
+    let database_handle = database::open(&settings).await?;
+
+    let session = session::Manager::new(database_handle.clone(), settings.session.clone(), settings.webhooks.clone())?;
     let session_handle = session.handle();
-    handles.push(tokio::spawn({
-        session.run()
-    }));
+    tokio::spawn({
+        session.run(
+            updates.clone().map(|settings| settings.session.clone()),
+            updates.clone().map(|settings| settings.webhooks.clone()))
+    });
+
+    tokio::spawn({
+        webhook::EventNotifier::new(session_handle.clone(), settings.webhooks.clone())?
+            .run(updates.clone().map(|settings| settings.webhooks.clone()))
+    });
+
+    // shared by HLS and RTMP playback
+    let authorizer = Arc::new(Authorizer::new(settings.playback.clone()));
+    tokio::spawn({
+        Arc::clone(&authorizer).run(updates.clone().map(|settings| settings.playback.clone()))
+    });
 
     #[cfg(feature = "hls")]
     handles.push(tokio::spawn({
-        javelin_hls::Service::new(session_handle.clone(), &config).run()
+        javelin_hls::Service::new(session_handle.clone(), settings.hls.clone(), Arc::clone(&authorizer))
+            .run(shutdown.clone(), updates.clone().map(|settings| settings.hls.clone()))
     }));
 
+    // disabled services would stop right away and be mistaken for failed ones
+    #[cfg(feature = "api")]
+    {
+        if settings.api.enabled {
+            handles.push(tokio::spawn({
+                javelin_api::Service::new(session_handle.clone(), database_handle.clone(), settings.api.clone())
+                    .run(shutdown.clone())
+            }));
+        }
+
+        if settings.metrics.enabled {
+            handles.push(tokio::spawn({
+                javelin_api::MetricsService::new(settings.metrics.clone()).run(shutdown.clone())
+            }));
+        }
+    }
+
     #[cfg(feature = "rtmp")]
     handles.push(tokio::spawn({
-        javelin_rtmp::Service::new(session_handle, &config).run()
+        javelin_rtmp::Service::new(session_handle, settings.rtmp.clone(), authorizer)
+            .run(shutdown.clone(), updates.clone().map(|settings| settings.rtmp.clone()))
     }));
 
-    // Wait for all spawned processes to complete
-    for handle in handles {
-        handle.await?;
+    let reloader = reload::Reloader::new(config_dir, config, updater);
+    tokio::spawn(async move {
+        if let Err(why) = reloader.run().await {
+            log::error!("Config reloading failed: {}", why);
+        }
+    });
+
+    drop(shutdown);
+
+    let service_failed = tokio::select! {
+        res = wait_for_signal() => {
+            res?;
+            false
+        },
+        _ = service_exited(&mut handles) => {
+            log::error!("A service stopped unexpectedly, shutting down");
+            true
+        },
+    };
+
+    let shutdown_config = updates.current().shutdown.clone();
+    log::info!("Shutting down, waiting up to {:?} for services to finish", shutdown_config.timeout);
+    shutdown_trigger.trigger();
+
+    let services = async {
+        for handle in handles {
+            if let Err(why) = handle.await {
+                log::error!("Service failed to shut down: {}", why);
+            }
+        }
+    };
+
+    if timeout(shutdown_config.timeout, services).await.is_err() {
+        log::warn!("Services did not shut down in time, exiting anyway");
+    }
+
+    if service_failed {
+        anyhow::bail!("A service stopped before shutdown");
     }
 
     Ok(())
 }
 
-fn init_logger() -> Result<()> {
-    use {
-        fern::{Dispatch, colors::ColoredLevelConfig, log_file},
-        log::LevelFilter,
-        chrono::{Utc, Local as LocalTime},
-    };
+/// Completes once one of the services stopped before shutdown was triggered.
+///
+/// The stopped service is removed from `handles`, as its handle can not be awaited again.
+async fn service_exited(handles: &mut Vec<JoinHandle<()>>) {
+    if handles.is_empty() {
+        return future::pending().await;
+    }
 
-    let colors = ColoredLevelConfig::default();
-    Dispatch::new()
-        .level(LevelFilter::Info)
-        .level_for("javelin", LevelFilter::Debug)
-        .level_for("javelin_rtmp", LevelFilter::Debug)
-        .level_for("javelin_hls", LevelFilter::Debug)
-        .level_for("javelin_types", LevelFilter::Debug)
-        .level_for("javelin_core", LevelFilter::Debug)
-        .level_for("javelin_codec", LevelFilter::Warn)
-        .chain(Dispatch::new()
-            .format(|out, msg, record| {
-                out.finish(format_args!(
-                    "level={:5} timestamp={} target={}  {}",
-                    record.level(),
-                    Utc::now().format("%Y-%m-%dT%H:%M:%S"),
-                    record.target(),
-                    msg
-                ))
-            })
-            // TODO: implement auto rotating file logger
-            .chain(log_file("javelin.log")?)
-        )
-        .chain(Dispatch::new()
-            .format(move |out, msg, record| {
-                out.finish(format_args!(
-                    "[{:5}] {} ({}) {}",
-                    colors.color(record.level()),
-                    LocalTime::now().format("%Y-%m-%d %H:%M:%S"),
-                    record.target(),
-                    msg
-                ))
-            })
-            .chain(std::io::stdout())
-        )
-        .apply()?;
+    let (res, index, _) = future::select_all(handles.iter_mut()).await;
+    handles.remove(index);
+
+    if let Err(why) = res {
+        log::error!("Service failed: {}", why);
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res?,
+        _ = terminate.recv() => (),
+    }
+
+    Ok(())
+}
 
+#[cfg(not(unix))]
+async fn wait_for_signal() -> Result<()> {
+    tokio::signal::ctrl_c().await?;
     Ok(())
 }
@@ -1,19 +1,425 @@
 use {
+    std::{
+        fs::File,
+        io::{self, Read, Write},
+        path::Path,
+        time::Duration,
+    },
     clap::ArgMatches,
-    anyhow::Result,
-    javelin_core::config::Config,
-    javelin_types::models::UserRepository,
-    crate::database::Database,
+    anyhow::{Result, bail},
+    chrono::{DateTime, Utc},
+    serde::{Serialize, Deserialize},
+    javelin_core::playback::Authorizer,
+    javelin_types::models::{
+        self, UserRepository, User, StreamKey, StreamPermission,
+        SessionHistory, SessionRecord, HistoryFilter,
+    },
+    crate::{database, settings::Settings},
 };
 
 
-pub async fn permit_stream(args: &ArgMatches<'_>, config: &Config) -> Result<()> {
-    let mut database_handle = Database::new(&config).await;
+pub async fn permit_stream(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
 
     let user = args.value_of("user").unwrap(); // required parameter
     let key = args.value_of("key").unwrap();  // required parameter
+    let key_name = args.value_of("key_name").unwrap(); // has default value
+    let expires_at = expiry(args)?;
 
-    database_handle.add_user_with_key(user, key).await?;
+    database_handle.add_user_key(user, key_name, key, expires_at).await?;
 
     Ok(())
 }
+
+pub async fn revoke_stream(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let user = args.value_of("user").unwrap(); // required parameter
+    let key_name = args.value_of("key_name").unwrap(); // has default value
+
+    if !database_handle.revoke_key(user, key_name).await? {
+        bail!("User {} has no key named {}", user, key_name);
+    }
+
+    Ok(())
+}
+
+pub async fn rotate_key(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let user = args.value_of("user").unwrap(); // required parameter
+    let key_name = args.value_of("key_name").unwrap(); // has default value
+    let expires_at = expiry(args)?;
+
+    let key = models::generate_key();
+    database_handle.add_user_key(user, key_name, &key, expires_at).await?;
+    println!("{}", key);
+
+    Ok(())
+}
+
+pub async fn allow_publish(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let user = args.value_of("user").unwrap(); // required parameter
+    let permission = stream_permission(args)?;
+
+    database_handle.allow_publish(user, &permission).await?;
+
+    Ok(())
+}
+
+pub async fn disallow_publish(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let user = args.value_of("user").unwrap(); // required parameter
+    let permission = stream_permission(args)?;
+
+    if !database_handle.disallow_publish(user, &permission).await? {
+        bail!("User {} is not allowed to publish to {}/{}", user, permission.app, permission.stream);
+    }
+
+    Ok(())
+}
+
+pub fn sign_playback(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let stream = args.value_of("stream").unwrap(); // required parameter
+    let valid_for = args.value_of("valid_for").unwrap().parse()?; // has default value
+
+    let authorizer = Authorizer::new(settings.playback.clone());
+
+    match authorizer.sign(stream, Duration::from_secs(valid_for)) {
+        Some(query) => println!("{}", query),
+        None => bail!("No playback secret configured"),
+    }
+
+    Ok(())
+}
+
+pub async fn list_users(settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    for user in database_handle.users().await? {
+        println!("{} ({} keys)", user.name, user.keys.len());
+    }
+
+    Ok(())
+}
+
+pub async fn show_user(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let name = args.value_of("user").unwrap(); // required parameter
+
+    let user = match database_handle.user_by_name(name).await? {
+        Some(user) => user,
+        None => bail!("No user with name {} found", name),
+    };
+
+    println!("{}", user.name);
+    for key in &user.keys {
+        println!("  {:<16} expires: {:<25} last used: {}",
+            key.name,
+            format_time(key.expires_at),
+            format_time(key.last_used_at));
+    }
+
+    for permission in database_handle.permissions(name).await? {
+        println!("  publishes to {}/{}", permission.app, permission.stream);
+    }
+
+    Ok(())
+}
+
+pub async fn delete_user(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let name = args.value_of("user").unwrap(); // required parameter
+
+    if !database_handle.delete_user(name).await? {
+        bail!("No user with name {} found", name);
+    }
+
+    Ok(())
+}
+
+pub async fn export_users(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let mut records = Vec::new();
+    for user in database_handle.users().await? {
+        let permissions = database_handle.permissions(&user.name).await?;
+        records.extend(UserRecord::from_user(user, permissions));
+    }
+
+    let output: Box<dyn Write> = match args.value_of("file") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    match format(args)? {
+        Format::Json => serde_json::to_writer_pretty(output, &records)?,
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            for record in &records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        },
+    }
+
+    Ok(())
+}
+
+pub async fn import_users(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let input: Box<dyn Read> = match args.value_of("file") {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+
+    let records: Vec<UserRecord> = match format(args)? {
+        Format::Json => serde_json::from_reader(input)?,
+        Format::Csv => csv::Reader::from_reader(input)
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+    };
+
+    let (mut keys, mut permissions) = (0, 0);
+
+    for record in &records {
+        if let Some(stream_key) = record.to_stream_key()? {
+            database_handle.put_key(&record.user, &stream_key).await?;
+            keys += 1;
+        }
+
+        if let Some(permission) = record.to_permission()? {
+            database_handle.allow_publish(&record.user, &permission).await?;
+            permissions += 1;
+        }
+    }
+
+    log::info!("Imported {} keys and {} stream permissions", keys, permissions);
+
+    Ok(())
+}
+
+pub async fn history(args: &ArgMatches<'_>, settings: &Settings) -> Result<()> {
+    let database_handle = database::open(settings).await?;
+
+    let since = match args.value_of("since") {
+        Some(time) => Some(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc)),
+        None => None,
+    };
+
+    let filter = HistoryFilter {
+        user: args.value_of("user").map(ToString::to_string),
+        app: args.value_of("stream").map(ToString::to_string),
+        since,
+        limit: match args.value_of("limit").unwrap().parse()? { // has default value
+            0 => None,
+            limit => Some(limit),
+        },
+    };
+
+    for record in database_handle.sessions(&filter).await? {
+        print_session(&record);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "db-sqlite")]
+pub fn db_migrate(settings: &Settings) -> Result<()> {
+    let database = database::open_sqlite(&settings.database)?;
+    let applied = database.migrate()?;
+
+    if applied.is_empty() {
+        println!("Database schema is up to date");
+    }
+
+    for migration in applied {
+        println!("Applied {:04} {}", migration.version, migration.name);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "db-sqlite")]
+pub fn db_status(settings: &Settings) -> Result<()> {
+    let database = database::open_sqlite(&settings.database)?;
+
+    for migration in database.migration_status()? {
+        let state = match migration.applied_at {
+            Some(time) => format!("applied {}", time.to_rfc3339()),
+            None => "pending".to_string(),
+        };
+        println!("{:04} {:<30} {}", migration.version, migration.name, state);
+    }
+
+    Ok(())
+}
+
+pub fn config_check(settings: &Settings) {
+    println!("Configuration is valid, using the {} database backend", settings.database.backend);
+}
+
+pub fn config_dump(settings: &Settings) -> Result<()> {
+    println!("{}", settings.dump()?);
+    Ok(())
+}
+
+/// A single key or stream permission of a user, as used for imports and exports.
+///
+/// Keys are exported as hashes, plaintext keys will be hashed on first use after the import.
+#[derive(Default, Serialize, Deserialize)]
+struct UserRecord {
+    user: String,
+    #[serde(default)]
+    key_name: Option<String>,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    last_used_at: Option<DateTime<Utc>>,
+    /// Stream the user is allowed to publish to, as `app/stream`
+    #[serde(default)]
+    publish: Option<String>,
+}
+
+impl UserRecord {
+    fn from_user(user: User, permissions: Vec<StreamPermission>) -> Vec<Self> {
+        let name = user.name;
+
+        let keys = user.keys
+            .into_iter()
+            .map(|key| Self {
+                user: name.clone(),
+                key_name: Some(key.name),
+                key: Some(key.hash),
+                expires_at: key.expires_at,
+                last_used_at: key.last_used_at,
+                ..Self::default()
+            });
+
+        let permissions = permissions
+            .into_iter()
+            .map(|permission| Self {
+                user: name.clone(),
+                publish: Some(format!("{}/{}", permission.app, permission.stream)),
+                ..Self::default()
+            });
+
+        keys.chain(permissions).collect()
+    }
+
+    fn to_stream_key(&self) -> Result<Option<StreamKey>> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        let name = match &self.key_name {
+            Some(name) => name,
+            None => bail!("Key of user {} has no name", self.user),
+        };
+
+        Ok(Some(StreamKey {
+            name: name.clone(),
+            hash: key.clone(),
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+        }))
+    }
+
+    fn to_permission(&self) -> Result<Option<StreamPermission>> {
+        match &self.publish {
+            Some(stream) => match StreamPermission::parse(stream) {
+                Some(permission) => Ok(Some(permission)),
+                None => bail!("Invalid stream '{}' for user {}, expected APP/STREAM", stream, self.user),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+
+enum Format {
+    Json,
+    Csv,
+}
+
+fn format(args: &ArgMatches<'_>) -> Result<Format> {
+    let format = match (args.value_of("format"), args.value_of("file")) {
+        (Some(format), _) => format.to_string(),
+        (None, Some(path)) => Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase(),
+        (None, None) => "json".to_string(),
+    };
+
+    Ok(match format.as_str() {
+        "json" => Format::Json,
+        "csv" => Format::Csv,
+        other => bail!("Unsupported format '{}'", other),
+    })
+}
+
+fn stream_permission(args: &ArgMatches<'_>) -> Result<StreamPermission> {
+    let stream = args.value_of("stream").unwrap(); // required parameter
+
+    match StreamPermission::parse(stream) {
+        Some(permission) => Ok(permission),
+        None => bail!("Stream has to be given as APP/STREAM"),
+    }
+}
+
+fn expiry(args: &ArgMatches<'_>) -> Result<Option<DateTime<Utc>>> {
+    match args.value_of("expires") {
+        Some(time) => Ok(Some(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc))),
+        None => Ok(None),
+    }
+}
+
+fn print_session(record: &SessionRecord) {
+    let codec = &record.codec;
+    let duration = record.duration();
+
+    println!("{} {} by {} from {}",
+        record.started_at.to_rfc3339(),
+        record.app,
+        record.user,
+        record.client_addr);
+
+    println!("  duration: {:02}:{:02}:{:02}  peak viewers: {}  received: {} bytes",
+        duration.num_hours(),
+        duration.num_minutes() % 60,
+        duration.num_seconds() % 60,
+        record.peak_viewers,
+        record.bytes_received);
+
+    if let (Some(width), Some(height)) = (codec.video_width, codec.video_height) {
+        println!("  video: {} {}x{} @ {} fps, {} kbps",
+            codec.video_codec.as_deref().unwrap_or("unknown"),
+            width,
+            height,
+            codec.video_frame_rate.unwrap_or_default(),
+            codec.video_bitrate.unwrap_or_default());
+    }
+
+    if let Some(audio_codec) = &codec.audio_codec {
+        println!("  audio: {} {} Hz, {} channels, {} kbps",
+            audio_codec,
+            codec.audio_sample_rate.unwrap_or_default(),
+            codec.audio_channels.unwrap_or_default(),
+            codec.audio_bitrate.unwrap_or_default());
+    }
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339()).unwrap_or_else(|| "never".to_string())
+}
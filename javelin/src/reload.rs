@@ -0,0 +1,133 @@
+use {
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    },
+    anyhow::Result,
+    serde_json::Value,
+    tokio::time::interval,
+    javelin_core::config::{self, Config, Updater},
+    crate::{logging, settings::Settings},
+};
+
+
+/// Settings that are only read when the server starts.
+const RESTART_REQUIRED: &[&str] = &[
+    "rtmp.addr",
+    "rtmp.tls",
+    "hls.enabled",
+    "hls.root_dir",
+    "hls.web",
+    "api",
+    "metrics",
+    "database",
+    "users",
+];
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+
+/// Reloads the configuration on SIGHUP or when the config file changes.
+pub struct Reloader {
+    config_dir: PathBuf,
+    current: Config,
+    updater: Updater<Settings>,
+    modified: Option<SystemTime>,
+}
+
+impl Reloader {
+    pub fn new<P>(config_dir: P, current: Config, updater: Updater<Settings>) -> Self
+        where P: Into<PathBuf>
+    {
+        let config_dir = config_dir.into();
+        let modified = modified_at(&config_dir);
+
+        Self { config_dir, current, updater, modified }
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        let mut hangup = hangup_signal()?;
+        let mut poll = interval(POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    log::info!("Received SIGHUP, reloading config");
+                },
+                _ = poll.tick() => {
+                    let modified = modified_at(&self.config_dir);
+                    if modified == self.modified {
+                        continue;
+                    }
+                    log::info!("Config file changed, reloading");
+                },
+            }
+
+            self.modified = modified_at(&self.config_dir);
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        let (config, settings) = match config::from_path(&self.config_dir).and_then(validate) {
+            Ok(loaded) => loaded,
+            Err(why) => {
+                log::error!("Failed to reload config, keeping the current one: {:#}", why);
+                return;
+            },
+        };
+
+        for key in changed_keys(&self.current, &config) {
+            log::warn!("Changed setting '{}' only takes effect after a restart", key);
+        }
+
+        logging::configure(&settings.log);
+        self.current = config;
+        self.updater.update(settings);
+    }
+}
+
+
+fn validate(config: Config) -> Result<(Config, Settings)> {
+    let settings = Settings::from_config(&config)?;
+    Ok((config, settings))
+}
+
+fn changed_keys(old: &Config, new: &Config) -> Vec<&'static str> {
+    RESTART_REQUIRED
+        .iter()
+        .filter(|key| old.get::<Value>(key).ok() != new.get::<Value>(key).ok())
+        .copied()
+        .collect()
+}
+
+fn modified_at(config_dir: &Path) -> Option<SystemTime> {
+    fs::metadata(config_dir.join("config.yml"))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(unix)]
+fn hangup_signal() -> Result<tokio::signal::unix::Signal> {
+    use tokio::signal::unix::{signal, SignalKind};
+    Ok(signal(SignalKind::hangup())?)
+}
+
+/// There is no SIGHUP outside of unix, only file changes are picked up.
+#[cfg(not(unix))]
+fn hangup_signal() -> Result<NoSignal> {
+    Ok(NoSignal)
+}
+
+#[cfg(not(unix))]
+struct NoSignal;
+
+#[cfg(not(unix))]
+impl NoSignal {
+    async fn recv(&mut self) -> Option<()> {
+        loop {
+            tokio::time::delay_for(Duration::from_secs(3600)).await;
+        }
+    }
+}
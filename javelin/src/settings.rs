@@ -0,0 +1,135 @@
+use {
+    std::collections::HashMap,
+    anyhow::{Result, Context, bail},
+    serde::{Serialize, Deserialize},
+    serde_json::Value,
+    javelin_core::{
+        session::config::Config as SessionConfig,
+        playback::PlaybackConfig,
+        webhook::WebhookConfig,
+        shutdown::ShutdownConfig,
+        Config,
+    },
+    crate::{
+        database::{DatabaseConfig, UserConfig},
+        logging::LogConfig,
+    },
+};
+
+#[cfg(not(all(feature = "rtmp", feature = "hls", feature = "api")))]
+use serde::de::IgnoredAny;
+
+
+/// Values of these keys are hidden in `config dump`.
+const SECRET_KEYS: &[&str] = &["key", "password", "cert_password", "secret", "token", "stream_keys", "url", "urls"];
+
+
+/// Every section of the configuration file.
+///
+/// Sections of services that are not compiled in are accepted, but ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[cfg(feature = "rtmp")]
+    #[serde(default)]
+    pub rtmp: javelin_rtmp::Config,
+    #[cfg(not(feature = "rtmp"))]
+    #[serde(default, skip_serializing)]
+    rtmp: Option<IgnoredAny>,
+
+    #[cfg(feature = "hls")]
+    #[serde(default)]
+    pub hls: javelin_hls::Config,
+    #[cfg(not(feature = "hls"))]
+    #[serde(default, skip_serializing)]
+    hls: Option<IgnoredAny>,
+
+    #[cfg(feature = "api")]
+    #[serde(default)]
+    pub api: javelin_api::Config,
+    #[cfg(not(feature = "api"))]
+    #[serde(default, skip_serializing)]
+    api: Option<IgnoredAny>,
+
+    #[cfg(feature = "api")]
+    #[serde(default)]
+    pub metrics: javelin_api::MetricsConfig,
+    #[cfg(not(feature = "api"))]
+    #[serde(default, skip_serializing)]
+    metrics: Option<IgnoredAny>,
+
+    #[serde(default)]
+    pub session: SessionConfig,
+
+    #[serde(default)]
+    pub playback: PlaybackConfig,
+
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+
+    #[serde(default)]
+    pub database: DatabaseConfig,
+
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+
+    #[serde(default)]
+    pub log: LogConfig,
+}
+
+impl Settings {
+    /// Rejects unknown keys and values of the wrong type.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let settings: Self = config.clone().try_into().context("Invalid configuration")?;
+
+        if settings.database.backend == "sqlite" && settings.database.sqlite.path.is_none() {
+            bail!("Invalid configuration: database.sqlite.path is required by the sqlite backend");
+        }
+
+        Ok(settings)
+    }
+
+    /// Stream keys of older versions, they were part of the rtmp section.
+    pub fn legacy_stream_keys(&self) -> HashMap<String, String> {
+        #[cfg(feature = "rtmp")]
+        return self.rtmp.stream_keys.clone();
+        #[cfg(not(feature = "rtmp"))]
+        return HashMap::new();
+    }
+
+    /// The effective configuration as JSON, which is valid YAML as well.
+    pub fn dump(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        redact(&mut value);
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+}
+
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) && !is_empty(value) {
+                    *value = Value::String("<redacted>".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        },
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}